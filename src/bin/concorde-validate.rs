@@ -0,0 +1,40 @@
+//! Checks an LDtk project against the entities, fields and items the game expects,
+//! run from the repository root with `cargo run --bin concorde-validate [project.ldtk]`
+use std::{env, fs, process::ExitCode};
+use concorde::validate::{default_project_path, known_items, validate_project};
+
+fn main() -> ExitCode {
+    let path = env::args().nth(1).unwrap_or_else(default_project_path);
+    let project = fs::read_to_string(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|project| serde_json::from_str(&project).map_err(|error| error.to_string()));
+    let project = match project {
+        Ok(project) => project,
+        Err(error) => {
+            eprintln!("Failed to read {}: {}", path, error);
+            return ExitCode::from(2);
+        },
+    };
+
+    let known_items = match known_items() {
+        Ok(known_items) => known_items,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::from(2);
+        },
+    };
+
+    match validate_project(&project, &known_items) {
+        Ok(()) => {
+            println!("{} is valid", path);
+            ExitCode::SUCCESS
+        },
+        Err(errors) => {
+            for error in errors.iter() {
+                eprintln!("error: {}", error);
+            }
+            eprintln!("{} problems found in {}", errors.len(), path);
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -1,10 +1,493 @@
+use std::{collections::HashSet, time::Duration};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::EntityInstance;
+use bevy_ecs_ldtk::prelude::FieldValue;
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+use bevy::sprite::collide_aabb::collide;
+use crate::{misc::{label::CombatLabel, state::GameState}, enemy::{attack::MeleeAttack, settings::{EnemyKind, EnemySettings}}, level::{Collider, terrain::TerrainMap}, player::{can_move, Direction, Orientation, Player}, item::PowerBuff, TILE_SIZE};
+
+const HIT_FLASH_COLOR: Color = Color::rgb(1.0, 0.25, 0.25);
+
 pub struct CombatPlugin;
 
 impl Plugin for CombatPlugin {
     fn build(&self, app: &mut App) {
         app
-            .a
+            .add_event::<DamageEvent>()
+            .init_resource::<HitStop>()
+            .add_system(
+                player_attack
+                    .run_in_state(GameState::InGame)
+                    .before(CombatLabel::Hitbox)
+            )
+            .add_system(
+                resolve_hitboxes
+                    .run_in_state(GameState::InGame)
+                    .label(CombatLabel::Hitbox)
+                    .before(CombatLabel::Damage)
+            )
+            .add_system(
+                apply_damage
+                    .run_in_state(GameState::InGame)
+                    .label(CombatLabel::Damage)
+            )
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::InGame)
+                    .after(CombatLabel::Damage)
+                    .with_system(tick_hit_stop)
+                    .with_system(tick_invulnerability)
+                    .with_system(tick_attack_cooldown)
+                    .with_system(hit_flash)
+                    .with_system(apply_knockback.run_if(not_hit_stopped))
+                    .into()
+            );
+
+        // Only the console turns god mode on, and it is left out of release builds
+        #[cfg(debug_assertions)]
+        app
+            .add_event::<GodModeEvent>()
+            .add_system(set_god_mode);
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Component)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Health {
+    pub fn new(max: i32) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0
+    }
+
+    pub fn damage(&mut self, amount: i32) {
+        self.current = (self.current - amount).clamp(0, self.max);
+    }
+
+    pub fn heal(&mut self, amount: i32) {
+        self.current = (self.current + amount).clamp(0, self.max);
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::new(100)
+    }
+}
+
+/// Reads the `life` field of an LDtk entity, falling back to the default health
+pub fn health_from_life(entity_instance: &EntityInstance) -> Health {
+    entity_instance
+        .field_instances
+        .iter()
+        .find(|f| f.identifier == *"life")
+        .and_then(|f| match f.value {
+            FieldValue::Int(life) => life,
+            _ => None,
+        })
+        .map_or_else(Health::default, Health::new)
+}
+
+/// How getting hit feels, both for whoever takes the hit and for whoever deals it
+/// Enemies read theirs from `settings/enemy.json`, the player uses the default
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct HitFeel {
+    /// Speed in pixels per second this character knocks its targets back with
+    pub knockback: f32,
+    pub knockback_ms: u64,
+    /// How long this character ignores damage after being hit
+    pub invulnerability_ms: u64,
+    pub flash_ms: u64,
+    /// How long the game freezes when this character is hit
+    pub hit_stop_ms: u64,
+}
+
+impl Default for HitFeel {
+    fn default() -> Self {
+        HitFeel {
+            knockback: 160.0,
+            knockback_ms: 120,
+            invulnerability_ms: 800,
+            flash_ms: 100,
+            hit_stop_ms: 80,
+        }
+    }
+}
+
+/// A melee attack, shared by the player and every enemy
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AttackStats {
+    pub damage: i32,
+    /// Distance to the target at which an enemy starts its attack
+    pub range: f32,
+    /// Width and height of the hitbox in pixels
+    pub reach: f32,
+    pub wind_up_ms: u64,
+    pub active_ms: u64,
+    pub cooldown_ms: u64,
+}
+
+impl Default for AttackStats {
+    fn default() -> Self {
+        AttackStats {
+            damage: 10,
+            range: TILE_SIZE,
+            reach: TILE_SIZE,
+            wind_up_ms: 0,
+            active_ms: 100,
+            cooldown_ms: 350,
+        }
+    }
+}
+
+/// Which side a hitbox hurts
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Team {
+    Player,
+    Enemy,
+}
+
+/// Damaging area spawned by an attack, hits each target at most once
+#[derive(Clone, Component)]
+pub struct Hitbox {
+    pub owner: Entity,
+    pub damage: i32,
+    pub size: Vec2,
+    pub targets: Team,
+    pub timer: Timer,
+    pub already_hit: Vec<Entity>,
+}
+
+impl Hitbox {
+    /// Whether `target`, on `team` and standing at `target_position`, gets hit by this hitbox at `position`
+    pub fn hits(&self, position: Vec2, target: Entity, team: Team, target_position: Vec2) -> bool {
+        team == self.targets
+            && target != self.owner
+            && !self.already_hit.contains(&target)
+            && collide(
+                position.extend(0.0),
+                self.size,
+                target_position.extend(0.0),
+                Vec2::splat(TILE_SIZE - 1.0),
+            ).is_some()
+    }
+}
+
+#[derive(Deref, DerefMut, Clone, Component)]
+pub struct AttackCooldown(pub Timer);
+
+/// Spawns the hitbox of `attack` in front of `origin`, facing `facing`
+pub fn spawn_hitbox(
+    commands: &mut Commands,
+    owner: Entity,
+    origin: Vec3,
+    facing: &Direction,
+    attack: &AttackStats,
+    targets: Team,
+) {
+    let offset = facing.vector() * (TILE_SIZE + attack.reach) / 2.0;
+    commands
+        .spawn_bundle(TransformBundle::from_transform(Transform::from_translation(
+            origin + offset.extend(0.0),
+        )))
+        .insert(Hitbox {
+            owner,
+            damage: attack.damage,
+            size: Vec2::splat(attack.reach),
+            targets,
+            timer: Timer::new(Duration::from_millis(attack.active_ms), false),
+            already_hit: Vec::new(),
+        });
+}
+
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: i32,
+    /// Whoever dealt the damage, used to knock the target away from them
+    pub source: Option<Entity>,
+}
+
+#[derive(Clone, Component)]
+pub struct Knockback {
+    pub velocity: Vec2,
+    pub timer: Timer,
+}
+
+#[derive(Deref, DerefMut, Clone, Component)]
+pub struct Invulnerable(pub Timer);
+
+/// Ignores every source of damage until removed, toggled from the console
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug, Component)]
+pub struct GodMode;
+
+/// Sent to turn `GodMode` on or off for `target`, only the console sends it
+#[cfg(debug_assertions)]
+pub struct GodModeEvent {
+    pub target: Entity,
+    pub enabled: bool,
+}
+
+#[cfg(debug_assertions)]
+fn set_god_mode(
+    mut commands: Commands,
+    mut events: EventReader<GodModeEvent>,
+) {
+    for event in events.iter() {
+        if event.enabled {
+            commands.entity(event.target).insert(GodMode);
+        } else {
+            commands.entity(event.target).remove::<GodMode>();
+        }
     }
 }
 
-fn 
\ No newline at end of file
+#[derive(Deref, DerefMut, Clone, Component)]
+pub struct HitFlash(pub Timer);
+
+/// Freezes movement for a moment when something gets hit
+#[derive(Default, Debug)]
+pub struct HitStop {
+    remaining: Duration,
+}
+
+impl HitStop {
+    pub fn start(&mut self, duration: Duration) {
+        self.remaining = self.remaining.max(duration);
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.remaining.is_zero()
+    }
+}
+
+pub fn not_hit_stopped(hit_stop: Res<HitStop>) -> bool {
+    !hit_stop.is_active()
+}
+
+fn player_attack(
+    mut commands: Commands,
+    player: Query<(Entity, &Transform, &Orientation, Option<&PowerBuff>), (With<Player>, Without<AttackCooldown>)>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+
+    for (entity, transform, orientation, buff) in player.iter() {
+        let mut attack = AttackStats::default();
+        if let Some(buff) = buff {
+            attack.damage = buff.apply(attack.damage);
+        }
+        spawn_hitbox(&mut commands, entity, transform.translation, &orientation.0, &attack, Team::Enemy);
+        commands
+            .entity(entity)
+            .insert(AttackCooldown(Timer::new(Duration::from_millis(attack.cooldown_ms), false)));
+    }
+}
+
+fn tick_attack_cooldown(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut AttackCooldown)>,
+    time: Res<Time>,
+) {
+    query.for_each_mut(|(entity, mut cooldown)| {
+        if cooldown.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<AttackCooldown>();
+        }
+    })
+}
+
+/// Turns hitbox overlaps into `DamageEvent`s, so every attack goes through `apply_damage`
+fn resolve_hitboxes(
+    mut commands: Commands,
+    mut hitboxes: Query<(Entity, &Transform, &mut Hitbox)>,
+    targets: Query<(Entity, &GlobalTransform, Option<&Player>), With<Health>>,
+    mut damage: EventWriter<DamageEvent>,
+    time: Res<Time>,
+) {
+    hitboxes.for_each_mut(|(entity, transform, mut hitbox)| {
+        let position = transform.translation.truncate();
+        for (target, target_transform, player) in targets.iter() {
+            let team = if player.is_some() { Team::Player } else { Team::Enemy };
+            if hitbox.hits(position, target, team, target_transform.translation().truncate()) {
+                hitbox.already_hit.push(target);
+                damage.send(DamageEvent {
+                    target,
+                    amount: hitbox.damage,
+                    source: Some(hitbox.owner),
+                });
+            }
+        }
+
+        if hitbox.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    })
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    mut events: EventReader<DamageEvent>,
+    mut targets: Query<(&mut Health, &GlobalTransform, Option<&EnemyKind>, Option<&Invulnerable>, Option<&GodMode>)>,
+    sources: Query<(&GlobalTransform, Option<&EnemyKind>)>,
+    settings: Res<EnemySettings>,
+    mut hit_stop: ResMut<HitStop>,
+) {
+    // Invulnerability is inserted through commands, so guard against several hits in one frame
+    let mut hit_this_frame = HashSet::new();
+
+    for event in events.iter() {
+        if let Ok((mut health, transform, kind, invulnerable, god_mode)) = targets.get_mut(event.target) {
+            if invulnerable.is_some() || god_mode.is_some() || !hit_this_frame.insert(event.target) {
+                continue;
+            }
+
+            health.damage(event.amount);
+
+            let feel = settings.hit_feel(kind);
+            hit_stop.start(Duration::from_millis(feel.hit_stop_ms));
+
+            let mut target = commands.entity(event.target);
+            target
+                .insert(Invulnerable(Timer::new(Duration::from_millis(feel.invulnerability_ms), false)))
+                .insert(HitFlash(Timer::new(Duration::from_millis(feel.flash_ms), false)));
+
+            if let Some((source_transform, source_kind)) = event.source.and_then(|source| sources.get(source).ok()) {
+                let attacker = settings.hit_feel(source_kind);
+                let away = (transform.translation() - source_transform.translation())
+                    .truncate()
+                    .normalize_or_zero();
+                target.insert(Knockback {
+                    velocity: away * attacker.knockback,
+                    timer: Timer::new(Duration::from_millis(attacker.knockback_ms), false),
+                });
+            }
+        }
+    }
+}
+
+fn tick_hit_stop(
+    mut hit_stop: ResMut<HitStop>,
+    time: Res<Time>,
+) {
+    hit_stop.remaining = hit_stop.remaining.saturating_sub(time.delta());
+}
+
+fn tick_invulnerability(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Invulnerable)>,
+    time: Res<Time>,
+) {
+    query.for_each_mut(|(entity, mut invulnerable)| {
+        if invulnerable.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    })
+}
+
+fn hit_flash(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut HitFlash, &mut TextureAtlasSprite, Option<&MeleeAttack>)>,
+    time: Res<Time>,
+) {
+    query.for_each_mut(|(entity, mut flash, mut sprite, melee)| {
+        if flash.tick(time.delta()).finished() {
+            // Bring back the telegraph of an enemy hit while winding up, or it would attack unannounced
+            sprite.color = melee.map_or(Color::WHITE, MeleeAttack::sprite_color);
+            commands.entity(entity).remove::<HitFlash>();
+        } else {
+            sprite.color = HIT_FLASH_COLOR;
+        }
+    })
+}
+
+/// Moves knocked back characters one axis at a time through `can_move`,
+/// the same collision resolution used for walking
+fn apply_knockback(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Transform, &mut Knockback)>,
+    colliders: Query<&Transform, (With<Collider>, Without<Knockback>)>,
+    terrain_map: Res<TerrainMap>,
+    time: Res<Time>,
+) {
+    query.for_each_mut(|(entity, mut transform, mut knockback)| {
+        let step = knockback.velocity * time.delta_seconds();
+
+        let horizontal = if step.x > 0.0 { Direction::Right } else { Direction::Left };
+        if can_move(transform.translation, &horizontal, step.x.abs(), colliders.iter(), &terrain_map) {
+            transform.translation.x += step.x;
+        }
+        let vertical = if step.y > 0.0 { Direction::Up } else { Direction::Down };
+        if can_move(transform.translation, &vertical, step.y.abs(), colliders.iter(), &terrain_map) {
+            transform.translation.y += step.y;
+        }
+
+        if knockback.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Knockback>();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hitbox(owner: Entity, targets: Team) -> Hitbox {
+        Hitbox {
+            owner,
+            damage: 10,
+            size: Vec2::splat(TILE_SIZE),
+            targets,
+            timer: Timer::new(Duration::from_millis(100), false),
+            already_hit: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn player_and_enemy_hitboxes_only_hit_the_other_team() {
+        let player = Entity::from_raw(0);
+        let knight = Entity::from_raw(1);
+
+        let player_hitbox = hitbox(player, Team::Enemy);
+        assert!(player_hitbox.hits(Vec2::ZERO, knight, Team::Enemy, Vec2::new(8.0, 0.0)));
+        assert!(!player_hitbox.hits(Vec2::ZERO, player, Team::Player, Vec2::ZERO));
+
+        let knight_hitbox = hitbox(knight, Team::Player);
+        assert!(knight_hitbox.hits(Vec2::ZERO, player, Team::Player, Vec2::new(0.0, -8.0)));
+        assert!(!knight_hitbox.hits(Vec2::ZERO, knight, Team::Enemy, Vec2::ZERO));
+    }
+
+    #[test]
+    fn hitboxes_miss_distant_and_already_hit_targets() {
+        let player = Entity::from_raw(0);
+        let knight = Entity::from_raw(1);
+
+        let mut hitbox = hitbox(player, Team::Enemy);
+        assert!(!hitbox.hits(Vec2::ZERO, knight, Team::Enemy, Vec2::new(TILE_SIZE * 2.0, 0.0)));
+
+        hitbox.already_hit.push(knight);
+        assert!(!hitbox.hits(Vec2::ZERO, knight, Team::Enemy, Vec2::ZERO));
+    }
+
+    #[test]
+    fn hitboxes_spawn_in_front_of_the_attacker() {
+        let mut world = World::new();
+        let owner = world.spawn().id();
+        let mut queue = bevy::ecs::system::CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        spawn_hitbox(&mut commands, owner, Vec3::ZERO, &Direction::Left, &AttackStats::default(), Team::Enemy);
+        queue.apply(&mut world);
+
+        let mut hitboxes = world.query::<(&Transform, &Hitbox)>();
+        let (transform, hitbox) = hitboxes.single(&world);
+        assert_eq!(transform.translation, Vec3::new(-TILE_SIZE, 0.0, 0.0));
+        assert_eq!(hitbox.owner, owner);
+    }
+}
@@ -0,0 +1,715 @@
+use std::{collections::{BTreeMap, VecDeque}, str::FromStr};
+use bevy::{input::InputSystem, prelude::*, window::ReceivedCharacter};
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{
+    misc::state::GameState,
+    player::{NoClip, NoClipEvent, Player},
+    enemy::{SpawnEnemyEvent, settings::EnemyKind},
+    combat::{DamageEvent, GodMode, GodModeEvent, Health, Invulnerable},
+    item::{CollectItemEvent, Item, ItemRegistry},
+    level::{dungeon, travel_to_tile, COLLISIONS_LAYER, LDTK_PROJECT},
+    map::{LevelMap, MapCell},
+    save::SaveData,
+    TILE_SIZE,
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::Grave;
+/// Output lines kept around, older ones are dropped
+const SCROLLBACK: usize = 200;
+const VISIBLE_LINES: usize = 12;
+const HISTORY_SIZE: usize = 50;
+const PROMPT: &str = "> ";
+const PANEL_BACKGROUND: Color = Color::rgba(0.0, 0.0, 0.0, 0.8);
+const FONT_SIZE: f32 = 14.0;
+/// States `state` can switch to, the others need resources set up by the systems that enter them
+/// or would load the level again
+const SWITCHABLE_STATES: [GameState; 6] = [
+    GameState::InGame,
+    GameState::Paused,
+    GameState::Inventory,
+    GameState::QuestLog,
+    GameState::WorldMap,
+    GameState::FreeCamera,
+];
+
+/// In-game console toggled with the backtick key, for trying things out while playtesting
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let mut commands = ConsoleCommands::default();
+        commands.register(ConsoleCommand::new("help", "help", "Lists every command"));
+        commands.register(
+            ConsoleCommand::new("spawn", "spawn <enemy> <x> <y>", "Spawns a hostile enemy on a tile of the current level")
+                .with_completions(0, ["knight"])
+        );
+        commands.register(ConsoleCommand::new("tp", "tp <level> [x y]", "Moves the player to a tile of a level, or the floor nearest its center"));
+        commands.register(ConsoleCommand::new("generate", "generate [seed]", "Generates a dungeon floor from a seed, or a random one, and moves the player there"));
+        commands.register(ConsoleCommand::new("give", "give <item> [count]", "Adds items to the player's inventory"));
+        commands.register(ConsoleCommand::new("god", "god", "Toggles taking no damage"));
+        commands.register(ConsoleCommand::new("noclip", "noclip", "Toggles walking through walls"));
+        commands.register(ConsoleCommand::new("kill_all", "kill_all", "Defeats every enemy in the current level"));
+        commands.register(
+            ConsoleCommand::new("state", "state <GameState>", "Switches to another game state")
+                .with_completions(0, SWITCHABLE_STATES.iter().map(|state| format!("{:?}", state)))
+        );
+
+        app
+            .insert_resource(commands)
+            .init_resource::<Console>()
+            .add_event::<ConsoleCommandEvent>()
+            .add_startup_system(complete_items)
+            .add_system(complete_levels)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                console_input.after(InputSystem)
+            )
+            .add_system(show_console)
+            .add_system(run_help)
+            .add_system(run_spawn)
+            .add_system(run_tp)
+            .add_system(run_generate)
+            .add_system(run_give)
+            .add_system(run_god)
+            .add_system(run_noclip)
+            .add_system(run_kill_all)
+            .add_system(run_state);
+    }
+}
+
+/// A command the console knows about, what `help` shows and autocomplete offers
+#[derive(Clone, Debug)]
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// Values offered for each argument, by position
+    completions: Vec<Vec<String>>,
+}
+
+impl ConsoleCommand {
+    pub fn new(name: &'static str, usage: &'static str, description: &'static str) -> Self {
+        ConsoleCommand {
+            name,
+            usage,
+            description,
+            completions: Vec::new(),
+        }
+    }
+
+    pub fn with_completions<S: Into<String>>(mut self, index: usize, values: impl IntoIterator<Item = S>) -> Self {
+        self.set_completions(index, values);
+        self
+    }
+
+    fn set_completions<S: Into<String>>(&mut self, index: usize, values: impl IntoIterator<Item = S>) {
+        if self.completions.len() <= index {
+            self.completions.resize(index + 1, Vec::new());
+        }
+        self.completions[index] = values.into_iter().map(Into::into).collect();
+    }
+}
+
+/// Every registered command by name, each one is carried out by a system reading `ConsoleCommandEvent`
+#[derive(Clone, Debug, Default)]
+pub struct ConsoleCommands(BTreeMap<&'static str, ConsoleCommand>);
+
+/// Result of autocompleting the word under the cursor
+#[derive(Clone, Debug, PartialEq)]
+pub struct Completion {
+    pub input: String,
+    /// Every value that matched, when there were several
+    pub candidates: Vec<String>,
+}
+
+impl ConsoleCommands {
+    pub fn register(&mut self, command: ConsoleCommand) {
+        self.0.insert(command.name, command);
+    }
+
+    /// Replaces the values offered for an argument, for ones only known once assets have loaded
+    pub fn set_completions<S: Into<String>>(&mut self, name: &str, index: usize, values: impl IntoIterator<Item = S>) {
+        if let Some(command) = self.0.get_mut(name) {
+            command.set_completions(index, values);
+        }
+    }
+
+    /// Completes the last word of `input` as far as every matching value agrees
+    pub fn complete(&self, input: &str) -> Completion {
+        let mut words: Vec<&str> = input.split_whitespace().collect();
+        let word = match input.chars().last() {
+            Some(last) if !last.is_whitespace() => words.pop().unwrap_or_default(),
+            _ => "",
+        };
+
+        let values: Vec<String> = match words.split_first() {
+            None => self.0.keys().map(|name| name.to_string()).collect(),
+            Some((name, arguments)) => self
+                .0
+                .get(name)
+                .and_then(|command| command.completions.get(arguments.len()))
+                .cloned()
+                .unwrap_or_default(),
+        };
+        let matching: Vec<String> = values
+            .into_iter()
+            .filter(|value| value.to_lowercase().starts_with(&word.to_lowercase()))
+            .collect();
+
+        let completed = match matching.as_slice() {
+            [] => return Completion { input: input.to_string(), candidates: Vec::new() },
+            [only] => format!("{} ", only),
+            [first, rest @ ..] => first
+                .chars()
+                .enumerate()
+                .take_while(|(index, letter)| {
+                    rest.iter().all(|value| {
+                        value.chars().nth(*index).map_or(false, |other| other.eq_ignore_ascii_case(letter))
+                    })
+                })
+                .map(|(_, letter)| letter)
+                .collect(),
+        };
+
+        words.push(&completed);
+        Completion {
+            input: words.join(" "),
+            candidates: if matching.len() > 1 { matching } else { Vec::new() },
+        }
+    }
+}
+
+/// A line entered in the console, sent once its command is known to be registered
+#[derive(Clone, Debug)]
+pub struct ConsoleCommandEvent {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl ConsoleCommandEvent {
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace().map(str::to_string);
+        Some(ConsoleCommandEvent {
+            name: words.next()?,
+            args: words.collect(),
+        })
+    }
+
+    pub fn is(&self, name: &str) -> bool {
+        self.name == name
+    }
+
+    /// Parses the argument at `index`, `None` when it was left out
+    pub fn arg<T: FromStr>(&self, index: usize) -> Result<Option<T>, String> {
+        self.args
+            .get(index)
+            .map(|arg| arg.parse().map_err(|_| format!("Invalid argument {}", arg)))
+            .transpose()
+    }
+
+    pub fn required_arg<T: FromStr>(&self, index: usize, usage: &str) -> Result<T, String> {
+        self.arg(index)?.ok_or_else(|| format!("Usage: {}", usage))
+    }
+}
+
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    lines: VecDeque<String>,
+    history: Vec<String>,
+    /// Entry shown while going through the history with the arrow keys
+    browsing: Option<usize>,
+}
+
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.lines.push_back(line.into());
+        while self.lines.len() > SCROLLBACK {
+            self.lines.pop_front();
+        }
+    }
+
+    /// Prints what a command did, or why it couldn't
+    pub fn report(&mut self, result: Result<String, String>) {
+        match result {
+            Ok(line) | Err(line) => self.print(line),
+        }
+    }
+
+    fn submit(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.input).trim().to_string();
+        self.browsing = None;
+        if line.is_empty() {
+            return None;
+        }
+
+        self.print(format!("{}{}", PROMPT, line));
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        if self.history.len() > HISTORY_SIZE {
+            self.history.remove(0);
+        }
+        Some(line)
+    }
+
+    fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        self.browsing = match (self.browsing, older) {
+            (None, true) => Some(self.history.len() - 1),
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < self.history.len() => Some(index + 1),
+            (_, false) => None,
+        };
+        self.input = self.browsing.map(|index| self.history[index].clone()).unwrap_or_default();
+    }
+}
+
+/// Runs right after input is read, so while the console is open no other system sees the keyboard
+fn console_input(
+    mut console: ResMut<Console>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    registry: Res<ConsoleCommands>,
+    mut command_events: EventWriter<ConsoleCommandEvent>,
+) {
+    // Always drain typed characters, so opening the console doesn't replay them
+    let typed: String = characters
+        .iter()
+        .map(|character| character.char)
+        .filter(|character| !character.is_control() && *character != '`')
+        .collect();
+
+    if keys.just_pressed(TOGGLE_KEY) {
+        console.open = !console.open;
+        keys.reset(TOGGLE_KEY);
+    }
+    if !console.open {
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Escape) {
+        console.open = false;
+    } else if keys.just_pressed(KeyCode::Return) {
+        if let Some(line) = console.submit() {
+            match ConsoleCommandEvent::parse(&line) {
+                Some(event) if registry.0.contains_key(event.name.as_str()) => command_events.send(event),
+                Some(event) => console.print(format!("Unknown command {}, try help", event.name)),
+                None => (),
+            }
+        }
+    } else if keys.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    } else if keys.just_pressed(KeyCode::Tab) {
+        let completion = registry.complete(&console.input);
+        if !completion.candidates.is_empty() {
+            console.print(completion.candidates.join("  "));
+        }
+        console.input = completion.input;
+    } else if keys.just_pressed(KeyCode::Up) {
+        console.browse_history(true);
+    } else if keys.just_pressed(KeyCode::Down) {
+        console.browse_history(false);
+    } else if !typed.is_empty() {
+        console.input.push_str(&typed);
+    }
+
+    let held: Vec<KeyCode> = keys.get_pressed().chain(keys.get_just_released()).copied().collect();
+    for key in held {
+        keys.reset(key);
+    }
+}
+
+#[derive(Component)]
+struct ConsolePanel;
+
+#[derive(Component)]
+struct ConsoleText;
+
+fn show_console(
+    mut commands: Commands,
+    console: Res<Console>,
+    panels: Query<Entity, With<ConsolePanel>>,
+    mut text: Query<&mut Text, With<ConsoleText>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    if !console.open {
+        panels.for_each(|entity| commands.entity(entity).despawn_recursive());
+        return;
+    }
+
+    let skipped = console.lines.len().saturating_sub(VISIBLE_LINES);
+    let mut value = console.lines.iter().skip(skipped).cloned().collect::<Vec<_>>().join("\n");
+    if !value.is_empty() {
+        value.push('\n');
+    }
+    value.push_str(&format!("{}{}_", PROMPT, console.input));
+
+    if let Ok(mut text) = text.get_single_mut() {
+        text.sections[0].value = value;
+        return;
+    }
+    if !panels.is_empty() {
+        return;
+    }
+
+    // The menu font may not be loaded yet, the console works from the main menu too
+    let font: Handle<Font> = asset_server.load("fonts/DejaVuSans.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(0.0),
+                    top: Val::Px(0.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            color: PANEL_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(ConsolePanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        value,
+                        TextStyle {
+                            font,
+                            font_size: FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ConsoleText);
+        });
+}
+
+fn complete_items(
+    mut registry: ResMut<ConsoleCommands>,
+    items: Res<ItemRegistry>,
+) {
+    let mut names: Vec<&str> = items.keys().map(Item::identifier).collect();
+    names.sort_unstable();
+    registry.set_completions("give", 0, names);
+}
+
+fn complete_levels(
+    mut registry: ResMut<ConsoleCommands>,
+    mut asset_events: EventReader<AssetEvent<LdtkAsset>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+) {
+    for event in asset_events.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            if let Some(ldtk_asset) = ldtk_assets.get(handle) {
+                let names = ldtk_asset.project.levels.iter().map(|level| level.identifier.clone());
+                registry.set_completions("tp", 0, names);
+            }
+        }
+    }
+}
+
+fn run_help(
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+    registry: Res<ConsoleCommands>,
+) {
+    for _ in events.iter().filter(|event| event.is("help")) {
+        for command in registry.0.values() {
+            console.print(format!("{} - {}", command.usage, command.description));
+        }
+    }
+}
+
+/// Center of a tile of the current level, counted from its bottom-left corner
+fn tile_center(tile: IVec2) -> Vec2 {
+    (tile.as_vec2() + 0.5) * TILE_SIZE
+}
+
+fn run_spawn(
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+    level_query: Query<Entity, With<Handle<LdtkLevel>>>,
+    mut spawn_enemies: EventWriter<SpawnEnemyEvent>,
+) {
+    const USAGE: &str = "spawn <enemy> <x> <y>";
+    for event in events.iter().filter(|event| event.is("spawn")) {
+        let result = (|| -> Result<String, String> {
+            let kind: String = event.required_arg(0, USAGE)?;
+            let tile = IVec2::new(event.required_arg(1, USAGE)?, event.required_arg(2, USAGE)?);
+            let level = level_query.iter().next().ok_or("No level is loaded")?;
+
+            // Knights are the only enemy with a bundle to spawn outside of LDtk so far
+            if !kind.eq_ignore_ascii_case("knight") {
+                return Err(format!("Can't spawn {}", kind));
+            }
+            spawn_enemies.send(SpawnEnemyEvent {
+                level,
+                kind: EnemyKind::Knight,
+                translation: tile_center(tile).extend(0.0),
+            });
+            Ok(format!("Spawned a knight at {} {}", tile.x, tile.y))
+        })();
+        console.report(result);
+    }
+}
+
+/// Floor tile closest to the middle of a level, counted from its bottom-left corner
+fn central_floor_tile(map: &LevelMap) -> IVec2 {
+    let center = IVec2::new(map.columns, map.rows) / 2;
+    let tile = (0..map.rows)
+        .flat_map(|y| (0..map.columns).map(move |x| IVec2::new(x, y)))
+        .filter(|tile| map.cell(*tile) == MapCell::Floor)
+        .min_by_key(|tile| (*tile - center).abs().max_element())
+        .unwrap_or(center);
+    IVec2::new(tile.x, map.rows - 1 - tile.y)
+}
+
+fn run_tp(
+    mut commands: Commands,
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+    worlds: Query<&Handle<LdtkAsset>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+) {
+    const USAGE: &str = "tp <level> [x y]";
+    for event in events.iter().filter(|event| event.is("tp")) {
+        let result = (|| -> Result<String, String> {
+            let name: String = event.required_arg(0, USAGE)?;
+            let project = worlds
+                .iter()
+                .find_map(|handle| ldtk_assets.get(handle))
+                .map(|ldtk_asset| &ldtk_asset.project)
+                .ok_or_else(|| format!("{} isn't loaded", LDTK_PROJECT))?;
+            let level = project
+                .levels
+                .iter()
+                .find(|level| level.identifier.eq_ignore_ascii_case(&name))
+                .ok_or_else(|| format!("Unknown level {}", name))?;
+
+            let tile = match (event.arg(1)?, event.arg(2)?) {
+                (Some(x), Some(y)) => IVec2::new(x, y),
+                (None, None) => LevelMap::from_level(level)
+                    .map(|map| central_floor_tile(&map))
+                    .ok_or_else(|| format!("{} has no {} layer", level.identifier, COLLISIONS_LAYER))?,
+                _ => return Err(format!("Usage: {}", USAGE)),
+            };
+            travel_to_tile(&mut commands, &level.iid, tile);
+            Ok(format!("Travelling to {} {} {}", level.identifier, tile.x, tile.y))
+        })();
+        console.report(result);
+    }
+}
+
+fn run_generate(
+    mut commands: Commands,
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+    worlds: Query<&Handle<LdtkAsset>>,
+    mut ldtk_assets: ResMut<Assets<LdtkAsset>>,
+    mut levels: ResMut<Assets<LdtkLevel>>,
+    items: Res<ItemRegistry>,
+    mut save: ResMut<SaveData>,
+) {
+    for event in events.iter().filter(|event| event.is("generate")) {
+        let result = (|| -> Result<String, String> {
+            // A fresh floor every time unless asked for one, the seed is printed so a run can be replayed
+            let seed: u64 = event.arg(0)?.unwrap_or_else(dungeon::random_seed);
+            let ldtk_asset = worlds
+                .iter()
+                .next()
+                .and_then(|handle| ldtk_assets.get_mut(handle))
+                .ok_or_else(|| format!("{} isn't loaded", LDTK_PROJECT))?;
+
+            let identifier = dungeon::enter_floor(&mut commands, ldtk_asset, &mut levels, &items, &mut save, seed)?;
+            Ok(format!("Generated {} from seed {}", identifier, seed))
+        })();
+        console.report(result);
+    }
+}
+
+fn run_give(
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+    player: Query<Entity, With<Player>>,
+    items: Res<ItemRegistry>,
+    mut collect: EventWriter<CollectItemEvent>,
+) {
+    const USAGE: &str = "give <item> [count]";
+    for event in events.iter().filter(|event| event.is("give")) {
+        let result = (|| -> Result<String, String> {
+            let name: String = event.required_arg(0, USAGE)?;
+            let count: u32 = event.arg(1)?.unwrap_or(1);
+            let item = items
+                .keys()
+                .find(|item| item.identifier().eq_ignore_ascii_case(&name))
+                .cloned()
+                .ok_or_else(|| format!("Unknown item {}", name))?;
+            let collector = player.get_single().map_err(|_| "There is no player")?;
+
+            // Counts towards quests the same way looting does
+            let given = format!("Gave {} {}", count, item.identifier());
+            collect.send(CollectItemEvent { collector, item, count });
+            Ok(given)
+        })();
+        console.report(result);
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled { "on" } else { "off" }
+}
+
+fn run_god(
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+    player: Query<(Entity, Option<&GodMode>), With<Player>>,
+    mut god_mode: EventWriter<GodModeEvent>,
+) {
+    for _ in events.iter().filter(|event| event.is("god")) {
+        let result = match player.get_single() {
+            Ok((target, current)) => {
+                let enabled = current.is_none();
+                god_mode.send(GodModeEvent { target, enabled });
+                Ok(format!("God mode {}", on_off(enabled)))
+            },
+            Err(_) => Err("There is no player".to_string()),
+        };
+        console.report(result);
+    }
+}
+
+fn run_noclip(
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+    player: Query<(Entity, Option<&NoClip>), With<Player>>,
+    mut noclip_events: EventWriter<NoClipEvent>,
+) {
+    for _ in events.iter().filter(|event| event.is("noclip")) {
+        let result = match player.get_single() {
+            Ok((target, current)) => {
+                let enabled = current.is_none();
+                noclip_events.send(NoClipEvent { target, enabled });
+                Ok(format!("Noclip {}", on_off(enabled)))
+            },
+            Err(_) => Err("There is no player".to_string()),
+        };
+        console.report(result);
+    }
+}
+
+fn run_kill_all(
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+    enemies: Query<(Entity, &Health, Option<&Invulnerable>), With<EnemyKind>>,
+    mut damage: EventWriter<DamageEvent>,
+    state: Res<CurrentState<GameState>>,
+) {
+    for _ in events.iter().filter(|event| event.is("kill_all")) {
+        // Damage is only applied in game, anywhere else the events would be dropped
+        if state.0 != GameState::InGame {
+            console.print("Only available in game");
+            continue;
+        }
+
+        // Enemies still invulnerable from a recent hit shrug this off, like any other attack
+        let (mut struck, mut spared) = (0, 0);
+        for (entity, health, invulnerable) in enemies.iter() {
+            if invulnerable.is_some() {
+                spared += 1;
+                continue;
+            }
+            damage.send(DamageEvent { target: entity, amount: health.current, source: None });
+            struck += 1;
+        }
+        if spared > 0 {
+            console.print(format!("Struck down {} enemies, {} were invulnerable", struck, spared));
+        } else {
+            console.print(format!("Struck down {} enemies", struck));
+        }
+    }
+}
+
+fn run_state(
+    mut commands: Commands,
+    mut events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<Console>,
+) {
+    for event in events.iter().filter(|event| event.is("state")) {
+        let result = event
+            .required_arg::<GameState>(0, "state <GameState>")
+            .and_then(|state| {
+                if !SWITCHABLE_STATES.contains(&state) {
+                    return Err(format!("Can't switch to {:?} from the console", state));
+                }
+                commands.insert_resource(NextState(state));
+                Ok(format!("Switching to {:?}", state))
+            });
+        console.report(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> ConsoleCommands {
+        let mut registry = ConsoleCommands::default();
+        registry.register(ConsoleCommand::new("give", "", "").with_completions(0, ["Battery", "Bandage", "Key"]));
+        registry.register(ConsoleCommand::new("god", "", ""));
+        registry.register(ConsoleCommand::new("tp", "", ""));
+        registry
+    }
+
+    #[test]
+    fn completes_unique_command_names() {
+        let completion = registry().complete("t");
+        assert_eq!(completion.input, "tp ");
+        assert!(completion.candidates.is_empty());
+    }
+
+    #[test]
+    fn completes_arguments_up_to_the_common_prefix() {
+        let registry = registry();
+
+        let completion = registry.complete("give b");
+        assert_eq!(completion.input, "give Ba");
+        assert_eq!(completion.candidates, vec!["Battery", "Bandage"]);
+
+        assert_eq!(registry.complete("give ban").input, "give Bandage ");
+        assert_eq!(registry.complete("god x").input, "god x");
+    }
+
+    #[test]
+    fn parses_game_states_ignoring_case() {
+        let event = ConsoleCommandEvent::parse("state worldmap").unwrap();
+        assert_eq!(event.required_arg::<GameState>(0, ""), Ok(GameState::WorldMap));
+        assert!(event.arg::<GameState>(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn refuses_states_that_need_setting_up() {
+        let mut world = World::new();
+        world.init_resource::<Console>();
+        world.init_resource::<Events<ConsoleCommandEvent>>();
+        world
+            .resource_mut::<Events<ConsoleCommandEvent>>()
+            .send(ConsoleCommandEvent::parse("state Combat").unwrap());
+        SystemStage::single_threaded().with_system(run_state).run(&mut world);
+
+        assert!(world.get_resource::<NextState<GameState>>().is_none());
+        assert_eq!(world.resource::<Console>().lines.back().unwrap(), "Can't switch to Combat from the console");
+    }
+}
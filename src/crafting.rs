@@ -0,0 +1,211 @@
+use std::{collections::{BTreeMap, HashSet}, fs};
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+use crate::{misc::state::GameState, item::{Inventory, Item}, projectile::Ammo, player::Player, level::ldtk_project_json};
+
+const RECIPE_SETTINGS_PATH: &str = "settings/recipes.json";
+/// Name of the LDtk enum every recipe ingredient and result has to come from
+const ITEM_ENUM: &str = "Item";
+
+pub struct CraftingPlugin;
+
+impl Plugin for CraftingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(RecipeBook::load())
+            .add_event::<CraftEvent>()
+            .add_system(
+                craft
+                    .run_in_state(GameState::Crafting)
+            );
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "kind")]
+pub enum CraftOutput {
+    Item { item: Item, amount: u32 },
+    Ammo { amount: i32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Recipe {
+    pub name: String,
+    pub inputs: BTreeMap<Item, u32>,
+    pub output: CraftOutput,
+}
+
+impl Recipe {
+    pub fn can_craft(&self, inventory: &Inventory) -> bool {
+        self.inputs.iter().all(|(item, amount)| inventory.count(item) >= *amount)
+    }
+
+    /// Human readable ingredients, such as `3 Scraps + 1 Battery`
+    pub fn describe_inputs(&self) -> String {
+        self.inputs
+            .iter()
+            .map(|(item, amount)| format!("{} {}", amount, item.identifier()))
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+
+    fn items(&self) -> impl Iterator<Item = &Item> {
+        let output = match &self.output {
+            CraftOutput::Item { item, .. } => Some(item),
+            CraftOutput::Ammo { .. } => None,
+        };
+        self.inputs.keys().chain(output)
+    }
+}
+
+/// Every recipe, in the order they are listed in `settings/recipes.json`
+#[derive(Clone, Debug, Default, Deref)]
+pub struct RecipeBook(pub Vec<Recipe>);
+
+impl RecipeBook {
+    pub fn read() -> Result<Self, String> {
+        let settings = fs::read_to_string(RECIPE_SETTINGS_PATH)
+            .map_err(|error| format!("Failed to open {}: {}", RECIPE_SETTINGS_PATH, error))?;
+        serde_json::from_str(&settings)
+            .map(RecipeBook)
+            .map_err(|error| format!("Failed to parse {}: {}", RECIPE_SETTINGS_PATH, error))
+    }
+
+    /// Falls back to no recipes at all when the settings can't be read or don't match the LDtk project
+    pub fn load() -> Self {
+        let recipes = match RecipeBook::read() {
+            Ok(recipes) => recipes,
+            Err(error) => {
+                warn!("{}, crafting is disabled", error);
+                return RecipeBook::default();
+            },
+        };
+
+        if let Some(project) = ldtk_project_json() {
+            if let Err(errors) = recipes.validate(&ldtk_items(&project)) {
+                warn!("Invalid recipes in {}, crafting is disabled:\n{}", RECIPE_SETTINGS_PATH, errors.join("\n"));
+                return RecipeBook::default();
+            }
+        }
+        recipes
+    }
+
+    /// Checks that every recipe only references values of the LDtk `Item` enum
+    pub fn validate(&self, known_items: &HashSet<String>) -> Result<(), Vec<String>> {
+        let errors: Vec<String> = self
+            .iter()
+            .flat_map(|recipe| {
+                recipe
+                    .items()
+                    .filter(|item| !known_items.contains(item.identifier()))
+                    .map(move |item| format!("{} uses unknown item {}", recipe.name, item.identifier()))
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Values of the `Item` enum declared in the LDtk project
+pub fn ldtk_items(project: &serde_json::Value) -> HashSet<String> {
+    project["defs"]["enums"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|enum_def| enum_def["identifier"] == ITEM_ENUM)
+        .flat_map(|enum_def| enum_def["values"].as_array().cloned().unwrap_or_default())
+        .filter_map(|value| value["id"].as_str().map(str::to_string))
+        .collect()
+}
+
+/// Sent by `ui::crafting` with the index of a recipe in the `RecipeBook`
+pub struct CraftEvent(pub usize);
+
+fn craft(
+    mut events: EventReader<CraftEvent>,
+    mut player: Query<(&mut Inventory, &mut Ammo), With<Player>>,
+    recipes: Res<RecipeBook>,
+) {
+    for event in events.iter() {
+        let recipe = match recipes.get(event.0) {
+            Some(recipe) => recipe,
+            None => continue,
+        };
+
+        for (mut inventory, mut ammo) in player.iter_mut() {
+            if !recipe.can_craft(&inventory) {
+                continue;
+            }
+            // Crafting ammo the player has no room for would waste the ingredients
+            if let CraftOutput::Ammo { .. } = recipe.output {
+                if ammo.current >= ammo.max {
+                    continue;
+                }
+            }
+
+            for (item, amount) in recipe.inputs.iter() {
+                inventory.remove(item, *amount);
+            }
+            match &recipe.output {
+                CraftOutput::Item { item, amount } => inventory.add(item.clone(), *amount),
+                CraftOutput::Ammo { amount } => ammo.current = (ammo.current + amount).min(ammo.max),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known_items() -> HashSet<String> {
+        ["Scraps", "Battery", "Food"].into_iter().map(str::to_string).collect()
+    }
+
+    fn recipe(inputs: &[(&str, u32)], output: CraftOutput) -> Recipe {
+        Recipe {
+            name: "Test".to_string(),
+            inputs: inputs.iter().map(|(item, amount)| (Item::new(item), *amount)).collect(),
+            output,
+        }
+    }
+
+    #[test]
+    fn recipes_from_known_items_are_valid() {
+        let recipes = RecipeBook(vec![
+            recipe(&[("Scraps", 3), ("Battery", 1)], CraftOutput::Ammo { amount: 10 }),
+            recipe(&[("Scraps", 2)], CraftOutput::Item { item: Item::new("Food"), amount: 1 }),
+        ]);
+
+        assert_eq!(recipes.validate(&known_items()), Ok(()));
+    }
+
+    #[test]
+    fn unknown_ingredients_and_results_are_reported() {
+        let recipes = RecipeBook(vec![
+            recipe(&[("Scraps", 3), ("Gear", 1)], CraftOutput::Item { item: Item::new("Laser"), amount: 1 }),
+        ]);
+
+        let errors = recipes.validate(&known_items()).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|error| error.contains("Gear")));
+        assert!(errors.iter().any(|error| error.contains("Laser")));
+    }
+
+    #[test]
+    fn crafting_needs_every_ingredient() {
+        let recipe = recipe(&[("Scraps", 3), ("Battery", 1)], CraftOutput::Ammo { amount: 10 });
+        let mut inventory = Inventory::default();
+        inventory.add(Item::new("Scraps"), 3);
+        assert!(!recipe.can_craft(&inventory));
+
+        inventory.add(Item::new("Battery"), 1);
+        assert!(recipe.can_craft(&inventory));
+        assert_eq!(recipe.describe_inputs(), "1 Battery + 3 Scraps");
+    }
+}
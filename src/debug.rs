@@ -0,0 +1,384 @@
+use bevy::{diagnostic::{Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin}, prelude::*};
+use bevy_ecs_ldtk::EntityInstance;
+use iyes_loopless::prelude::*;
+use crate::{
+    misc::state::GameState,
+    player::Player,
+    level::{Collider, props::{Container, Door}},
+    enemy::{Patrol, settings::EnemyKind, attack::Hostile},
+    combat::Health,
+    projectile::Ammo,
+    item::{EquippedItem, Inventory},
+    device::{Device, Elevator},
+    dialogue::Dialogue,
+    trigger::TriggerZone,
+    lighting::LightSource,
+    ui::{menu_assets::MenuAssets, style::sized_text_bundle},
+    PlayerCamera,
+    TILE_SIZE,
+};
+
+/// Above every game layer, fog and lighting included
+const DEBUG_Z: f32 = 200.0;
+const LINE_WIDTH: f32 = 0.5;
+/// Box the movement and combat code collide characters with
+const CHARACTER_SIZE: f32 = TILE_SIZE - 1.0;
+const COLLIDER_COLOR: Color = Color::rgba(0.2, 1.0, 0.2, 0.8);
+const PLAYER_COLOR: Color = Color::rgba(0.2, 0.8, 1.0, 0.9);
+const ENEMY_COLOR: Color = Color::rgba(1.0, 0.2, 0.2, 0.9);
+const PATROL_COLOR: Color = Color::rgba(1.0, 0.9, 0.2, 0.8);
+const TRIGGER_COLOR: Color = Color::rgba(0.25, 0.75, 0.63, 0.8);
+const PANEL_BACKGROUND: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+const FONT_SIZE: f32 = 14.0;
+const TEXT_MARGIN: f32 = 4.0;
+
+/// Development overlay toggled with F3, only added to debug builds
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugin(FrameTimeDiagnosticsPlugin::default())
+            .add_plugin(EntityCountDiagnosticsPlugin::default())
+            .init_resource::<DebugOverlay>()
+            .add_system(toggle_debug_overlay)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_if(debug_enabled)
+                    .with_system(draw_debug_shapes)
+                    .with_system(despawn_orphaned_shapes)
+                    .with_system(update_stats)
+                    .with_system(update_inspector)
+                    .into()
+            )
+            .add_system(
+                pick_entity
+                    .run_in_state(GameState::InGame)
+                    .run_if(debug_enabled)
+            );
+    }
+}
+
+#[derive(Default)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    /// Entity clicked on last, shown in the inspector panel
+    pub inspected: Option<Entity>,
+}
+
+fn debug_enabled(overlay: Res<DebugOverlay>) -> bool {
+    overlay.enabled
+}
+
+/// Line sprite of the overlay, drawn for `owner`
+#[derive(Component)]
+struct DebugShape {
+    owner: Entity,
+}
+
+/// Lines outlining an entity and the sprites showing them, moved along with the entity
+#[derive(Component, Default)]
+struct DebugShapes {
+    lines: Vec<Line>,
+    sprites: Vec<Entity>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Line {
+    from: Vec2,
+    to: Vec2,
+    color: Color,
+}
+
+impl Line {
+    fn sprite(&self) -> (Sprite, Transform) {
+        let delta = self.to - self.from;
+        let sprite = Sprite {
+            color: self.color,
+            custom_size: Some(Vec2::new(delta.length().max(LINE_WIDTH), LINE_WIDTH)),
+            ..Default::default()
+        };
+        let transform = Transform {
+            translation: ((self.from + self.to) / 2.0).extend(DEBUG_Z),
+            rotation: Quat::from_rotation_z(delta.y.atan2(delta.x)),
+            ..Default::default()
+        };
+        (sprite, transform)
+    }
+}
+
+#[derive(Component)]
+struct DebugPanel;
+
+#[derive(Component)]
+struct StatsText;
+
+#[derive(Component)]
+struct InspectorText;
+
+fn toggle_debug_overlay(
+    mut commands: Commands,
+    mut overlay: ResMut<DebugOverlay>,
+    panels: Query<Entity, With<DebugPanel>>,
+    shapes: Query<Entity, With<DebugShape>>,
+    owners: Query<Entity, With<DebugShapes>>,
+    menu_assets: Option<Res<MenuAssets>>,
+    keys: Res<Input<KeyCode>>,
+) {
+    // The panels need the menu font, which is only there once the main menu has loaded
+    let menu_assets = match menu_assets {
+        Some(menu_assets) if keys.just_pressed(KeyCode::F3) => menu_assets,
+        _ => return,
+    };
+
+    overlay.enabled = !overlay.enabled;
+    if !overlay.enabled {
+        overlay.inspected = None;
+        panels.for_each(|entity| commands.entity(entity).despawn_recursive());
+        shapes.for_each(|entity| commands.entity(entity).despawn());
+        owners.for_each(|entity| {
+            commands.entity(entity).remove::<DebugShapes>();
+        });
+        return;
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(12.0),
+                    bottom: Val::Px(12.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: UiRect::all(Val::Px(4.0)),
+                ..Default::default()
+            },
+            color: PANEL_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(DebugPanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(sized_text_bundle("", FONT_SIZE, Color::WHITE, TEXT_MARGIN, &menu_assets))
+                .insert(StatsText);
+            parent
+                .spawn_bundle(sized_text_bundle("", FONT_SIZE, Color::WHITE, TEXT_MARGIN, &menu_assets))
+                .insert(InspectorText);
+        });
+}
+
+fn outline(lines: &mut Vec<Line>, center: Vec2, size: Vec2, color: Color) {
+    let half = size / 2.0;
+    let corners = [
+        center + Vec2::new(-half.x, -half.y),
+        center + Vec2::new(half.x, -half.y),
+        center + Vec2::new(half.x, half.y),
+        center + Vec2::new(-half.x, half.y),
+    ];
+    for (index, corner) in corners.iter().enumerate() {
+        lines.push(Line { from: *corner, to: corners[(index + 1) % corners.len()], color });
+    }
+}
+
+fn spawn_line(commands: &mut Commands, owner: Entity, line: &Line) -> Entity {
+    let (sprite, transform) = line.sprite();
+    commands
+        .spawn_bundle(SpriteBundle { sprite, transform, ..Default::default() })
+        .insert(DebugShape { owner })
+        .id()
+}
+
+/// Outlines colliders, characters, patrols and trigger zones, only touching the sprites of entities that moved
+#[allow(clippy::type_complexity)]
+fn draw_debug_shapes(
+    mut commands: Commands,
+    mut owners: Query<
+        (Entity, &Transform, &GlobalTransform, Option<&mut DebugShapes>, Option<&Collider>, Option<&Player>, Option<&EnemyKind>, Option<&Patrol>, Option<&TriggerZone>),
+        (Or<(With<Collider>, With<Player>, With<EnemyKind>, With<TriggerZone>)>, Without<DebugShape>),
+    >,
+    mut sprites: Query<(&mut Sprite, &mut Transform), With<DebugShape>>,
+) {
+    for (entity, transform, global_transform, shapes, collider, player, enemy, patrol, zone) in owners.iter_mut() {
+        let position = global_transform.translation().truncate();
+        let mut lines = Vec::new();
+
+        // Level colliders store their size in their scale
+        if collider.is_some() && enemy.is_none() {
+            outline(&mut lines, position, transform.scale.truncate(), COLLIDER_COLOR);
+        }
+        if let Some(zone) = zone {
+            outline(&mut lines, position, zone.size, TRIGGER_COLOR);
+        }
+        if player.is_some() {
+            outline(&mut lines, position, Vec2::splat(CHARACTER_SIZE), PLAYER_COLOR);
+        }
+        if enemy.is_some() {
+            outline(&mut lines, position, Vec2::splat(CHARACTER_SIZE), ENEMY_COLOR);
+
+            // Patrol points are in the same space as the enemy's own transform
+            let parent_offset = position - transform.translation.truncate();
+            if let Some(patrol) = patrol {
+                for point in patrol.points.iter() {
+                    outline(&mut lines, *point + parent_offset, Vec2::splat(2.0), PATROL_COLOR);
+                }
+                for pair in patrol.points.windows(2) {
+                    lines.push(Line { from: pair[0] + parent_offset, to: pair[1] + parent_offset, color: PATROL_COLOR });
+                }
+            }
+        }
+
+        let mut shapes = match shapes {
+            Some(shapes) => shapes,
+            None => {
+                let sprites = lines.iter().map(|line| spawn_line(&mut commands, entity, line)).collect();
+                commands.entity(entity).insert(DebugShapes { lines, sprites });
+                continue;
+            },
+        };
+        if shapes.lines == lines {
+            continue;
+        }
+
+        // Patrols can gain or lose points, everything else keeps its number of lines
+        if shapes.lines.len() == lines.len() {
+            for (line, sprite_entity) in lines.iter().zip(shapes.sprites.iter()) {
+                if let Ok((mut sprite, mut sprite_transform)) = sprites.get_mut(*sprite_entity) {
+                    (*sprite, *sprite_transform) = line.sprite();
+                }
+            }
+        } else {
+            for sprite in shapes.sprites.drain(..) {
+                commands.entity(sprite).despawn();
+            }
+            shapes.sprites = lines.iter().map(|line| spawn_line(&mut commands, entity, line)).collect();
+        }
+        shapes.lines = lines;
+    }
+}
+
+/// Removes the outlines of entities that were despawned
+fn despawn_orphaned_shapes(
+    mut commands: Commands,
+    shapes: Query<(Entity, &DebugShape)>,
+    owners: Query<(), With<DebugShapes>>,
+) {
+    for (entity, shape) in shapes.iter() {
+        if owners.get(shape.owner).is_err() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn update_stats(
+    mut text: Query<&mut Text, With<StatsText>>,
+    diagnostics: Res<Diagnostics>,
+) {
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+        .unwrap_or_default();
+    let entities = diagnostics
+        .get(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        .and_then(|count| count.value())
+        .unwrap_or_default();
+
+    for mut text in text.iter_mut() {
+        text.sections[0].value = format!("FPS {:.0}  Entities {:.0}", fps, entities);
+    }
+}
+
+fn cursor_to_world(window: &Window, camera: &Camera, camera_transform: &GlobalTransform) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    let window_size = Vec2::new(window.width(), window.height());
+    let ndc = cursor / window_size * 2.0 - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+    Some(ndc_to_world.project_point3(ndc.extend(-1.0)).truncate())
+}
+
+/// Selects the smallest inspectable entity under the cursor on left click
+fn pick_entity(
+    mut overlay: ResMut<DebugOverlay>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    camera: Query<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    candidates: Query<
+        (Entity, &Transform, &GlobalTransform, Option<&TriggerZone>),
+        Or<(With<EntityInstance>, With<EnemyKind>, With<Collider>)>,
+    >,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let cursor = match (windows.get_primary(), camera.get_single()) {
+        (Some(window), Ok((camera, transform))) => cursor_to_world(window, camera, transform),
+        _ => None,
+    };
+    let cursor = match cursor {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    overlay.inspected = candidates
+        .iter()
+        .filter_map(|(entity, transform, global_transform, zone)| {
+            let size = zone.map_or_else(
+                || transform.scale.truncate().max(Vec2::splat(TILE_SIZE)),
+                |zone| zone.size,
+            );
+            let offset = (cursor - global_transform.translation().truncate()).abs();
+            (offset.x <= size.x / 2.0 && offset.y <= size.y / 2.0).then(|| (entity, size.x * size.y))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity);
+}
+
+fn update_inspector(
+    mut text: Query<&mut Text, With<InspectorText>>,
+    overlay: Res<DebugOverlay>,
+    common: Query<(Option<&EntityInstance>, &Transform, Option<&Health>, Option<&EnemyKind>, Option<&Hostile>, Option<&Patrol>)>,
+    player: Query<(Option<&Ammo>, Option<&Inventory>, Option<&EquippedItem>, Option<&LightSource>)>,
+    props: Query<(Option<&Door>, Option<&Container>, Option<&Device>, Option<&Elevator>, Option<&Dialogue>, Option<&TriggerZone>)>,
+) {
+    let mut lines = Vec::new();
+    match overlay.inspected {
+        None => lines.push("Click an entity to inspect it".to_string()),
+        Some(entity) => {
+            lines.push(format!("{:?}", entity));
+            if let Ok((instance, transform, health, kind, hostile, patrol)) = common.get(entity) {
+                if let Some(instance) = instance {
+                    lines.push(format!("{} ({})", instance.identifier, instance.iid));
+                }
+                lines.push(format!("Transform {:.1} {:.1} {:.3}", transform.translation.x, transform.translation.y, transform.translation.z));
+                lines.extend(health.map(|value| format!("{:?}", value)));
+                lines.extend(kind.map(|value| format!("{:?}", value)));
+                lines.extend(hostile.map(|value| format!("{:?}", value)));
+                lines.extend(patrol.map(|value| format!("{:?}", value)));
+            } else {
+                lines.push("Despawned".to_string());
+            }
+            if let Ok((ammo, inventory, equipped, light)) = player.get(entity) {
+                lines.extend(ammo.map(|value| format!("{:?}", value)));
+                lines.extend(inventory.map(|value| format!("{:?}", value)));
+                lines.extend(equipped.map(|value| format!("{:?}", value)));
+                lines.extend(light.map(|value| format!("{:?}", value)));
+            }
+            if let Ok((door, container, device, elevator, dialogue, zone)) = props.get(entity) {
+                lines.extend(door.map(|value| format!("{:?}", value)));
+                lines.extend(container.map(|value| format!("{:?}", value)));
+                lines.extend(device.map(|value| format!("{:?}", value)));
+                lines.extend(elevator.map(|value| format!("{:?}", value)));
+                lines.extend(dialogue.map(|value| format!("{:?}", value)));
+                lines.extend(zone.map(|value| format!("{:?}", value)));
+            }
+        },
+    }
+
+    let value = lines.join("\n");
+    for mut text in text.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_ecs_ldtk::prelude::LayerMetadata;
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, level::{COLLISIONS_LAYER, ENTITIES_LAYER, terrain::{Terrain, TerrainMap}}, TILE_SIZE};
+
+/// How much of a layer's depth one pixel of height takes up
+/// Keeps every sorted sprite within the layer as long as it stays within 5000 pixels of the origin
+const DEPTH_PER_PIXEL: f32 = 0.0001;
+
+pub struct DepthPlugin;
+
+impl Plugin for DepthPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                y_sort
+                    .run_in_state(GameState::InGame)
+                    .before(TransformSystem::TransformPropagate)
+            );
+    }
+}
+
+/// Sprites with this component get their Z set from their Y position,
+/// so lower sprites are drawn in front of higher ones within their layer
+#[derive(Copy, Clone, Eq, PartialEq, Default, Component, Debug)]
+pub struct YSort;
+
+/// Offset within a layer for a sprite at world height `y`, always within `0.0..1.0`
+pub fn depth_offset(y: f32) -> f32 {
+    (0.5 - y * DEPTH_PER_PIXEL).clamp(0.0, 0.999)
+}
+
+fn y_sort(
+    mut sorted: Query<(&mut Transform, Option<&Parent>), With<YSort>>,
+    parents: Query<&GlobalTransform>,
+    layers: Query<(&LayerMetadata, &GlobalTransform)>,
+    terrain_map: Res<TerrainMap>,
+) {
+    let layer_z = |identifier: &str| {
+        layers
+            .iter()
+            .find(|(metadata, _)| metadata.identifier == identifier)
+            .map(|(_, global_transform)| global_transform.translation().z)
+    };
+
+    let entities_z = match layer_z(ENTITIES_LAYER) {
+        Some(z) => z,
+        None => return,
+    };
+    let collisions_z = layer_z(COLLISIONS_LAYER);
+
+    sorted.for_each_mut(|(mut transform, parent)| {
+        let parent_translation = parent
+            .and_then(|parent| parents.get(parent.get()).ok())
+            .map_or(Vec3::ZERO, |parent| parent.translation());
+        let global = parent_translation + transform.translation;
+
+        // Wall faces live in the Collisions tile layer, so a sprite standing behind one
+        // drops just below that layer instead of being sorted against other sprites
+        let feet = global.truncate() - Vec2::new(0.0, TILE_SIZE / 2.0);
+        let behind_face = terrain_map.get(feet) == Some(Terrain::Face);
+        let layer = match collisions_z {
+            Some(collisions_z) if behind_face => collisions_z - 1.0,
+            _ => entities_z,
+        };
+
+        // Only written when it moved, so idle sprites don't look changed to `update_darkness` and the renderer
+        let z = layer + depth_offset(global.y) - parent_translation.z;
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    })
+}
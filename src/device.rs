@@ -0,0 +1,191 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{prelude::*, EntityInstance};
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, item::{Inventory, ItemEffect, ItemRegistry, PowerEvent, UseItemEvent}, player::Player, save::SaveData, depth::YSort, level::{props::in_reach, travel_to}};
+
+const UNPOWERED_COLOR: Color = Color::rgb(0.35, 0.35, 0.4);
+const POWERED_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
+const ACTIVE_COLOR: Color = Color::rgb(1.0, 1.0, 0.7);
+
+pub struct DevicePlugin;
+
+impl Plugin for DevicePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<DeviceSwitchedEvent>()
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::InGame)
+                    .with_system(restore_powered_devices)
+                    .with_system(interact_with_devices)
+                    .with_system(power_devices)
+                    .with_system(device_visuals)
+                    .with_system(ride_elevator)
+                    .into()
+            );
+    }
+}
+
+/// Anything the player can switch on and off, optionally only once it has been powered
+#[derive(Clone, Debug, Default, Component)]
+pub struct Device {
+    /// LDtk iid, used to remember the powered state in the save data
+    pub iid: String,
+    pub needs_power: bool,
+    pub powered: bool,
+    pub on: bool,
+}
+
+impl Device {
+    pub fn can_switch(&self) -> bool {
+        !self.needs_power || self.powered
+    }
+}
+
+/// Sent whenever the player flips a device
+pub struct DeviceSwitchedEvent {
+    pub device: Entity,
+    pub on: bool,
+}
+
+/// One end of an elevator ride, the other end is the `destination` field of the LDtk `ElevatorLink`
+#[derive(Clone, Debug, Default, Component)]
+pub struct Elevator {
+    pub destination: Option<String>,
+    pub destination_level: Option<String>,
+}
+
+impl From<EntityInstance> for Elevator {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let destination = entity_instance
+            .field_instances
+            .iter()
+            .find(|f| f.identifier == *"destination")
+            .and_then(|f| match &f.value {
+                FieldValue::EntityRef(reference) => reference.clone(),
+                _ => None,
+            });
+
+        Elevator {
+            destination: destination.as_ref().map(|reference| reference.entity_iid.clone()),
+            destination_level: destination.map(|reference| reference.level_iid),
+        }
+    }
+}
+
+fn elevator_device(entity_instance: &EntityInstance) -> Device {
+    Device {
+        iid: entity_instance.iid.clone(),
+        needs_power: true,
+        ..Default::default()
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct ElevatorBundle {
+    // The LDtk tile for elevators comes from the editor's internal icons,
+    // which aren't available at runtime
+    #[sprite_sheet_bundle("sprites/TopDown_by_deepnight.png", 16.0, 16.0, 11, 11, 0.0, 57)]
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[with(elevator_device)]
+    pub device: Device,
+    #[from_entity_instance]
+    pub elevator: Elevator,
+    pub y_sort: YSort,
+}
+
+fn restore_powered_devices(
+    mut devices: Query<&mut Device, Added<Device>>,
+    save: Res<SaveData>,
+) {
+    for mut device in devices.iter_mut() {
+        if save.powered_devices.contains(&device.iid) {
+            device.powered = true;
+        }
+    }
+}
+
+/// E next to a device flips it, an unpowered device first has an item with the `Power` effect used on it
+fn interact_with_devices(
+    player: Query<(Entity, &Transform, &Inventory), With<Player>>,
+    mut devices: Query<(Entity, &mut Device, &GlobalTransform)>,
+    mut switched: EventWriter<DeviceSwitchedEvent>,
+    mut use_item: EventWriter<UseItemEvent>,
+    registry: Res<ItemRegistry>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if !keys.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    for (user, transform, inventory) in player.iter() {
+        for (entity, mut device, device_transform) in devices.iter_mut() {
+            if !in_reach(transform, device_transform) {
+                continue;
+            }
+
+            if !device.can_switch() {
+                let power_source = inventory.items.keys().find(|item| {
+                    registry
+                        .get(item)
+                        .map_or(false, |definition| definition.has_effect(|effect| matches!(effect, ItemEffect::Power)))
+                });
+                if let Some(item) = power_source {
+                    use_item.send(UseItemEvent { user, item: item.clone(), target: Some(entity) });
+                }
+                continue;
+            }
+
+            device.on = !device.on;
+            switched.send(DeviceSwitchedEvent { device: entity, on: device.on });
+        }
+    }
+}
+
+fn power_devices(
+    mut events: EventReader<PowerEvent>,
+    mut devices: Query<(&mut Device, Option<&Elevator>)>,
+    mut save: ResMut<SaveData>,
+) {
+    for event in events.iter() {
+        let (mut device, elevator) = match devices.get_mut(event.target) {
+            Ok(device) => device,
+            Err(_) => continue,
+        };
+
+        device.powered = true;
+        save.powered_devices.insert(device.iid.clone());
+        // Both ends of an elevator share the battery, so the player can always ride back
+        if let Some(destination) = elevator.and_then(|elevator| elevator.destination.clone()) {
+            save.powered_devices.insert(destination);
+        }
+    }
+}
+
+/// Tints devices by state: dim while unpowered, bright while switched on
+fn device_visuals(
+    mut devices: Query<(&Device, &mut TextureAtlasSprite), Changed<Device>>,
+) {
+    devices.for_each_mut(|(device, mut sprite)| {
+        sprite.color = if !device.can_switch() {
+            UNPOWERED_COLOR
+        } else if device.on {
+            ACTIVE_COLOR
+        } else {
+            POWERED_COLOR
+        };
+    })
+}
+
+fn ride_elevator(
+    mut commands: Commands,
+    mut switched: EventReader<DeviceSwitchedEvent>,
+    elevators: Query<&Elevator>,
+) {
+    for event in switched.iter() {
+        if let Ok(Elevator { destination: Some(destination), destination_level: Some(level) }) = elevators.get(event.device) {
+            travel_to(&mut commands, level, destination);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, fs, time::Duration};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{prelude::*, EntityInstance};
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+use crate::{misc::state::GameState, player::Player, level::props::{in_reach, Container}, enemy::attack::Hostile};
+
+/// Scripts live in `assets/dialogue/<name>.json`, where `<name>` is the LDtk `Dialogue` field
+const DIALOGUE_DIRECTORY: &str = "assets/dialogue";
+const CHARACTERS_PER_SECOND: f32 = 40.0;
+/// Node used for dialogue built on the fly, such as a container's description
+const START_NODE: &str = "start";
+
+pub struct DialoguePlugin;
+
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<DialogueChoiceEvent>()
+            .add_system(
+                start_dialogue
+                    .run_in_state(GameState::InGame)
+            )
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Dialogue)
+                    .with_system(type_dialogue)
+                    .with_system(advance_dialogue)
+                    .with_system(choose_dialogue)
+                    .into()
+            );
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    /// Node to jump to, the dialogue ends when there is none
+    pub next: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DialogueNode {
+    pub speaker: Option<String>,
+    /// Image shown next to the text, relative to the assets folder
+    pub portrait: Option<String>,
+    pub text: String,
+    pub choices: Vec<DialogueChoice>,
+    /// Node following this one when it has no choices
+    pub next: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DialogueScript {
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+impl DialogueScript {
+    pub fn load(name: &str) -> Result<Self, String> {
+        let path = format!("{}/{}.json", DIALOGUE_DIRECTORY, name);
+        let script = fs::read_to_string(&path).map_err(|error| format!("Failed to open {}: {}", path, error))?;
+        serde_json::from_str(&script).map_err(|error| format!("Failed to parse {}: {}", path, error))
+    }
+
+    /// A single line of text without choices, used for signs that don't have a script
+    pub fn line(speaker: Option<String>, text: String) -> Self {
+        let node = DialogueNode { speaker, text, ..Default::default() };
+        DialogueScript {
+            start: START_NODE.to_string(),
+            nodes: HashMap::from([(START_NODE.to_string(), node)]),
+        }
+    }
+}
+
+/// Name of the dialogue script of an NPC or sign, read from the LDtk `Dialogue` field
+#[derive(Clone, Debug, Default, Component)]
+pub struct Dialogue(pub Option<String>);
+
+pub fn dialogue_from_field(entity_instance: &EntityInstance) -> Dialogue {
+    let script = entity_instance
+        .field_instances
+        .iter()
+        .find(|f| f.identifier == *"Dialogue")
+        .and_then(|f| match &f.value {
+            FieldValue::String(script) => script.clone(),
+            _ => None,
+        });
+
+    Dialogue(script)
+}
+
+/// The conversation shown by `ui::dialogue`, only exists in `GameState::Dialogue`
+pub struct ActiveDialogue {
+    pub script: DialogueScript,
+    pub node: String,
+    /// How many characters of the current node the typewriter has revealed
+    pub revealed: usize,
+    typewriter: Timer,
+}
+
+impl ActiveDialogue {
+    pub fn new(script: DialogueScript) -> Self {
+        let node = script.start.clone();
+        ActiveDialogue {
+            script,
+            node,
+            revealed: 0,
+            typewriter: Timer::new(Duration::from_secs_f32(1.0 / CHARACTERS_PER_SECOND), true),
+        }
+    }
+
+    pub fn current(&self) -> Option<&DialogueNode> {
+        self.script.nodes.get(&self.node)
+    }
+
+    pub fn is_fully_revealed(&self) -> bool {
+        self.current().map_or(true, |node| self.revealed >= node.text.chars().count())
+    }
+
+    pub fn revealed_text(&self) -> String {
+        self.current().map_or_else(String::new, |node| node.text.chars().take(self.revealed).collect())
+    }
+
+    /// Moves to `next`, returns false when the dialogue is over
+    fn go_to(&mut self, next: Option<&String>) -> bool {
+        match next {
+            Some(next) if self.script.nodes.contains_key(next) => {
+                self.node = next.clone();
+                self.revealed = 0;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Sent by `ui::dialogue` with the index of the clicked choice
+pub struct DialogueChoiceEvent(pub usize);
+
+fn start_dialogue(
+    mut commands: Commands,
+    player: Query<&Transform, With<Player>>,
+    speakers: Query<(&Dialogue, &GlobalTransform, Option<&Container>, Option<&Hostile>)>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if !keys.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    for transform in player.iter() {
+        for (dialogue, speaker_transform, container, hostile) in speakers.iter() {
+            // Hostile knights fight instead of talking
+            if !in_reach(transform, speaker_transform) || hostile.map_or(false, |hostile| hostile.0) {
+                continue;
+            }
+
+            let script = match (&dialogue.0, container) {
+                (Some(name), _) => match DialogueScript::load(name) {
+                    Ok(script) => script,
+                    Err(error) => {
+                        warn!("{}", error);
+                        continue;
+                    },
+                },
+                // Containers without a script read out their description instead
+                (None, Some(Container { label, description: Some(description), .. })) => {
+                    DialogueScript::line(Some(label.clone()), description.replace("**", ""))
+                },
+                _ => continue,
+            };
+
+            commands.insert_resource(ActiveDialogue::new(script));
+            commands.insert_resource(NextState(GameState::Dialogue));
+            return;
+        }
+    }
+}
+
+fn type_dialogue(
+    mut dialogue: ResMut<ActiveDialogue>,
+    time: Res<Time>,
+) {
+    if dialogue.is_fully_revealed() {
+        return;
+    }
+
+    let ticks = dialogue.typewriter.tick(time.delta()).times_finished_this_tick() as usize;
+    if ticks > 0 {
+        dialogue.revealed += ticks;
+    }
+}
+
+fn end_dialogue(commands: &mut Commands) {
+    commands.remove_resource::<ActiveDialogue>();
+    commands.insert_resource(NextState(GameState::InGame));
+}
+
+/// E, Space or Enter skips the typewriter, then moves on to the next node if there are no choices
+fn advance_dialogue(
+    mut commands: Commands,
+    mut dialogue: ResMut<ActiveDialogue>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if !keys.any_just_pressed([KeyCode::E, KeyCode::Space, KeyCode::Return]) {
+        return;
+    }
+
+    if !dialogue.is_fully_revealed() {
+        dialogue.revealed = usize::MAX;
+        return;
+    }
+
+    let next = match dialogue.current() {
+        Some(node) if node.choices.is_empty() => node.next.clone(),
+        Some(_) => return,
+        None => None,
+    };
+    if !dialogue.go_to(next.as_ref()) {
+        end_dialogue(&mut commands);
+    }
+}
+
+/// Number keys pick a choice, as do clicks on the choice buttons
+fn choose_dialogue(
+    mut commands: Commands,
+    mut dialogue: ResMut<ActiveDialogue>,
+    mut clicked: EventReader<DialogueChoiceEvent>,
+    keys: Res<Input<KeyCode>>,
+) {
+    const NUMBER_KEYS: [KeyCode; 9] = [
+        KeyCode::Key1, KeyCode::Key2, KeyCode::Key3,
+        KeyCode::Key4, KeyCode::Key5, KeyCode::Key6,
+        KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    ];
+
+    let pressed = NUMBER_KEYS.iter().position(|key| keys.just_pressed(*key));
+    let choice = match clicked.iter().last().map(|event| event.0).or(pressed) {
+        Some(choice) => choice,
+        None => return,
+    };
+    if !dialogue.is_fully_revealed() {
+        return;
+    }
+
+    let next = match dialogue.current().and_then(|node| node.choices.get(choice)) {
+        Some(choice) => choice.next.clone(),
+        None => return,
+    };
+    if !dialogue.go_to(next.as_ref()) {
+        end_dialogue(&mut commands);
+    }
+}
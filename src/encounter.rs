@@ -0,0 +1,480 @@
+use std::{collections::VecDeque, time::Duration};
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, player::{Player, can_move, Direction}, enemy::{attack::Hostile, settings::{EnemyKind, EnemySettings}}, combat::{AttackStats, GodMode, Health, Invulnerable}, item::{Inventory, ItemRegistry, UseItemEvent}, level::{Collider, terrain::TerrainMap}, quest::QuestEvent, TILE_SIZE};
+
+/// How long the player can't start another encounter after retreating from one
+const RETREAT_INVULNERABILITY: Duration = Duration::from_millis(1500);
+const ENEMY_TURN_DELAY: Duration = Duration::from_millis(600);
+const RETREAT_DISTANCE: f32 = TILE_SIZE * 1.5;
+
+pub struct EncounterPlugin;
+
+impl Plugin for EncounterPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<EncounterMode>()
+            .add_event::<CombatAction>()
+            .add_system(
+                toggle_encounter_mode
+                    .run_in_state(GameState::InGame)
+            )
+            .add_system(
+                start_encounter
+                    .run_in_state(GameState::InGame)
+                    .run_if(encounter_mode_enabled)
+            )
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Combat)
+                    .with_system(player_turn)
+                    .with_system(enemy_turn)
+                    .with_system(finish_encounter)
+                    .into()
+            );
+    }
+}
+
+/// Turn-based encounters are optional, when disabled knights fight in real time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EncounterMode {
+    pub enabled: bool,
+}
+
+fn encounter_mode_enabled(mode: Res<EncounterMode>) -> bool {
+    mode.enabled
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Turn {
+    Player,
+    Enemy,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Outcome {
+    /// The enemy is despawned from the overworld
+    Victory,
+    /// The player is moved away from the enemy and briefly can't start another encounter
+    Retreat,
+}
+
+/// Choices of the combat menu, sent by `ui::combat_menu`
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum CombatAction {
+    Attack,
+    Item,
+    Defend,
+    Flee,
+}
+
+impl CombatAction {
+    pub const ALL: [CombatAction; 4] = [
+        CombatAction::Attack,
+        CombatAction::Item,
+        CombatAction::Defend,
+        CombatAction::Flee,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CombatAction::Attack => "Attack",
+            CombatAction::Item => "Item",
+            CombatAction::Defend => "Defend",
+            CombatAction::Flee => "Flee",
+        }
+    }
+}
+
+/// The fight currently shown by the combat menu, only exists in `GameState::Combat`
+pub struct Encounter {
+    pub enemy: Entity,
+    pub turns: VecDeque<Turn>,
+    pub defending: bool,
+    pub log: String,
+    pub outcome: Option<Outcome>,
+    /// Where the player goes if they flee or get knocked out
+    pub retreat: Vec2,
+    enemy_timer: Timer,
+}
+
+impl Encounter {
+    pub fn current_turn(&self) -> Turn {
+        self.turns[0]
+    }
+
+    fn end_turn(&mut self) {
+        self.turns.rotate_left(1);
+        self.enemy_timer.reset();
+    }
+}
+
+fn toggle_encounter_mode(
+    mut mode: ResMut<EncounterMode>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::T) {
+        mode.enabled = !mode.enabled;
+    }
+}
+
+fn start_encounter(
+    mut commands: Commands,
+    player: Query<(&Transform, &GlobalTransform), (With<Player>, Without<Invulnerable>)>,
+    enemies: Query<(Entity, &GlobalTransform, &Hostile), With<EnemyKind>>,
+    colliders: Query<&Transform, (With<Collider>, Without<Player>)>,
+    terrain_map: Res<TerrainMap>,
+) {
+    for (transform, global_transform) in player.iter() {
+        let touching = enemies.iter().find(|(_, enemy_transform, hostile)| {
+            hostile.0 && collide(
+                global_transform.translation(),
+                Vec2::splat(TILE_SIZE),
+                enemy_transform.translation(),
+                Vec2::splat(TILE_SIZE),
+            ).is_some()
+        });
+
+        if let Some((enemy, enemy_transform, _)) = touching {
+            // Retreat one step straight away from the enemy along the dominant axis,
+            // staying put if that would walk into a wall. The retreat itself is local, like the colliders
+            let away = global_transform.translation().truncate() - enemy_transform.translation().truncate();
+            let direction = if away.x.abs() > away.y.abs() {
+                if away.x > 0.0 { Direction::Right } else { Direction::Left }
+            } else if away.y > 0.0 {
+                Direction::Up
+            } else {
+                Direction::Down
+            };
+            let retreat = if can_move(transform.translation, &direction, RETREAT_DISTANCE, colliders.iter(), &terrain_map) {
+                transform.translation.truncate() + direction.vector() * RETREAT_DISTANCE
+            } else {
+                transform.translation.truncate()
+            };
+
+            commands.insert_resource(Encounter {
+                enemy,
+                turns: VecDeque::from([Turn::Player, Turn::Enemy]),
+                defending: false,
+                log: "A knight blocks the way!".to_string(),
+                outcome: None,
+                retreat,
+                enemy_timer: Timer::new(ENEMY_TURN_DELAY, false),
+            });
+            commands.insert_resource(NextState(GameState::Combat));
+            return;
+        }
+    }
+}
+
+fn player_turn(
+    mut actions: EventReader<CombatAction>,
+    mut encounter: ResMut<Encounter>,
+    player: Query<(Entity, &Inventory), With<Player>>,
+    mut enemies: Query<&mut Health, With<EnemyKind>>,
+    mut use_item: EventWriter<UseItemEvent>,
+    registry: Res<ItemRegistry>,
+) {
+    for action in actions.iter() {
+        if encounter.outcome.is_some() || encounter.current_turn() != Turn::Player {
+            continue;
+        }
+        let (player, inventory) = match player.get_single() {
+            Ok(player) => player,
+            Err(_) => return,
+        };
+
+        encounter.defending = false;
+        match action {
+            CombatAction::Attack => {
+                if let Ok(mut enemy_health) = enemies.get_mut(encounter.enemy) {
+                    let damage = AttackStats::default().damage;
+                    enemy_health.damage(damage);
+                    encounter.log = format!("You hit the knight for {}.", damage);
+                    if enemy_health.is_dead() {
+                        encounter.log = "The knight falls!".to_string();
+                        encounter.outcome = Some(Outcome::Victory);
+                    }
+                }
+            },
+            CombatAction::Item => {
+                // Eat the weakest healing item first so stronger ones are saved for later
+                let healing = inventory
+                    .items
+                    .keys()
+                    .filter_map(|item| {
+                        registry
+                            .get(item)
+                            .and_then(|definition| definition.heal_amount())
+                            .map(|amount| (item.clone(), amount))
+                    })
+                    .min_by_key(|(_, amount)| *amount);
+                match healing {
+                    Some((item, amount)) => {
+                        use_item.send(UseItemEvent { user: player, item: item.clone(), target: None });
+                        encounter.log = format!("You use {} and recover {} health.", item.identifier(), amount);
+                    },
+                    None => {
+                        // Looking through an empty bag doesn't cost a turn
+                        encounter.log = "You have nothing to use.".to_string();
+                        continue;
+                    },
+                }
+            },
+            CombatAction::Defend => {
+                encounter.defending = true;
+                encounter.log = "You raise your guard.".to_string();
+            },
+            CombatAction::Flee => {
+                encounter.log = "You flee!".to_string();
+                encounter.outcome = Some(Outcome::Retreat);
+            },
+        }
+        encounter.end_turn();
+    }
+}
+
+fn enemy_turn(
+    mut encounter: ResMut<Encounter>,
+    mut player: Query<(&mut Health, Option<&GodMode>), With<Player>>,
+    enemies: Query<&EnemyKind>,
+    settings: Res<EnemySettings>,
+    time: Res<Time>,
+) {
+    if encounter.outcome.is_some() || encounter.current_turn() != Turn::Enemy {
+        return;
+    }
+    if !encounter.enemy_timer.tick(time.delta()).finished() {
+        return;
+    }
+
+    if let (Ok((mut health, god_mode)), Ok(kind)) = (player.get_single_mut(), enemies.get(encounter.enemy)) {
+        let attack = settings.stats(*kind).attack.damage;
+        let damage = match (god_mode, encounter.defending) {
+            (Some(_), _) => 0,
+            (None, true) => attack / 2,
+            (None, false) => attack,
+        };
+        health.damage(damage);
+        encounter.log = format!("The knight strikes you for {}.", damage);
+
+        // Getting knocked out sends the player back the same way fleeing does
+        if health.is_dead() {
+            health.current = 1;
+            encounter.log = "You are knocked back, barely standing.".to_string();
+            encounter.outcome = Some(Outcome::Retreat);
+        }
+    }
+    encounter.end_turn();
+}
+
+/// Writes the outcome back to the overworld once the combat menu has been dismissed
+fn finish_encounter(
+    mut commands: Commands,
+    encounter: Res<Encounter>,
+    mut player: Query<(Entity, &mut Transform), With<Player>>,
+    enemies: Query<&EnemyKind>,
+    mut quest_events: EventWriter<QuestEvent>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+) {
+    let outcome = match encounter.outcome {
+        Some(outcome) => outcome,
+        None => return,
+    };
+    // Leave the final log line on screen for at least a frame before it can be dismissed
+    if encounter.is_changed() {
+        return;
+    }
+    if !keys.just_pressed(KeyCode::Return) && !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    match outcome {
+        Outcome::Victory => {
+            if let Ok(kind) = enemies.get(encounter.enemy) {
+                quest_events.send(QuestEvent::EnemyDefeated(*kind));
+            }
+            commands.entity(encounter.enemy).despawn_recursive();
+        },
+        Outcome::Retreat => {
+            if let Ok((entity, mut transform)) = player.get_single_mut() {
+                transform.translation.x = encounter.retreat.x;
+                transform.translation.y = encounter.retreat.y;
+                commands
+                    .entity(entity)
+                    .insert(Invulnerable(Timer::new(RETREAT_INVULNERABILITY, false)));
+            }
+        },
+    }
+
+    commands.remove_resource::<Encounter>();
+    commands.insert_resource(NextState(GameState::InGame));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use crate::item::Item;
+    use super::*;
+
+    /// A knight fight on the player's turn, with each combat system in its own stage
+    struct Fight {
+        world: World,
+        player: Entity,
+        enemy: Entity,
+        player_turn: SystemStage,
+        enemy_turn: SystemStage,
+        finish: SystemStage,
+        now: Instant,
+    }
+
+    impl Fight {
+        fn new() -> Self {
+            let mut world = World::new();
+            world.insert_resource(EnemySettings::load());
+            world.insert_resource(ItemRegistry::load());
+            world.insert_resource(Time::default());
+            world.insert_resource(Input::<MouseButton>::default());
+            let mut keys = Input::<KeyCode>::default();
+            keys.press(KeyCode::Return);
+            world.insert_resource(keys);
+            world.init_resource::<Events<CombatAction>>();
+            world.init_resource::<Events<UseItemEvent>>();
+            world.init_resource::<Events<QuestEvent>>();
+
+            let player = world
+                .spawn()
+                .insert(Player)
+                .insert(Transform::default())
+                .insert(Health::new(100))
+                .insert(Inventory::default())
+                .id();
+            let enemy = world.spawn().insert(EnemyKind::Knight).insert(Health::new(30)).id();
+            world.insert_resource(Encounter {
+                enemy,
+                turns: VecDeque::from([Turn::Player, Turn::Enemy]),
+                defending: false,
+                log: String::new(),
+                outcome: None,
+                retreat: Vec2::new(24.0, 0.0),
+                enemy_timer: Timer::new(ENEMY_TURN_DELAY, false),
+            });
+
+            let now = Instant::now();
+            world.resource_mut::<Time>().update_with_instant(now);
+            Fight {
+                world,
+                player,
+                enemy,
+                player_turn: SystemStage::single_threaded().with_system(player_turn),
+                enemy_turn: SystemStage::single_threaded().with_system(enemy_turn),
+                finish: SystemStage::single_threaded().with_system(finish_encounter),
+                now,
+            }
+        }
+
+        fn choose(&mut self, action: CombatAction) {
+            self.world.resource_mut::<Events<CombatAction>>().send(action);
+            self.player_turn.run(&mut self.world);
+        }
+
+        /// Lets the enemy take its turn once its delay is over
+        fn wait_for_enemy(&mut self) {
+            self.now += ENEMY_TURN_DELAY;
+            self.world.resource_mut::<Time>().update_with_instant(self.now);
+            self.enemy_turn.run(&mut self.world);
+        }
+
+        /// Dismisses the combat menu, which needs the final log line to have been shown for a frame
+        fn dismiss(&mut self) {
+            self.finish.run(&mut self.world);
+            self.finish.run(&mut self.world);
+        }
+
+        fn encounter(&self) -> &Encounter {
+            self.world.resource::<Encounter>()
+        }
+
+        fn health(&self, entity: Entity) -> i32 {
+            self.world.get::<Health>(entity).unwrap().current
+        }
+
+        fn knight_damage(&self) -> i32 {
+            self.world.resource::<EnemySettings>().stats(EnemyKind::Knight).attack.damage
+        }
+    }
+
+    #[test]
+    fn turns_alternate_between_player_and_enemy() {
+        let mut fight = Fight::new();
+
+        fight.choose(CombatAction::Attack);
+        assert_eq!(fight.health(fight.enemy), 30 - AttackStats::default().damage);
+        assert_eq!(fight.encounter().current_turn(), Turn::Enemy);
+
+        // Acting out of turn does nothing
+        fight.choose(CombatAction::Attack);
+        assert_eq!(fight.health(fight.enemy), 30 - AttackStats::default().damage);
+
+        fight.wait_for_enemy();
+        assert_eq!(fight.health(fight.player), 100 - fight.knight_damage());
+        assert_eq!(fight.encounter().current_turn(), Turn::Player);
+    }
+
+    #[test]
+    fn items_are_used_through_their_effects() {
+        let mut fight = Fight::new();
+
+        // Nothing to use doesn't cost the turn
+        fight.choose(CombatAction::Item);
+        assert_eq!(fight.encounter().current_turn(), Turn::Player);
+
+        let mut inventory = fight.world.get_mut::<Inventory>(fight.player).unwrap();
+        inventory.add(Item::new("Health"), 1);
+        inventory.add(Item::new("Food"), 1);
+        fight.choose(CombatAction::Item);
+        assert_eq!(fight.encounter().current_turn(), Turn::Enemy);
+        let events = fight.world.resource::<Events<UseItemEvent>>();
+        let used: Vec<Item> = events.get_reader().iter(events).map(|event| event.item.clone()).collect();
+        assert_eq!(used, vec![Item::new("Food")]);
+    }
+
+    #[test]
+    fn defending_halves_the_enemy_damage() {
+        let mut fight = Fight::new();
+
+        fight.choose(CombatAction::Defend);
+        fight.wait_for_enemy();
+        assert_eq!(fight.health(fight.player), 100 - fight.knight_damage() / 2);
+    }
+
+    #[test]
+    fn fleeing_sends_the_player_back() {
+        let mut fight = Fight::new();
+
+        fight.choose(CombatAction::Flee);
+        assert_eq!(fight.encounter().outcome, Some(Outcome::Retreat));
+
+        fight.dismiss();
+        assert_eq!(fight.world.get::<Transform>(fight.player).unwrap().translation, Vec3::new(24.0, 0.0, 0.0));
+        assert!(fight.world.get::<Invulnerable>(fight.player).is_some());
+        assert!(fight.world.get_entity(fight.enemy).is_some());
+        assert!(fight.world.get_resource::<Encounter>().is_none());
+    }
+
+    #[test]
+    fn victory_despawns_the_enemy() {
+        let mut fight = Fight::new();
+        fight.world.get_mut::<Health>(fight.enemy).unwrap().current = 1;
+
+        fight.choose(CombatAction::Attack);
+        assert_eq!(fight.encounter().outcome, Some(Outcome::Victory));
+
+        fight.dismiss();
+        assert!(fight.world.get_entity(fight.enemy).is_none());
+        let events = fight.world.resource::<Events<QuestEvent>>();
+        assert_eq!(events.get_reader().iter(events).collect::<Vec<_>>(), vec![&QuestEvent::EnemyDefeated(EnemyKind::Knight)]);
+        assert!(fight.world.get_resource::<Encounter>().is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{EntityInstance, prelude::FieldValue};
+
+use crate::{combat::{spawn_hitbox, Team}, player::{Direction, Orientation, Player}};
+
+use super::settings::{EnemyKind, EnemySettings};
+
+const TELEGRAPH_COLOR: Color = Color::rgb(1.0, 0.85, 0.3);
+
+/// Whether an enemy attacks the player, read from the LDtk `Hostile` field
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Hostile(pub bool);
+
+pub fn hostile_from_field(entity_instance: &EntityInstance) -> Hostile {
+    let hostile = entity_instance
+        .field_instances
+        .iter()
+        .find(|f| f.identifier == *"Hostile")
+        .map_or(false, |f| matches!(f.value, FieldValue::Bool(true)));
+    Hostile(hostile)
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum AttackState {
+    #[default] Ready,
+    /// Telegraphing the attack, the hitbox spawns once the timer finishes
+    WindUp(Timer),
+    Cooldown(Timer),
+}
+
+#[derive(Clone, Debug, Default, Component)]
+pub struct MeleeAttack {
+    pub state: AttackState,
+}
+
+impl MeleeAttack {
+    /// Enemies stand still while winding up an attack
+    pub fn is_winding_up(&self) -> bool {
+        matches!(self.state, AttackState::WindUp(_))
+    }
+
+    /// Tint of the enemy's sprite when nothing else, like a hit flash, is showing
+    pub fn sprite_color(&self) -> Color {
+        if self.is_winding_up() {
+            TELEGRAPH_COLOR
+        } else {
+            Color::WHITE
+        }
+    }
+}
+
+/// Direction along the dominant axis from `from` to `to`
+fn facing(from: Vec2, to: Vec2) -> Direction {
+    let delta = to - from;
+    if delta.x.abs() > delta.y.abs() {
+        if delta.x > 0.0 { Direction::Right } else { Direction::Left }
+    } else if delta.y > 0.0 {
+        Direction::Up
+    } else {
+        Direction::Down
+    }
+}
+
+pub fn enemy_attack(
+    mut commands: Commands,
+    mut enemies: Query<(Entity, &GlobalTransform, &EnemyKind, &Hostile, &mut MeleeAttack, &mut Orientation, &mut TextureAtlasSprite), Without<Player>>,
+    player: Query<&GlobalTransform, With<Player>>,
+    settings: Res<EnemySettings>,
+    time: Res<Time>,
+) {
+    let player_position = match player.get_single() {
+        Ok(transform) => transform.translation().truncate(),
+        Err(_) => return,
+    };
+
+    enemies.for_each_mut(|(entity, transform, kind, hostile, mut melee, mut orientation, mut sprite)| {
+        let attack = settings.stats(*kind).attack;
+        let position = transform.translation().truncate();
+
+        match &mut melee.state {
+            AttackState::Ready => {
+                if hostile.0 && position.distance(player_position) <= attack.range {
+                    orientation.0 = facing(position, player_position);
+                    sprite.color = TELEGRAPH_COLOR;
+                    melee.state = AttackState::WindUp(Timer::new(Duration::from_millis(attack.wind_up_ms), false));
+                }
+            },
+            AttackState::WindUp(timer) => {
+                if timer.tick(time.delta()).finished() {
+                    sprite.color = Color::WHITE;
+                    spawn_hitbox(&mut commands, entity, transform.translation(), &orientation.0, &attack, Team::Player);
+                    melee.state = AttackState::Cooldown(Timer::new(Duration::from_millis(attack.cooldown_ms), false));
+                }
+            },
+            AttackState::Cooldown(timer) => {
+                if timer.tick(time.delta()).finished() {
+                    melee.state = AttackState::Ready;
+                }
+            },
+        }
+    })
+}
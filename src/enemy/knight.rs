@@ -1,9 +1,9 @@
-use bevy::{sprite::SpriteSheetBundle, prelude::{Component, Bundle}};
+use bevy::{sprite::{SpriteSheetBundle, TextureAtlas, TextureAtlasSprite}, prelude::{Component, Bundle, Handle, Transform, Vec2, Vec3, AssetServer}};
 use bevy_ecs_ldtk::LdtkEntity;
 
-use crate::{player::{Orientation, Direction, AnimationTimer, FrameTimer}, level::Collider};
+use crate::{player::{Orientation, Direction, AnimationTimer, FrameTimer}, level::Collider, depth::YSort, dialogue::{Dialogue, dialogue_from_field}};
 
-use super::Patrol;
+use super::{Patrol, settings::EnemyKind, attack::{Hostile, MeleeAttack, hostile_from_field}};
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
 pub struct Knight;
@@ -15,10 +15,43 @@ pub struct KnightBundle {
     pub sprite_sheet_bundle: SpriteSheetBundle,
     pub collider: Collider,
     pub knight: Knight,
+    pub enemy_kind: EnemyKind,
+    #[with(hostile_from_field)]
+    pub hostile: Hostile,
+    #[with(dialogue_from_field)]
+    pub dialogue: Dialogue,
+    pub melee_attack: MeleeAttack,
     pub direction: Direction,
     pub orientation: Orientation,
     pub animation_timer: AnimationTimer,
     pub frame_timer: FrameTimer,
+    pub y_sort: YSort,
     #[ldtk_entity]
     pub patrol: Patrol,
-}
\ No newline at end of file
+}
+
+impl KnightBundle {
+    /// Sprite sheet for knights spawned outside of LDtk, which gets its atlas from the entity's tile instead
+    pub fn texture_atlas(asset_server: &AssetServer) -> TextureAtlas {
+        TextureAtlas::from_grid(asset_server.load("sprites/knight.png"), Vec2::splat(16.0), 4, 4)
+    }
+
+    /// Hostile knight standing guard at `translation`, for knights spawned outside of LDtk
+    pub fn hostile(texture_atlas: Handle<TextureAtlas>, translation: Vec3) -> Self {
+        KnightBundle {
+            sprite_sheet_bundle: SpriteSheetBundle {
+                texture_atlas,
+                sprite: TextureAtlasSprite::new(0),
+                transform: Transform::from_translation(translation),
+                ..Default::default()
+            },
+            hostile: Hostile(true),
+            patrol: Patrol {
+                points: vec![translation.truncate()],
+                index: 0,
+                forward: true,
+            },
+            ..Default::default()
+        }
+    }
+}
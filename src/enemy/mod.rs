@@ -1,21 +1,51 @@
 use std::time::Duration;
 
-use bevy::{prelude::{Component, Vec2, Handle, Image, AssetServer, Assets, IVec2, Transform, Query, EventWriter, Plugin, App, CoreStage, SystemStage, Res}, sprite::TextureAtlas, time::Time};
+use bevy::{prelude::{Component, Vec2, Vec3, Handle, Image, AssetServer, Assets, IVec2, Transform, Query, EventReader, EventWriter, Plugin, App, CoreStage, SystemStage, Res, ResMut, Local, Commands, Entity, Added, Changed, With, Without, BuildChildren, DespawnRecursiveExt}, log::{debug, trace}, sprite::TextureAtlas, time::Time};
 use bevy_ecs_ldtk::{prelude::{LdtkEntity, LayerInstance, TilesetDefinition, FieldValue}, EntityInstance, utils::ldtk_pixel_coords_to_translation_pivoted};
 use iyes_loopless::{prelude::FixedTimestepStage, condition::{ConditionSet, IntoConditionalSystem}};
-use crate::{player::{Direction, FrameTimer}, misc::state::GameState};
+use crate::{player::{Direction, FrameTimer}, misc::{state::GameState, label::CombatLabel}, combat::{Health, Knockback, not_hit_stopped}, quest::QuestEvent};
+use attack::MeleeAttack;
+use knight::KnightBundle;
+use settings::{EnemyKind, EnemySettings};
 
+pub mod attack;
 pub mod knight;
+pub mod settings;
 
 const ENEMY_SPEED: f32 = 1.0/3.0;
 
 pub struct PatrolPointReached(Patrol);
 
+/// Sent to spawn a hostile enemy outside of LDtk, at `translation` within `level`
+pub struct SpawnEnemyEvent {
+    pub level: Entity,
+    pub kind: EnemyKind,
+    pub translation: Vec3,
+}
+
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
         app
+            .insert_resource(EnemySettings::load())
+            .add_event::<SpawnEnemyEvent>()
+            .add_system(spawn_enemies)
+            .add_system(
+                insert_enemy_health
+                    .run_in_state(GameState::InGame)
+            )
+            .add_system(
+                attack::enemy_attack
+                    .run_in_state(GameState::InGame)
+                    .run_if(not_hit_stopped)
+                    .before(CombatLabel::Hitbox)
+            )
+            .add_system(
+                despawn_dead_enemies
+                    .run_in_state(GameState::InGame)
+                    .after(CombatLabel::Damage)
+            )
             .add_system(
                 next_patrol
                     .run_in_state(GameState::InGame)
@@ -24,6 +54,7 @@ impl Plugin for EnemyPlugin {
             .add_system(
                 enemy_movement
                     .run_in_state(GameState::InGame)
+                    .run_if(not_hit_stopped)
                     .after("patrol")
             );
     }
@@ -32,6 +63,30 @@ impl Plugin for EnemyPlugin {
         std::any::type_name::<Self>()
     }
 }
+
+/// Spawns the enemies triggers and the console ask for as children of their level
+fn spawn_enemies(
+    mut commands: Commands,
+    mut events: EventReader<SpawnEnemyEvent>,
+    mut knight_atlas: Local<Option<Handle<TextureAtlas>>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in events.iter() {
+        let bundle = match event.kind {
+            EnemyKind::Knight => {
+                let texture_atlas = knight_atlas
+                    .get_or_insert_with(|| texture_atlases.add(KnightBundle::texture_atlas(&asset_server)))
+                    .clone();
+                KnightBundle::hostile(texture_atlas, event.translation)
+            },
+        };
+        commands.entity(event.level).with_children(|level| {
+            level.spawn_bundle(bundle);
+        });
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Default, Component)]
 pub struct Patrol {
     pub points: Vec<Vec2>,
@@ -56,12 +111,14 @@ impl LdtkEntity for Patrol {
             entity_instance.pivot,
         ));
 
+        // Without the field the enemy stands guard where it was placed,
+        // `report_malformed_entities` warns about it once the level has spawned
         let ldtk_patrol = entity_instance
             .field_instances
             .iter()
             .find(|f| f.identifier == *"Patrol")
-            .unwrap();
-        if let FieldValue::Points(ldtk_points) = &ldtk_patrol.value {
+            .map(|f| &f.value);
+        if let Some(FieldValue::Points(ldtk_points)) = ldtk_patrol {
             for ldtk_point in ldtk_points {
                 if let Some(ldtk_point) = ldtk_point {
                     // The +1 is necessary here due to the pivot of the entities in the sample
@@ -90,6 +147,30 @@ impl LdtkEntity for Patrol {
     }
 }
 
+fn insert_enemy_health(
+    mut commands: Commands,
+    enemies: Query<(Entity, &EnemyKind), Added<EnemyKind>>,
+    settings: Res<EnemySettings>,
+) {
+    for (entity, kind) in enemies.iter() {
+        commands.entity(entity).insert(Health::new(settings.stats(*kind).health));
+    }
+}
+
+fn despawn_dead_enemies(
+    mut commands: Commands,
+    enemies: Query<(Entity, &Health, &EnemyKind), Changed<Health>>,
+    mut quest_events: EventWriter<QuestEvent>,
+) {
+    for (entity, health, kind) in enemies.iter() {
+        if health.is_dead() {
+            debug!(?kind, ?entity, "enemy defeated");
+            quest_events.send(QuestEvent::EnemyDefeated(*kind));
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
 fn next_patrol(
     mut query: Query<(&mut Patrol, &mut Direction, &mut Transform)>,
 ) {
@@ -108,6 +189,7 @@ fn next_patrol(
                 } else {
                     patrol.index -= 1;
                 }
+                trace!(index = patrol.index, forward = patrol.forward, "patrol point reached");
             } 
             if patrol.points[patrol.index][0] > transform.translation.x {
                 *direction = Direction::Right;
@@ -123,10 +205,13 @@ fn next_patrol(
 }
 
 fn enemy_movement(
-    mut enemy: Query<(&Direction, &mut Transform, &mut FrameTimer, &Patrol)>,
+    mut enemy: Query<(&Direction, &mut Transform, &mut FrameTimer, &Patrol, Option<&MeleeAttack>), Without<Knockback>>,
     time: Res<Time>,
 ) {
-    for (direction, mut transform, mut frame_timer, patrol) in enemy.iter_mut() {
+    for (direction, mut transform, mut frame_timer, patrol, melee) in enemy.iter_mut() {
+        if melee.map_or(false, |melee| melee.is_winding_up()) {
+            continue;
+        }
         if frame_timer.tick(time.delta()).just_finished() {
             match direction {
                 Direction::Right => {
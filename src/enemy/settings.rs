@@ -0,0 +1,73 @@
+use std::{collections::HashMap, fs};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::combat::{AttackStats, HitFeel};
+
+const ENEMY_SETTINGS_PATH: &str = "settings/enemy.json";
+
+/// Every kind of enemy, named the same way as in `settings/enemy.json`
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash, Component, Deserialize)]
+pub enum EnemyKind {
+    #[default] Knight,
+}
+
+impl EnemyKind {
+    /// Name of the matching LDtk entity
+    pub fn identifier(&self) -> &'static str {
+        match self {
+            EnemyKind::Knight => "Knight",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct EnemyStats {
+    pub health: i32,
+    pub attack: AttackStats,
+    #[serde(flatten)]
+    pub hit_feel: HitFeel,
+}
+
+impl Default for EnemyStats {
+    fn default() -> Self {
+        EnemyStats {
+            health: 30,
+            attack: AttackStats::default(),
+            hit_feel: HitFeel::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deref)]
+pub struct EnemySettings(HashMap<EnemyKind, EnemyStats>);
+
+impl EnemySettings {
+    pub fn read() -> Result<Self, String> {
+        let settings = fs::read_to_string(ENEMY_SETTINGS_PATH)
+            .map_err(|error| format!("Failed to open {}: {}", ENEMY_SETTINGS_PATH, error))?;
+        serde_json::from_str(&settings)
+            .map(EnemySettings)
+            .map_err(|error| format!("Failed to parse {}: {}", ENEMY_SETTINGS_PATH, error))
+    }
+
+    /// Falls back to no settings at all, so every enemy gets the default stats, when the file can't be read
+    pub fn load() -> Self {
+        EnemySettings::read().unwrap_or_else(|error| {
+            warn!("{}, using the default enemy stats", error);
+            EnemySettings::default()
+        })
+    }
+
+    pub fn stats(&self, kind: EnemyKind) -> EnemyStats {
+        self.0.get(&kind).copied().unwrap_or_else(|| {
+            warn!("{:?} is missing from {}, using the default stats", kind, ENEMY_SETTINGS_PATH);
+            EnemyStats::default()
+        })
+    }
+
+    /// Hit feel of an enemy, or the player's when `kind` is `None`
+    pub fn hit_feel(&self, kind: Option<&EnemyKind>) -> HitFeel {
+        kind.map_or_else(HitFeel::default, |kind| self.stats(*kind).hit_feel)
+    }
+}
@@ -0,0 +1,324 @@
+use std::{collections::BTreeMap, time::Duration};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{misc::state::GameState, player::Player, level::{COLLISIONS_LAYER, terrain::{TerrainMap, sight_blocked}}, save::SaveData};
+
+/// Level field turning fog of war on for a level
+const FOG_FIELD: &str = "FogOfWar";
+/// How far the player sees, in tiles
+const SIGHT_RADIUS: i32 = 6;
+/// Above the lighting overlay, unexplored tiles stay black no matter how they're lit
+const FOG_Z: f32 = 101.0;
+/// Exploring reveals tiles nearly every step, so the save only picks them up this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct FogPlugin;
+
+impl Plugin for FogPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ExploredMap>()
+            .add_enter_system(GameState::LoadGame, restore_explored)
+            .add_exit_system(GameState::InGame, flush_explored)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::InGame)
+                    .with_system(spawn_fog)
+                    .with_system(reveal_tiles)
+                    .with_system(save_explored)
+                    .into()
+            );
+    }
+}
+
+/// Which tiles of a level the player has seen, in LDtk grid coordinates with y going up
+/// Saved as rows of `#` for explored and `.` for unexplored tiles, top row first
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<String>", into = "Vec<String>")]
+pub struct VisibilityGrid {
+    pub columns: i32,
+    pub rows: i32,
+    revealed: Vec<bool>,
+}
+
+impl VisibilityGrid {
+    pub fn new(columns: i32, rows: i32) -> Self {
+        VisibilityGrid {
+            columns,
+            rows,
+            revealed: vec![false; (columns * rows).max(0) as usize],
+        }
+    }
+
+    pub fn contains(&self, coords: IVec2) -> bool {
+        coords.x >= 0 && coords.y >= 0 && coords.x < self.columns && coords.y < self.rows
+    }
+
+    fn index(&self, coords: IVec2) -> usize {
+        (coords.y * self.columns + coords.x) as usize
+    }
+
+    pub fn is_revealed(&self, coords: IVec2) -> bool {
+        self.contains(coords) && self.revealed[self.index(coords)]
+    }
+
+    /// Returns whether the tile was unexplored until now
+    pub fn reveal(&mut self, coords: IVec2) -> bool {
+        if !self.contains(coords) || self.is_revealed(coords) {
+            return false;
+        }
+        let index = self.index(coords);
+        self.revealed[index] = true;
+        true
+    }
+}
+
+impl From<Vec<String>> for VisibilityGrid {
+    fn from(rows: Vec<String>) -> Self {
+        let columns = rows.first().map_or(0, |row| row.chars().count()) as i32;
+        let mut grid = VisibilityGrid::new(columns, rows.len() as i32);
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, tile) in row.chars().enumerate() {
+                if tile == '#' {
+                    grid.reveal(IVec2::new(x as i32, y as i32));
+                }
+            }
+        }
+        grid
+    }
+}
+
+impl From<VisibilityGrid> for Vec<String> {
+    fn from(grid: VisibilityGrid) -> Self {
+        (0..grid.rows)
+            .rev()
+            .map(|y| {
+                (0..grid.columns)
+                    .map(|x| if grid.is_revealed(IVec2::new(x, y)) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Explored tiles of every level with fog of war, keyed by LDtk level iid
+#[derive(Clone, Debug, Default, Deref, DerefMut)]
+pub struct ExploredMap(pub BTreeMap<String, VisibilityGrid>);
+
+/// Black tiles covering the unexplored parts of a level, spawned as a child of the level
+#[derive(Clone, Debug, Component)]
+pub struct Fog {
+    pub level_iid: String,
+    pub grid_size: f32,
+    /// Cover of every tile, `None` once it's been revealed
+    tiles: Vec<Option<Entity>>,
+}
+
+fn restore_explored(
+    mut explored: ResMut<ExploredMap>,
+    save: Res<SaveData>,
+) {
+    explored.0 = save.explored.clone();
+}
+
+fn spawn_fog(
+    mut commands: Commands,
+    mut level_events: EventReader<LevelEvent>,
+    mut explored: ResMut<ExploredMap>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    levels: Res<Assets<LdtkLevel>>,
+) {
+    for event in level_events.iter() {
+        let iid = match event {
+            LevelEvent::Spawned(iid) => iid,
+            _ => continue,
+        };
+        let found = level_query
+            .iter()
+            .filter_map(|(entity, handle)| levels.get(handle).map(|level| (entity, level)))
+            .find(|(_, level)| level.level.iid == *iid);
+        let (level_entity, level) = match found {
+            Some(found) => found,
+            None => continue,
+        };
+
+        let fog_of_war = level
+            .level
+            .field_instances
+            .iter()
+            .find(|f| f.identifier == FOG_FIELD)
+            .map_or(false, |f| matches!(f.value, FieldValue::Bool(true)));
+        let layer = level
+            .level
+            .layer_instances
+            .as_ref()
+            .and_then(|layers| layers.iter().find(|layer| layer.identifier == COLLISIONS_LAYER));
+        let layer = match layer {
+            Some(layer) if fog_of_war => layer,
+            _ => continue,
+        };
+
+        let grid = explored
+            .entry(iid.clone())
+            .or_insert_with(|| VisibilityGrid::new(layer.c_wid, layer.c_hei));
+        // The level was resized in LDtk since it was explored, start over
+        if grid.columns != layer.c_wid || grid.rows != layer.c_hei {
+            *grid = VisibilityGrid::new(layer.c_wid, layer.c_hei);
+        }
+
+        let grid_size = layer.grid_size as f32;
+        let mut fog = Fog {
+            level_iid: iid.clone(),
+            grid_size,
+            tiles: Vec::with_capacity(grid.revealed.len()),
+        };
+
+        commands.entity(level_entity).with_children(|level| {
+            level
+                .spawn_bundle(SpatialBundle {
+                    transform: Transform::from_xyz(0.0, 0.0, FOG_Z),
+                    ..Default::default()
+                })
+                .with_children(|overlay| {
+                    for y in 0..grid.rows {
+                        for x in 0..grid.columns {
+                            if grid.is_revealed(IVec2::new(x, y)) {
+                                fog.tiles.push(None);
+                                continue;
+                            }
+
+                            let center = (Vec2::new(x as f32, y as f32) + 0.5) * grid_size;
+                            let tile = overlay
+                                .spawn_bundle(SpriteBundle {
+                                    sprite: Sprite {
+                                        color: Color::BLACK,
+                                        custom_size: Some(Vec2::splat(grid_size)),
+                                        ..Default::default()
+                                    },
+                                    transform: Transform::from_translation(center.extend(0.0)),
+                                    ..Default::default()
+                                })
+                                .id();
+                            fog.tiles.push(Some(tile));
+                        }
+                    }
+                })
+                .insert(fog);
+        });
+    }
+}
+
+/// Reveals every tile within sight of the player that no wall hides
+fn reveal_tiles(
+    mut commands: Commands,
+    mut explored: ResMut<ExploredMap>,
+    mut fogs: Query<(&mut Fog, &GlobalTransform)>,
+    player: Query<&GlobalTransform, With<Player>>,
+    terrain_map: Res<TerrainMap>,
+) {
+    let eye = match player.get_single() {
+        Ok(transform) => transform.translation().truncate(),
+        Err(_) => return,
+    };
+    let is_solid = |point: Vec2| terrain_map.is_solid(point);
+
+    for (mut fog, transform) in fogs.iter_mut() {
+        let grid = match explored.get(&fog.level_iid) {
+            Some(grid) => grid,
+            None => continue,
+        };
+        let origin = transform.translation().truncate();
+        let standing_on = ((eye - origin) / fog.grid_size).floor().as_ivec2();
+
+        let mut seen = Vec::new();
+        for y in -SIGHT_RADIUS..=SIGHT_RADIUS {
+            for x in -SIGHT_RADIUS..=SIGHT_RADIUS {
+                let coords = standing_on + IVec2::new(x, y);
+                if x * x + y * y > SIGHT_RADIUS * SIGHT_RADIUS
+                    || !grid.contains(coords)
+                    || grid.is_revealed(coords) {
+                    continue;
+                }
+
+                let center = origin + (coords.as_vec2() + 0.5) * fog.grid_size;
+                if !sight_blocked(eye, center, &is_solid) {
+                    seen.push(coords);
+                }
+            }
+        }
+
+        // Only borrow the map mutably when something new was seen, so it isn't saved every frame
+        if seen.is_empty() {
+            continue;
+        }
+        let grid = explored.get_mut(&fog.level_iid).unwrap();
+        for coords in seen {
+            grid.reveal(coords);
+            let index = grid.index(coords);
+            if let Some(tile) = fog.tiles[index].take() {
+                commands.entity(tile).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn save_explored(
+    explored: Res<ExploredMap>,
+    mut save: ResMut<SaveData>,
+    mut unsaved: Local<bool>,
+    mut since_save: Local<Duration>,
+    time: Res<Time>,
+) {
+    if explored.is_changed() && !explored.is_added() {
+        *unsaved = true;
+    }
+
+    *since_save += time.delta();
+    if *unsaved && *since_save >= SAVE_INTERVAL {
+        if save.explored != explored.0 {
+            save.explored = explored.0.clone();
+        }
+        *unsaved = false;
+        *since_save = Duration::ZERO;
+    }
+}
+
+/// Saves what was explored since the last interval when leaving the game for a menu
+fn flush_explored(
+    explored: Res<ExploredMap>,
+    mut save: ResMut<SaveData>,
+) {
+    if save.explored != explored.0 {
+        save.explored = explored.0.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reveal_ignores_tiles_outside_the_grid() {
+        let mut grid = VisibilityGrid::new(3, 2);
+
+        assert!(grid.reveal(IVec2::new(2, 1)));
+        assert!(!grid.reveal(IVec2::new(2, 1)));
+        assert!(!grid.reveal(IVec2::new(3, 0)));
+        assert!(!grid.reveal(IVec2::new(-1, 0)));
+        assert!(grid.is_revealed(IVec2::new(2, 1)));
+        assert!(!grid.is_revealed(IVec2::new(0, 0)));
+    }
+
+    #[test]
+    fn grid_is_saved_top_row_first() {
+        let mut grid = VisibilityGrid::new(3, 2);
+        grid.reveal(IVec2::new(0, 0));
+        grid.reveal(IVec2::new(2, 1));
+
+        let json = serde_json::to_string(&grid).unwrap();
+        assert_eq!(json, r#"["..#","#.."]"#);
+        assert_eq!(serde_json::from_str::<VisibilityGrid>(&json).unwrap(), grid);
+    }
+}
@@ -0,0 +1,235 @@
+use bevy::{input::mouse::{MouseScrollUnit, MouseWheel}, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{
+    misc::state::GameState,
+    player::{camera_on_player, NoClip, NoClipEvent, Player},
+    map::{LevelMap, MapCanvas},
+    ui::{menu_assets::MenuAssets, style::sized_text_bundle},
+    PlayerCamera,
+    SPRITE_SCALE,
+};
+
+const FREE_CAMERA_KEY: KeyCode = KeyCode::F4;
+const NOCLIP_KEY: KeyCode = KeyCode::F2;
+/// Screen pixels per second the camera pans at, whatever the zoom
+const PAN_SPEED: f32 = 600.0;
+/// Zoom change per scrolled line
+const ZOOM_STEP: f32 = 0.1;
+/// How far in the camera can zoom, relative to the gameplay zoom
+const MIN_ZOOM: f32 = 0.5;
+/// Below the level layers, so levels overlapping in the LDtk world don't hide the current one
+const OTHER_LEVELS_Z: f32 = -1.0;
+const OTHER_LEVELS_TINT: Color = Color::rgba(1.0, 1.0, 1.0, 0.6);
+const HINT_FONT_SIZE: f32 = 14.0;
+
+/// Debug modes for getting around levels: noclip with F2, and a camera detached from the player
+/// with F4 that pans with WASD or the arrow keys and zooms with the mouse wheel while the game is paused
+pub struct FreeCameraPlugin;
+
+impl Plugin for FreeCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(
+                toggle_player_noclip
+                    .run_in_state(GameState::InGame)
+            )
+            .add_system(
+                enter_free_camera
+                    .run_in_state(GameState::InGame)
+            )
+            .add_enter_system(GameState::FreeCamera, spawn_world_overview)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::FreeCamera)
+                    .with_system(move_free_camera)
+                    .with_system(exit_free_camera)
+                    .into()
+            )
+            .add_exit_system(GameState::FreeCamera, despawn_world_overview)
+            .add_exit_system(GameState::FreeCamera, reset_camera_zoom)
+            .add_exit_system(GameState::FreeCamera, camera_on_player);
+    }
+}
+
+/// Area of the LDtk world the free camera can move over, relative to the current level
+pub struct FreeCameraBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+#[derive(Component)]
+struct WorldOverview;
+
+fn toggle_player_noclip(
+    player: Query<(Entity, Option<&NoClip>), With<Player>>,
+    mut noclip_events: EventWriter<NoClipEvent>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if !keys.just_pressed(NOCLIP_KEY) {
+        return;
+    }
+
+    for (target, noclip) in player.iter() {
+        noclip_events.send(NoClipEvent { target, enabled: noclip.is_none() });
+    }
+}
+
+fn enter_free_camera(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(FREE_CAMERA_KEY) {
+        commands.insert_resource(NextState(GameState::FreeCamera));
+    }
+}
+
+fn exit_free_camera(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(FREE_CAMERA_KEY) || keys.just_pressed(KeyCode::Escape) {
+        commands.insert_resource(NextState(GameState::InGame));
+    }
+}
+
+/// Only the current level is spawned, so every other level of the LDtk world is drawn as its map,
+/// placed where it sits in the world relative to the current one
+fn spawn_world_overview(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    worlds: Query<&Handle<LdtkAsset>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    level_query: Query<(&Handle<LdtkLevel>, &GlobalTransform)>,
+    levels: Res<Assets<LdtkLevel>>,
+    menu_assets: Res<MenuAssets>,
+) {
+    let project = worlds.iter().find_map(|handle| ldtk_assets.get(handle)).map(|ldtk_asset| &ldtk_asset.project);
+    let current = level_query
+        .iter()
+        .find_map(|(handle, transform)| levels.get(handle).map(|level| (level, transform)))
+        .and_then(|(level, transform)| LevelMap::from_level(&level.level).map(|map| (map, transform.translation().truncate())));
+    let (project, (current, origin)) = match (project, current) {
+        (Some(project), Some(current)) => (project, current),
+        _ => return,
+    };
+
+    let grid_size = current.grid_size as f32;
+    // The current level's top-left corner, where LDtk world coordinates start counting down from
+    let top_left = origin + Vec2::new(0.0, current.rows as f32 * grid_size);
+    let mut min = origin;
+    let mut max = top_left + Vec2::new(current.columns as f32 * grid_size, 0.0);
+
+    for map in project.levels.iter().filter_map(LevelMap::from_level) {
+        if map.iid == current.iid {
+            continue;
+        }
+
+        let offset = (map.world_origin - current.world_origin).as_vec2() * grid_size;
+        let size = Vec2::new(map.columns as f32, map.rows as f32) * grid_size;
+        let corner = top_left + Vec2::new(offset.x, -offset.y);
+        min = min.min(corner - Vec2::new(0.0, size.y));
+        max = max.max(corner + Vec2::new(size.x, 0.0));
+
+        let mut canvas = MapCanvas::new(map.columns, map.rows);
+        map.paint(&mut canvas, IVec2::ZERO, None);
+        commands
+            .spawn_bundle(SpriteBundle {
+                texture: images.add(canvas.into_image()),
+                sprite: Sprite {
+                    color: OTHER_LEVELS_TINT,
+                    custom_size: Some(size),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation((corner + Vec2::new(size.x, -size.y) / 2.0).extend(OTHER_LEVELS_Z)),
+                ..Default::default()
+            })
+            .insert(WorldOverview);
+    }
+    commands.insert_resource(FreeCameraBounds { min, max });
+
+    let mut hint = sized_text_bundle(
+        "Free camera: WASD to pan, mouse wheel to zoom, F4 to return",
+        HINT_FONT_SIZE,
+        Color::WHITE,
+        0.0,
+        &menu_assets,
+    );
+    hint.style.position_type = PositionType::Absolute;
+    hint.style.position = UiRect {
+        left: Val::Px(12.0),
+        top: Val::Px(12.0),
+        ..Default::default()
+    };
+    commands.spawn_bundle(hint).insert(WorldOverview);
+}
+
+fn move_free_camera(
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<PlayerCamera>>,
+    mut wheel: EventReader<MouseWheel>,
+    keys: Res<Input<KeyCode>>,
+    bounds: Option<Res<FreeCameraBounds>>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+) {
+    let (mut transform, mut projection) = match camera.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+
+    let mut direction = Vec2::ZERO;
+    if keys.any_pressed([KeyCode::W, KeyCode::Up]) {
+        direction.y += 1.0;
+    }
+    if keys.any_pressed([KeyCode::S, KeyCode::Down]) {
+        direction.y -= 1.0;
+    }
+    if keys.any_pressed([KeyCode::D, KeyCode::Right]) {
+        direction.x += 1.0;
+    }
+    if keys.any_pressed([KeyCode::A, KeyCode::Left]) {
+        direction.x -= 1.0;
+    }
+    let pan = direction.normalize_or_zero() * PAN_SPEED * projection.scale * time.delta_seconds();
+    transform.translation += pan.extend(0.0);
+
+    let lines: f32 = wheel
+        .iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 100.0,
+        })
+        .sum();
+    let mut scale = projection.scale * (1.0 - lines * ZOOM_STEP);
+
+    if let Some(bounds) = bounds {
+        // Zoom out no further than what fits the whole world in the window
+        let window = windows.primary();
+        let world = bounds.max - bounds.min;
+        let max_scale = (world.x / window.width()).max(world.y / window.height()).max(1.0 / SPRITE_SCALE);
+        scale = scale.clamp(MIN_ZOOM / SPRITE_SCALE, max_scale);
+
+        let position = transform.translation.truncate().clamp(bounds.min, bounds.max);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+    if scale != projection.scale {
+        projection.scale = scale;
+    }
+}
+
+fn despawn_world_overview(
+    mut commands: Commands,
+    overview: Query<Entity, With<WorldOverview>>,
+) {
+    overview.for_each(|entity| commands.entity(entity).despawn_recursive());
+    commands.remove_resource::<FreeCameraBounds>();
+}
+
+fn reset_camera_zoom(
+    mut camera: Query<&mut OrthographicProjection, With<PlayerCamera>>,
+) {
+    for mut projection in camera.iter_mut() {
+        projection.scale = 1.0 / SPRITE_SCALE;
+    }
+}
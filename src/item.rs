@@ -0,0 +1,434 @@
+use std::{collections::{BTreeMap, HashMap}, fs, time::Duration};
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+use crate::{misc::state::GameState, combat::Health, projectile::Ammo, quest::QuestEvent};
+
+const ITEM_SETTINGS_PATH: &str = "settings/items.json";
+
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ItemRegistry::load())
+            .add_event::<UseItemEvent>()
+            .add_event::<UnlockEvent>()
+            .add_event::<PowerEvent>()
+            .add_event::<CollectItemEvent>()
+            // Not tied to a state, so items given while a menu is open aren't lost
+            // and items used from the combat menu take effect
+            .add_system(collect_items)
+            .add_system(apply_item_effects)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::InGame)
+                    .with_system(cycle_equipped_item)
+                    .with_system(clear_empty_equipped_item)
+                    .with_system(use_equipped_item)
+                    .with_system(tick_power_buff)
+                    .into()
+            );
+    }
+}
+
+/// A value of the LDtk `Item` enum
+/// Items are identified by name so new ones only need an entry in `settings/items.json`
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct Item(pub String);
+
+impl Item {
+    pub fn new(identifier: &str) -> Item {
+        Item(identifier.to_string())
+    }
+
+    pub fn identifier(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "effect")]
+pub enum ItemEffect {
+    Heal { amount: i32 },
+    Ammo { amount: i32 },
+    /// Temporarily multiplies the damage the user deals
+    Buff { damage_multiplier: f32, duration_ms: u64 },
+    /// Powers devices, used by interacting with a device while holding the item
+    Power,
+    /// Opens doors whose `lockedWith` field names this item
+    Unlock,
+    /// Only spent through crafting
+    Currency,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ItemDefinition {
+    /// Whether using the item takes one out of the inventory
+    #[serde(default = "consumed_by_default")]
+    pub consumed: bool,
+    pub effects: Vec<ItemEffect>,
+}
+
+fn consumed_by_default() -> bool {
+    true
+}
+
+impl ItemDefinition {
+    /// Items whose effects only apply in context, such as keys on doors, can't be used on their own
+    pub fn is_usable(&self) -> bool {
+        self.effects.iter().any(|effect| {
+            matches!(effect, ItemEffect::Heal { .. } | ItemEffect::Ammo { .. } | ItemEffect::Buff { .. })
+        })
+    }
+
+    pub fn heal_amount(&self) -> Option<i32> {
+        self.effects.iter().find_map(|effect| match effect {
+            ItemEffect::Heal { amount } => Some(*amount),
+            _ => None,
+        })
+    }
+
+    pub fn has_effect(&self, predicate: impl Fn(&ItemEffect) -> bool) -> bool {
+        self.effects.iter().any(predicate)
+    }
+}
+
+/// Every item the game knows about, loaded from `settings/items.json`
+#[derive(Clone, Debug, Default, Deref)]
+pub struct ItemRegistry(HashMap<Item, ItemDefinition>);
+
+impl ItemRegistry {
+    /// Reads `settings/items.json`, for tools that have to stop when it's broken
+    pub fn read() -> Result<Self, String> {
+        let settings = fs::read_to_string(ITEM_SETTINGS_PATH)
+            .map_err(|error| format!("Failed to open {}: {}", ITEM_SETTINGS_PATH, error))?;
+        serde_json::from_str(&settings)
+            .map(ItemRegistry)
+            .map_err(|error| format!("Failed to parse {}: {}", ITEM_SETTINGS_PATH, error))
+    }
+
+    /// Falls back to an empty registry, where no item does anything, when the settings can't be read
+    pub fn load() -> Self {
+        ItemRegistry::read().unwrap_or_else(|error| {
+            warn!("{}, items will have no effect", error);
+            ItemRegistry::default()
+        })
+    }
+}
+
+/// Sent when `collector` picks up items, from containers or the console
+pub struct CollectItemEvent {
+    pub collector: Entity,
+    pub item: Item,
+    pub count: u32,
+}
+
+/// Sent to use one of `user`'s items, the effects are applied by `apply_item_effects`
+pub struct UseItemEvent {
+    pub user: Entity,
+    pub item: Item,
+    /// What the item is used on, items like keys only do something with a target
+    pub target: Option<Entity>,
+}
+
+/// Sent when an item with the `Unlock` effect is used on something, the door decides whether it fits
+pub struct UnlockEvent {
+    pub target: Entity,
+    pub key: Item,
+}
+
+/// Sent when an item with the `Power` effect is used on something
+pub struct PowerEvent {
+    pub target: Entity,
+}
+
+#[derive(Clone, Debug, Default, Component)]
+pub struct Inventory {
+    pub items: BTreeMap<Item, u32>,
+}
+
+impl Inventory {
+    pub fn count(&self, item: &Item) -> u32 {
+        self.items.get(item).copied().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, item: Item, amount: u32) {
+        *self.items.entry(item).or_insert(0) += amount;
+    }
+
+    /// Removes `amount` of `item`, returns false and leaves the inventory untouched if there aren't enough
+    pub fn remove(&mut self, item: &Item, amount: u32) -> bool {
+        match self.items.get_mut(item) {
+            Some(count) if *count >= amount => {
+                *count -= amount;
+                if *count == 0 {
+                    self.items.remove(item);
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Item in the quick slot, cycled through the inventory with Q and used with R
+#[derive(Clone, Debug, Default, Component)]
+pub struct EquippedItem(pub Option<Item>);
+
+/// Damage multiplier granted by a `Buff` item
+#[derive(Clone, Debug, Component)]
+pub struct PowerBuff {
+    pub damage_multiplier: f32,
+    pub timer: Timer,
+}
+
+impl PowerBuff {
+    pub fn apply(&self, damage: i32) -> i32 {
+        (damage as f32 * self.damage_multiplier).round() as i32
+    }
+}
+
+fn cycle_equipped_item(
+    mut player: Query<(&Inventory, &mut EquippedItem)>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if !keys.just_pressed(KeyCode::Q) {
+        return;
+    }
+
+    for (inventory, mut equipped) in player.iter_mut() {
+        let mut owned = inventory.items.keys().cloned();
+        equipped.0 = match &equipped.0 {
+            Some(current) => owned
+                .clone()
+                .find(|item| item > current)
+                .or_else(|| owned.next()),
+            None => owned.next(),
+        };
+    }
+}
+
+/// Empties the quick slot once the equipped item runs out
+fn clear_empty_equipped_item(
+    mut player: Query<(&Inventory, &mut EquippedItem), Changed<Inventory>>,
+) {
+    for (inventory, mut equipped) in player.iter_mut() {
+        let empty = equipped.0.as_ref().map_or(false, |item| inventory.count(item) == 0);
+        if empty {
+            equipped.0 = None;
+        }
+    }
+}
+
+fn use_equipped_item(
+    player: Query<(Entity, &EquippedItem)>,
+    keys: Res<Input<KeyCode>>,
+    mut use_item: EventWriter<UseItemEvent>,
+) {
+    if !keys.just_pressed(KeyCode::R) {
+        return;
+    }
+
+    for (entity, equipped) in player.iter() {
+        if let Some(item) = &equipped.0 {
+            use_item.send(UseItemEvent { user: entity, item: item.clone(), target: None });
+        }
+    }
+}
+
+/// Adds collected items to the inventory, each one counts towards quests
+fn collect_items(
+    mut events: EventReader<CollectItemEvent>,
+    mut inventories: Query<&mut Inventory>,
+    mut quest_events: EventWriter<QuestEvent>,
+) {
+    for event in events.iter() {
+        if let Ok(mut inventory) = inventories.get_mut(event.collector) {
+            for _ in 0..event.count {
+                quest_events.send(QuestEvent::ItemCollected(event.item.clone()));
+            }
+            inventory.add(event.item.clone(), event.count);
+        }
+    }
+}
+
+fn apply_item_effects(
+    mut commands: Commands,
+    mut events: EventReader<UseItemEvent>,
+    mut unlock_events: EventWriter<UnlockEvent>,
+    mut power_events: EventWriter<PowerEvent>,
+    mut users: Query<(&mut Inventory, Option<&mut Health>, Option<&mut Ammo>)>,
+    registry: Res<ItemRegistry>,
+) {
+    for event in events.iter() {
+        let definition = match registry.get(&event.item) {
+            Some(definition) if definition.is_usable() || event.target.is_some() => definition,
+            _ => continue,
+        };
+        let (mut inventory, mut health, mut ammo) = match users.get_mut(event.user) {
+            Ok(user) => user,
+            Err(_) => continue,
+        };
+        if inventory.count(&event.item) == 0 {
+            continue;
+        }
+
+        for effect in &definition.effects {
+            match effect {
+                ItemEffect::Heal { amount } => {
+                    if let Some(health) = health.as_mut() {
+                        health.heal(*amount);
+                    }
+                },
+                ItemEffect::Ammo { amount } => {
+                    if let Some(ammo) = ammo.as_mut() {
+                        ammo.current = (ammo.current + amount).min(ammo.max);
+                    }
+                },
+                ItemEffect::Buff { damage_multiplier, duration_ms } => {
+                    commands.entity(event.user).insert(PowerBuff {
+                        damage_multiplier: *damage_multiplier,
+                        timer: Timer::new(Duration::from_millis(*duration_ms), false),
+                    });
+                },
+                ItemEffect::Power => {
+                    if let Some(target) = event.target {
+                        power_events.send(PowerEvent { target });
+                    }
+                },
+                ItemEffect::Unlock => {
+                    if let Some(target) = event.target {
+                        unlock_events.send(UnlockEvent { target, key: event.item.clone() });
+                    }
+                },
+                ItemEffect::Currency => (),
+            }
+        }
+
+        if definition.consumed {
+            inventory.remove(&event.item, 1);
+        }
+    }
+}
+
+fn tick_power_buff(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut PowerBuff)>,
+    time: Res<Time>,
+) {
+    query.for_each_mut(|(entity, mut buff)| {
+        if buff.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<PowerBuff>();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory(items: &[(&str, u32)]) -> Inventory {
+        let mut inventory = Inventory::default();
+        for (item, count) in items {
+            inventory.add(Item::new(item), *count);
+        }
+        inventory
+    }
+
+    /// A world holding a wounded user with `items`, ready to run `apply_item_effects`
+    fn user_with(items: &[(&str, u32)]) -> (World, Entity) {
+        let mut world = World::new();
+        world.insert_resource(ItemRegistry::read().unwrap());
+        world.init_resource::<Events<UseItemEvent>>();
+        world.init_resource::<Events<UnlockEvent>>();
+        world.init_resource::<Events<PowerEvent>>();
+        let user = world
+            .spawn()
+            .insert(inventory(items))
+            .insert(Health { current: 10, max: 100 })
+            .id();
+        (world, user)
+    }
+
+    fn use_item(world: &mut World, stage: &mut SystemStage, user: Entity, item: &str, target: Option<Entity>) {
+        world.resource_mut::<Events<UseItemEvent>>().send(UseItemEvent { user, item: Item::new(item), target });
+        stage.run(world);
+    }
+
+    #[test]
+    fn inventory_counts_what_was_added_and_removed() {
+        let mut inventory = inventory(&[("Scraps", 3), ("Food", 1)]);
+        inventory.add(Item::new("Scraps"), 2);
+        assert_eq!(inventory.count(&Item::new("Scraps")), 5);
+
+        assert!(inventory.remove(&Item::new("Scraps"), 4));
+        assert_eq!(inventory.count(&Item::new("Scraps")), 1);
+
+        // Not enough left, so nothing is taken
+        assert!(!inventory.remove(&Item::new("Food"), 2));
+        assert_eq!(inventory.count(&Item::new("Food")), 1);
+
+        // Running out removes the entry, so empty items aren't listed
+        assert!(inventory.remove(&Item::new("Food"), 1));
+        assert!(!inventory.items.contains_key(&Item::new("Food")));
+        assert_eq!(inventory.count(&Item::new("Battery")), 0);
+    }
+
+    #[test]
+    fn registry_reads_effects_and_defaults_to_consumed() {
+        let registry: HashMap<Item, ItemDefinition> = serde_json::from_str(r#"{
+            "Bandage": { "effects": [{ "effect": "Heal", "amount": 15 }] },
+            "Coin": { "consumed": false, "effects": [{ "effect": "Currency" }] }
+        }"#).unwrap();
+        let registry = ItemRegistry(registry);
+
+        let bandage = &registry[&Item::new("Bandage")];
+        assert!(bandage.consumed);
+        assert!(bandage.is_usable());
+        assert_eq!(bandage.heal_amount(), Some(15));
+
+        let coin = &registry[&Item::new("Coin")];
+        assert!(!coin.consumed);
+        assert!(!coin.is_usable());
+        assert_eq!(coin.heal_amount(), None);
+
+        assert!(serde_json::from_str::<HashMap<Item, ItemDefinition>>(r#"{ "Gear": { "effects": [{ "effect": "Teleport" }] } }"#).is_err());
+        assert!(ItemRegistry::read().is_ok());
+    }
+
+    #[test]
+    fn using_an_item_applies_its_effects_and_consumes_it() {
+        let (mut world, user) = user_with(&[("Food", 2), ("PowerOrb", 1)]);
+        let mut stage = SystemStage::single_threaded().with_system(apply_item_effects);
+
+        use_item(&mut world, &mut stage, user, "Food", None);
+        assert_eq!(world.get::<Health>(user).unwrap().current, 30);
+        assert_eq!(world.get::<Inventory>(user).unwrap().count(&Item::new("Food")), 1);
+
+        use_item(&mut world, &mut stage, user, "PowerOrb", None);
+        assert_eq!(world.get::<PowerBuff>(user).unwrap().damage_multiplier, 2.0);
+        assert_eq!(world.get::<Inventory>(user).unwrap().count(&Item::new("PowerOrb")), 0);
+
+        // Nothing happens without the item
+        use_item(&mut world, &mut stage, user, "Health", None);
+        assert_eq!(world.get::<Health>(user).unwrap().current, 30);
+    }
+
+    #[test]
+    fn keys_only_unlock_with_a_target() {
+        let (mut world, user) = user_with(&[("Key", 1)]);
+        let mut stage = SystemStage::single_threaded().with_system(apply_item_effects);
+        let door = world.spawn().id();
+
+        use_item(&mut world, &mut stage, user, "Key", None);
+        assert_eq!(world.get::<Inventory>(user).unwrap().count(&Item::new("Key")), 1);
+
+        use_item(&mut world, &mut stage, user, "Key", Some(door));
+        let events = world.resource::<Events<UnlockEvent>>();
+        let unlocked: Vec<Entity> = events.get_reader().iter(events).map(|event| event.target).collect();
+        assert_eq!(unlocked, vec![door]);
+        assert_eq!(world.get::<Inventory>(user).unwrap().count(&Item::new("Key")), 0);
+    }
+}
@@ -0,0 +1,603 @@
+use std::{collections::{BTreeMap, HashMap}, env, time::{SystemTime, UNIX_EPOCH}};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{prelude::*, ldtk::{LdtkJson, Level, TileInstance}, EntityInstance};
+use crate::{item::{Item, ItemEffect, ItemRegistry}, save::SaveData};
+use super::{terrain::Terrain, travel_to_tile, COLLISIONS_LAYER, ENTITIES_LAYER};
+
+/// Size of a generated floor in tiles
+const COLUMNS: i32 = 48;
+const ROWS: i32 = 32;
+const ROOM_ATTEMPTS: usize = 60;
+const MAX_ROOMS: usize = 8;
+/// Floor size of a room in tiles, both ways
+const MIN_ROOM_SIZE: i32 = 4;
+const MAX_ROOM_SIZE: i32 = 9;
+/// Walls kept between two rooms, so they don't merge into one
+const ROOM_SPACING: i32 = 2;
+/// Chance out of 100 that a doorway gets a door
+const DOOR_CHANCE: u64 = 50;
+/// Chance out of 100 that a room other than the start and the elevator room gets a container
+const CONTAINER_CHANCE: u64 = 60;
+/// Empty tiles between a generated floor and the levels already in the world
+const WORLD_GAP: i32 = 4;
+/// Replays a floor entered from the main menu, a new seed is picked each time without it
+const SEED_VARIABLE: &str = "CONCORDE_SEED";
+
+/// Floor picked from the main menu, entered once the LDtk project has loaded
+pub struct DungeonRequest {
+    pub seed: u64,
+}
+
+impl DungeonRequest {
+    pub fn from_env() -> Self {
+        let seed = match env::var(SEED_VARIABLE) {
+            Ok(seed) => seed.parse().unwrap_or_else(|_| {
+                warn!("{} isn't a seed, picking a new one", seed);
+                random_seed()
+            }),
+            Err(_) => random_seed(),
+        };
+        DungeonRequest { seed }
+    }
+}
+
+/// A different seed on every call, for a fresh floor
+pub fn random_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+/// SplitMix64, small enough to keep here so a seed gives the same floor on every machine
+struct SeededRng(u64);
+
+impl SeededRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, max: u64) -> u64 {
+        self.next() % max
+    }
+
+    /// Between `min` and `max`, both included
+    fn between(&mut self, min: i32, max: i32) -> i32 {
+        min + self.below((max - min + 1) as u64) as i32
+    }
+}
+
+/// A rectangle of floor, in LDtk grid coordinates counted from the top-left corner
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Room {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Room {
+    pub fn center(&self) -> IVec2 {
+        IVec2::new(self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    pub fn contains(&self, tile: IVec2) -> bool {
+        tile.x >= self.x && tile.x < self.x + self.width && tile.y >= self.y && tile.y < self.y + self.height
+    }
+
+    /// Corners of the floor, clockwise from the top-left
+    pub fn corners(&self) -> [IVec2; 4] {
+        let (right, bottom) = (self.x + self.width - 1, self.y + self.height - 1);
+        [
+            IVec2::new(self.x, self.y),
+            IVec2::new(right, self.y),
+            IVec2::new(right, bottom),
+            IVec2::new(self.x, bottom),
+        ]
+    }
+
+    /// Whether the rooms overlap or have fewer than `spacing` tiles between them
+    fn near(&self, other: &Room, spacing: i32) -> bool {
+        self.x - spacing < other.x + other.width
+            && other.x - spacing < self.x + self.width
+            && self.y - spacing < other.y + other.height
+            && other.y - spacing < self.y + self.height
+    }
+}
+
+/// Rooms and corridors of a floor, before they are turned into LDtk layers
+#[derive(Clone, Debug, PartialEq)]
+pub struct DungeonLayout {
+    pub columns: i32,
+    pub rows: i32,
+    /// `Collisions` IntGrid values, row by row from the top like LDtk stores them
+    pub cells: Vec<i32>,
+    /// Each room has a corridor to the one before it, the first is where the player starts
+    pub rooms: Vec<Room>,
+    /// Corridor tiles next to a room and narrow enough to hold a door
+    pub doorways: Vec<IVec2>,
+}
+
+impl DungeonLayout {
+    fn generate(rng: &mut SeededRng, columns: i32, rows: i32) -> Self {
+        let mut layout = DungeonLayout {
+            columns,
+            rows,
+            cells: vec![Terrain::Wall.value(); (columns * rows) as usize],
+            rooms: Vec::new(),
+            doorways: Vec::new(),
+        };
+
+        for _ in 0..ROOM_ATTEMPTS {
+            if layout.rooms.len() == MAX_ROOMS {
+                break;
+            }
+
+            let width = rng.between(MIN_ROOM_SIZE, MAX_ROOM_SIZE);
+            let height = rng.between(MIN_ROOM_SIZE, MAX_ROOM_SIZE);
+            // Leave the outermost tiles as walls
+            let room = Room {
+                x: rng.between(1, columns - width - 1),
+                y: rng.between(1, rows - height - 1),
+                width,
+                height,
+            };
+            if layout.rooms.iter().any(|other| room.near(other, ROOM_SPACING)) {
+                continue;
+            }
+
+            for y in room.y..room.y + room.height {
+                for x in room.x..room.x + room.width {
+                    layout.carve(IVec2::new(x, y));
+                }
+            }
+            if let Some(previous) = layout.rooms.last() {
+                layout.carve_corridor(rng, previous.center(), room.center());
+            }
+            layout.rooms.push(room);
+        }
+
+        layout.doorways = layout.find_doorways();
+        layout
+    }
+
+    /// IntGrid value of a tile, tiles outside the floor count as walls
+    pub fn cell(&self, tile: IVec2) -> i32 {
+        if tile.x < 0 || tile.y < 0 || tile.x >= self.columns || tile.y >= self.rows {
+            return Terrain::Wall.value();
+        }
+        self.cells[(tile.y * self.columns + tile.x) as usize]
+    }
+
+    pub fn is_floor(&self, tile: IVec2) -> bool {
+        self.cell(tile) == 0
+    }
+
+    fn carve(&mut self, tile: IVec2) {
+        self.cells[(tile.y * self.columns + tile.x) as usize] = 0;
+    }
+
+    /// An L-shaped corridor, turning either way
+    fn carve_corridor(&mut self, rng: &mut SeededRng, from: IVec2, to: IVec2) {
+        let corner = if rng.below(2) == 0 {
+            IVec2::new(to.x, from.y)
+        } else {
+            IVec2::new(from.x, to.y)
+        };
+        for (start, end) in [(from, corner), (corner, to)] {
+            let (min, max) = (start.min(end), start.max(end));
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    self.carve(IVec2::new(x, y));
+                }
+            }
+        }
+    }
+
+    fn find_doorways(&self) -> Vec<IVec2> {
+        let in_room = |tile: IVec2| self.rooms.iter().any(|room| room.contains(tile));
+        (0..self.rows)
+            .flat_map(|y| (0..self.columns).map(move |x| IVec2::new(x, y)))
+            .filter(|tile| self.is_floor(*tile) && !in_room(*tile))
+            .filter(|tile| {
+                [IVec2::X, -IVec2::X, IVec2::Y, -IVec2::Y].into_iter().any(|direction| {
+                    let side = IVec2::new(-direction.y, direction.x);
+                    in_room(*tile + direction) && !self.is_floor(*tile + side) && !self.is_floor(*tile - side)
+                })
+            })
+            .collect()
+    }
+}
+
+/// The most common tile under each IntGrid value of a designed level, when it covers
+/// enough of them to pass for that value's look. Tiles that depend on their neighbours,
+/// like wall outlines, don't, and are left out
+fn representative_tiles(tiles: &[TileInstance], cells: &[i32], columns: i32, grid_size: i32) -> HashMap<i32, TileInstance> {
+    let mut cell_counts: HashMap<i32, usize> = HashMap::new();
+    for value in cells {
+        *cell_counts.entry(*value).or_default() += 1;
+    }
+
+    let mut tile_counts: HashMap<i32, BTreeMap<i32, (usize, &TileInstance)>> = HashMap::new();
+    for tile in tiles {
+        let index = (tile.px.y / grid_size * columns + tile.px.x / grid_size) as usize;
+        if let Some(value) = cells.get(index) {
+            tile_counts.entry(*value).or_default().entry(tile.t).or_insert((0, tile)).0 += 1;
+        }
+    }
+
+    tile_counts
+        .into_iter()
+        .filter_map(|(value, counts)| {
+            let (count, tile) = counts.into_values().max_by_key(|(count, _)| *count)?;
+            (count * 4 >= cell_counts[&value]).then(|| (value, tile.clone()))
+        })
+        .collect()
+}
+
+/// A copy of `template` standing on `tile`
+fn place(template: &EntityInstance, iid: String, tile: IVec2, grid_size: i32) -> EntityInstance {
+    let mut entity = template.clone();
+    entity.iid = iid;
+    entity.grid = tile;
+    entity.px = tile * grid_size + (entity.pivot * grid_size as f32).as_ivec2();
+    entity
+}
+
+fn set_field(entity: &mut EntityInstance, identifier: &str, value: FieldValue) {
+    if let Some(field) = entity.field_instances.iter_mut().find(|field| field.identifier == identifier) {
+        field.value = value;
+    }
+}
+
+/// Whether a container is part of a quest, with a script to read or an item that opens the way on,
+/// which only makes sense where the designer placed it
+fn is_quest_container(container: &EntityInstance, registry: &ItemRegistry) -> bool {
+    container.field_instances.iter().any(|field| match (field.identifier.as_str(), &field.value) {
+        ("Dialogue", FieldValue::String(script)) => script.is_some(),
+        ("loot", FieldValue::Enums(loot)) => loot.iter().flatten().any(|item| {
+            registry
+                .get(&Item::new(item))
+                .map_or(false, |definition| definition.has_effect(|effect| matches!(effect, ItemEffect::Unlock | ItemEffect::Power)))
+        }),
+        _ => false,
+    })
+}
+
+/// Builds floor `seed` as a level of `project`.
+/// Its layers, tiles and entities are copied from the levels designers made, so
+/// generated floors look and behave like them and need nothing new from the project
+pub fn generate_level(project: &LdtkJson, registry: &ItemRegistry, seed: u64) -> Result<LdtkLevel, String> {
+    let mut rng = SeededRng(seed);
+    let layer = |level: &Level, identifier: &str| {
+        level.layer_instances.iter().flatten().find(|layer| layer.identifier == identifier).cloned()
+    };
+    let (template, template_collisions) = project
+        .levels
+        .iter()
+        .find_map(|level| Some((level, layer(level, COLLISIONS_LAYER)?)).filter(|_| layer(level, ENTITIES_LAYER).is_some()))
+        .ok_or_else(|| format!("No level has both a {} and an {} layer to copy", COLLISIONS_LAYER, ENTITIES_LAYER))?;
+
+    // Every entity a designer placed, with the level and layer iids it was placed in
+    let instances: Vec<(&str, &str, &EntityInstance)> = project
+        .levels
+        .iter()
+        .flat_map(|level| {
+            level.layer_instances.iter().flatten().flat_map(move |layer| {
+                layer.entity_instances.iter().map(move |entity| (level.iid.as_str(), layer.iid.as_str(), entity))
+            })
+        })
+        .collect();
+    let all = |identifier: &str| -> Vec<&EntityInstance> {
+        instances.iter().filter(|(_, _, entity)| entity.identifier == identifier).map(|(_, _, entity)| *entity).collect()
+    };
+    let first = |identifier: &str| {
+        all(identifier).first().copied().ok_or_else(|| format!("No {} in the project to copy", identifier))
+    };
+    let (player, knight, door) = (first("Player")?, first("Knight")?, first("Door")?);
+    let containers: Vec<&EntityInstance> = all("Container")
+        .into_iter()
+        .filter(|container| !is_quest_container(container, registry))
+        .collect();
+    if containers.is_empty() {
+        return Err("No Container outside of quests in the project to copy".to_string());
+    }
+    // The generated elevator rides to this one, the only way back without the console
+    let (elevator, return_trip) = instances
+        .iter()
+        .find_map(|(level_iid, layer_iid, entity)| {
+            let reference = entity.field_instances.iter().find_map(|field| match &field.value {
+                FieldValue::EntityRef(Some(reference)) if field.identifier == "destination" => Some(reference),
+                _ => None,
+            })?;
+            let mut return_trip = reference.clone();
+            return_trip.entity_iid = entity.iid.clone();
+            return_trip.layer_iid = layer_iid.to_string();
+            return_trip.level_iid = level_iid.to_string();
+            Some((*entity, return_trip))
+        })
+        .ok_or_else(|| "No ElevatorLink with a destination in the project to copy".to_string())?;
+
+    let layout = DungeonLayout::generate(&mut rng, COLUMNS, ROWS);
+    let grid_size = template_collisions.grid_size;
+    let iid = format!("generated-{:016x}", seed);
+    let mut next_iid = 0;
+    let mut entity_iid = || {
+        next_iid += 1;
+        format!("{}-entity-{}", iid, next_iid)
+    };
+
+    let mut entities = Vec::new();
+    let start = layout.rooms[0];
+    // The player keeps the iid of the designed one, so it isn't spawned twice while it is worldly
+    entities.push(place(player, player.iid.clone(), start.center(), grid_size));
+
+    let exit = layout
+        .rooms
+        .iter()
+        .max_by_key(|room| {
+            let distance = (room.center() - start.center()).abs();
+            distance.x + distance.y
+        })
+        .copied()
+        .unwrap_or(start);
+    let mut link = place(elevator, entity_iid(), exit.center(), grid_size);
+    set_field(&mut link, "destination", FieldValue::EntityRef(Some(return_trip)));
+    entities.push(link);
+
+    for room in layout.rooms.iter().filter(|room| **room != start) {
+        // Walks round the room, there is nothing in the way along its walls
+        let [spawn, patrol @ ..] = room.corners();
+        let mut guard = place(knight, entity_iid(), spawn, grid_size);
+        set_field(&mut guard, "Patrol", FieldValue::Points(patrol.into_iter().map(Some).collect()));
+        entities.push(guard);
+
+        if *room != exit && rng.below(100) < CONTAINER_CHANCE {
+            let container = containers[rng.below(containers.len() as u64) as usize];
+            entities.push(place(container, entity_iid(), room.center(), grid_size));
+        }
+    }
+
+    let mut doors: Vec<IVec2> = Vec::new();
+    for doorway in layout.doorways.iter() {
+        // Doors side by side would only be in each other's way
+        let crowded = doors.iter().any(|door| (*door - *doorway).abs().max_element() <= 1);
+        if !crowded && rng.below(100) < DOOR_CHANCE {
+            // The key to the copied door may be nowhere on this floor
+            let mut placed = place(door, entity_iid(), *doorway, grid_size);
+            set_field(&mut placed, "lockedWith", FieldValue::Enum(None));
+            entities.push(placed);
+            doors.push(*doorway);
+        }
+    }
+
+    let looks: Vec<HashMap<i32, TileInstance>> = template
+        .layer_instances
+        .iter()
+        .flatten()
+        .map(|layer| representative_tiles(&layer.auto_layer_tiles, &template_collisions.int_grid_csv, template_collisions.c_wid, grid_size))
+        .collect();
+
+    let mut level = template.clone();
+    level.identifier = format!("Dungeon_{}", seed);
+    level.uid = project.levels.iter().map(|level| level.uid).max().unwrap_or_default() + 1;
+    level.px_wid = layout.columns * grid_size;
+    level.px_hei = layout.rows * grid_size;
+    level.world_x = project
+        .levels
+        .iter()
+        .filter(|other| other.iid != iid)
+        .map(|other| other.world_x + other.px_wid)
+        .max()
+        .unwrap_or_default()
+        + WORLD_GAP * grid_size;
+    level.world_y = 0;
+    level.neighbours.clear();
+    level.external_rel_path = None;
+
+    for (index, (layer, look)) in level.layer_instances.iter_mut().flatten().zip(looks).enumerate() {
+        layer.iid = format!("{}-layer-{}", iid, index);
+        layer.level_id = level.uid;
+        layer.c_wid = layout.columns;
+        layer.c_hei = layout.rows;
+        layer.grid_tiles.clear();
+        layer.entity_instances.clear();
+        layer.auto_layer_tiles = layout
+            .cells
+            .iter()
+            .enumerate()
+            .filter_map(|(cell, value)| {
+                let mut tile = look.get(value)?.clone();
+                let tile_coords = IVec2::new(cell as i32 % layout.columns, cell as i32 / layout.columns);
+                tile.px = tile_coords * layer.grid_size;
+                // Auto-layer tiles are tagged with the rule and cell that made them
+                tile.d = vec![tile.d.first().copied().unwrap_or_default(), cell as i32];
+                Some(tile)
+            })
+            .collect();
+
+        if layer.identifier == COLLISIONS_LAYER {
+            layer.int_grid_csv = layout.cells.clone();
+        } else if layer.identifier == ENTITIES_LAYER {
+            layer.entity_instances = std::mem::take(&mut entities);
+        }
+    }
+    level.iid = iid;
+
+    Ok(LdtkLevel {
+        level,
+        background_image: None,
+    })
+}
+
+/// Adds a generated level to the loaded project, replacing the one from the same seed.
+/// There is no battery to find on a generated floor, so its elevator is powered in `save` from the start
+pub fn add_to_project(ldtk_asset: &mut LdtkAsset, levels: &mut Assets<LdtkLevel>, level: LdtkLevel, save: &mut SaveData) {
+    let elevators = level
+        .level
+        .layer_instances
+        .iter()
+        .flatten()
+        .flat_map(|layer| layer.entity_instances.iter())
+        .filter(|entity| entity.identifier == "ElevatorLink")
+        .map(|entity| entity.iid.clone());
+    save.powered_devices.extend(elevators);
+
+    let iid = level.level.iid.clone();
+    ldtk_asset.project.levels.retain(|existing| existing.iid != iid);
+    ldtk_asset.project.levels.push(level.level.clone());
+    ldtk_asset.level_map.insert(iid, levels.add(level));
+}
+
+/// Generates floor `seed`, adds it to the loaded project and sends the player to its start.
+/// Returns the identifier of the new level
+pub fn enter_floor(
+    commands: &mut Commands,
+    ldtk_asset: &mut LdtkAsset,
+    levels: &mut Assets<LdtkLevel>,
+    registry: &ItemRegistry,
+    save: &mut SaveData,
+    seed: u64,
+) -> Result<String, String> {
+    let level = generate_level(&ldtk_asset.project, registry, seed)?;
+    let (identifier, iid) = (level.level.identifier.clone(), level.level.iid.clone());
+    let start = start_tile(&level.level).ok_or("The generated floor has no player start")?;
+    add_to_project(ldtk_asset, levels, level, save);
+    travel_to_tile(commands, &iid, start);
+    Ok(identifier)
+}
+
+/// Enters the floor picked from the main menu as soon as the LDtk project is there
+pub fn enter_requested_floor(
+    mut commands: Commands,
+    request: Option<Res<DungeonRequest>>,
+    worlds: Query<&Handle<LdtkAsset>>,
+    mut ldtk_assets: ResMut<Assets<LdtkAsset>>,
+    mut levels: ResMut<Assets<LdtkLevel>>,
+    registry: Res<ItemRegistry>,
+    mut save: ResMut<SaveData>,
+) {
+    let seed = match request {
+        Some(request) => request.seed,
+        None => return,
+    };
+    let ldtk_asset = match worlds.iter().next().and_then(|handle| ldtk_assets.get_mut(handle)) {
+        Some(ldtk_asset) => ldtk_asset,
+        None => return,
+    };
+
+    match enter_floor(&mut commands, ldtk_asset, &mut levels, &registry, &mut save, seed) {
+        Ok(identifier) => info!(seed, level = %identifier, "entering a generated floor"),
+        Err(error) => error!("Failed to generate floor {}: {}", seed, error),
+    }
+    commands.remove_resource::<DungeonRequest>();
+}
+
+/// Tile the player starts on in a level, counted from its bottom-left corner
+pub fn start_tile(level: &Level) -> Option<IVec2> {
+    let entities = level.layer_instances.iter().flatten().find(|layer| layer.identifier == ENTITIES_LAYER)?;
+    let player = entities.entity_instances.iter().find(|entity| entity.identifier == "Player")?;
+    Some(IVec2::new(player.grid.x, entities.c_hei - 1 - player.grid.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use bevy::asset::AssetPlugin;
+    use crate::{level::{LDTK_PROJECT, props::Door}, validate};
+    use super::*;
+
+    fn layout(seed: u64) -> DungeonLayout {
+        DungeonLayout::generate(&mut SeededRng(seed), COLUMNS, ROWS)
+    }
+
+    fn project() -> LdtkJson {
+        let project = std::fs::read_to_string(format!("assets/{}", LDTK_PROJECT)).unwrap();
+        serde_json::from_str(&project).unwrap()
+    }
+
+    #[test]
+    fn seeds_are_replayable() {
+        assert_eq!(layout(7), layout(7));
+        assert_ne!(layout(7), layout(8));
+    }
+
+    #[test]
+    fn every_floor_tile_is_reachable_from_the_start() {
+        for seed in 0..50 {
+            let layout = layout(seed);
+            assert!(layout.rooms.len() > 1, "seed {} has a single room", seed);
+
+            let mut reached = HashSet::from([layout.rooms[0].center()]);
+            let mut frontier = vec![layout.rooms[0].center()];
+            while let Some(tile) = frontier.pop() {
+                for next in [tile + IVec2::X, tile - IVec2::X, tile + IVec2::Y, tile - IVec2::Y] {
+                    if layout.is_floor(next) && reached.insert(next) {
+                        frontier.push(next);
+                    }
+                }
+            }
+            let floor = layout.cells.iter().filter(|value| **value == 0).count();
+            assert_eq!(reached.len(), floor, "seed {} has unreachable floor", seed);
+        }
+    }
+
+    #[test]
+    fn generated_entities_are_complete_and_on_the_floor() {
+        let (project, registry) = (project(), ItemRegistry::read().unwrap());
+        for seed in 0..20 {
+            let level = generate_level(&project, &registry, seed).unwrap().level;
+            let layers = level.layer_instances.as_ref().unwrap();
+            let collisions = layers.iter().find(|layer| layer.identifier == COLLISIONS_LAYER).unwrap();
+            let entities = &layers.iter().find(|layer| layer.identifier == ENTITIES_LAYER).unwrap().entity_instances;
+            let on_floor = |tile: IVec2| collisions.int_grid_csv[(tile.y * collisions.c_wid + tile.x) as usize] == 0;
+
+            for identifier in ["Player", "Knight", "ElevatorLink"] {
+                assert!(entities.iter().any(|entity| entity.identifier == identifier), "seed {} has no {}", seed, identifier);
+            }
+            for entity in entities {
+                assert!(on_floor(entity.grid), "seed {}: {} {} is in a wall", seed, entity.identifier, entity.iid);
+                if entity.identifier == "Container" {
+                    assert!(!is_quest_container(entity, &registry), "seed {}: {} is a quest container", seed, entity.iid);
+                }
+                if entity.identifier == "Door" {
+                    assert_eq!(Door::from(entity.clone()).locked_with, None, "seed {}: {} is locked", seed, entity.iid);
+                }
+                assert_eq!(validate::instance_problems(entity), Vec::<String>::new());
+
+                for field in entity.field_instances.iter() {
+                    if let FieldValue::Points(points) = &field.value {
+                        assert!(points.iter().flatten().all(|point| on_floor(*point)), "seed {}: patrol of {} leaves the floor", seed, entity.iid);
+                    }
+                }
+            }
+            assert_eq!(start_tile(&level).map(|tile| on_floor(IVec2::new(tile.x, collisions.c_hei - 1 - tile.y))), Some(true));
+        }
+    }
+
+    #[test]
+    fn generated_elevators_start_powered() {
+        let (project, registry) = (project(), ItemRegistry::read().unwrap());
+        let level = generate_level(&project, &registry, 3).unwrap();
+        let iid = level.level.iid.clone();
+        let elevator = level
+            .level
+            .layer_instances
+            .iter()
+            .flatten()
+            .flat_map(|layer| layer.entity_instances.iter())
+            .find(|entity| entity.identifier == "ElevatorLink")
+            .map(|entity| entity.iid.clone())
+            .unwrap();
+
+        let mut app = App::new();
+        app.add_plugin(AssetPlugin).add_asset::<LdtkLevel>();
+        let mut ldtk_asset = LdtkAsset { project, tileset_map: Default::default(), level_map: Default::default() };
+        let mut save = SaveData::default();
+        add_to_project(&mut ldtk_asset, &mut app.world.resource_mut::<Assets<LdtkLevel>>(), level, &mut save);
+
+        assert!(ldtk_asset.level_map.contains_key(&iid));
+        assert_eq!(save.powered_devices, [elevator].into_iter().collect());
+    }
+}
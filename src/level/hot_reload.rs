@@ -0,0 +1,300 @@
+use std::{collections::{HashMap, HashSet}, time::Duration};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use crate::{
+    Player,
+    player::Direction,
+    combat::Health,
+    projectile::Ammo,
+    item::{EquippedItem, Inventory},
+    enemy::{Patrol, attack::MeleeAttack, settings::EnemyKind},
+};
+use super::{props::{Container, Door, open_door}, terrain::SafePosition};
+
+/// How long a level may take to spawn again before the reload is given up on
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Every LDtk entity as it was last spawned, keyed by iid, so defeated enemies are still known after they're gone
+#[derive(Default)]
+pub struct SpawnedInstances(HashMap<String, EntityInstance>);
+
+/// What was going on in the level before the LDtk project was edited,
+/// kept until the level has spawned again from the new project
+pub struct ReloadSnapshot {
+    player: Option<PlayerState>,
+    /// Every LDtk entity of the level as it was, to tell which ones the edit changed
+    instances: HashMap<String, EntityInstance>,
+    /// Enemies still alive, keyed by LDtk iid
+    enemies: HashMap<String, EnemyState>,
+    /// LDtk iids of the containers the player emptied
+    looted: HashSet<String>,
+    /// LDtk iids of the doors the player opened
+    opened: HashSet<String>,
+    /// `Time::time_since_startup` when the reload started
+    started: Duration,
+}
+
+struct PlayerState {
+    transform: Transform,
+    health: Health,
+    ammo: Ammo,
+    inventory: Inventory,
+    equipped_item: EquippedItem,
+    safe_position: SafePosition,
+}
+
+struct EnemyState {
+    transform: Transform,
+    patrol: Patrol,
+    direction: Direction,
+    health: Health,
+    melee_attack: MeleeAttack,
+}
+
+impl ReloadSnapshot {
+    /// Whether the edit left an entity as it was before the reload
+    fn unchanged(&self, instance: &EntityInstance) -> bool {
+        self.instances.get(&instance.iid) == Some(instance)
+    }
+
+    /// Hands an unchanged enemy its AI state back, or despawns it again if it had been defeated
+    fn restore_enemy(&self, commands: &mut Commands, entity: Entity, instance: &EntityInstance, transform: &mut Transform) {
+        if !self.unchanged(instance) {
+            debug!(entity = %instance.iid, "enemy is new or changed, spawning it afresh");
+            return;
+        }
+
+        match self.enemies.get(&instance.iid) {
+            Some(state) => {
+                *transform = state.transform;
+                // Health is inserted by the enemy plugin once the enemy spawns, this overrides it
+                commands
+                    .entity(entity)
+                    .insert(state.patrol.clone())
+                    .insert(state.direction.clone())
+                    .insert(state.health)
+                    .insert(state.melee_attack.clone());
+            },
+            // Unchanged, but it wasn't alive anymore, so it stays defeated
+            None => commands.entity(entity).despawn_recursive(),
+        }
+    }
+}
+
+/// Remembers every LDtk entity as it spawns, for `snapshot_before_reload` to compare the edited project against
+pub fn record_spawned_instances(
+    mut spawned: ResMut<SpawnedInstances>,
+    instances: Query<&EntityInstance, Added<EntityInstance>>,
+) {
+    for instance in instances.iter() {
+        spawned.0.insert(instance.iid.clone(), instance.clone());
+    }
+}
+
+/// Runs before bevy_ecs_ldtk respawns the level from the modified project, while its entities are still around
+#[allow(clippy::too_many_arguments)]
+pub fn snapshot_before_reload(
+    mut commands: Commands,
+    mut project_events: EventReader<AssetEvent<LdtkAsset>>,
+    mut level_events: EventReader<AssetEvent<LdtkLevel>>,
+    snapshot: Option<Res<ReloadSnapshot>>,
+    spawned: Res<SpawnedInstances>,
+    time: Res<Time>,
+    player: Query<(&Transform, &Health, &Ammo, &Inventory, &EquippedItem, &SafePosition), With<Player>>,
+    enemies: Query<(&EntityInstance, &Transform, &Patrol, &Direction, &Health, &MeleeAttack), With<EnemyKind>>,
+    containers: Query<(&EntityInstance, &Container)>,
+    doors: Query<(&EntityInstance, &Visibility), With<Door>>,
+) {
+    let project_modified = project_events.iter().any(|event| matches!(event, AssetEvent::Modified { .. }));
+    let level_modified = level_events.iter().any(|event| matches!(event, AssetEvent::Modified { .. }));
+    // A second save while the level is respawning would only see what's left of it
+    if !(project_modified || level_modified) || snapshot.is_some() {
+        return;
+    }
+
+    let player = player.get_single().ok().map(|(transform, health, ammo, inventory, equipped_item, safe_position)| PlayerState {
+        transform: *transform,
+        health: *health,
+        ammo: *ammo,
+        inventory: inventory.clone(),
+        equipped_item: equipped_item.clone(),
+        safe_position: *safe_position,
+    });
+    let enemies: HashMap<String, EnemyState> = enemies
+        .iter()
+        .map(|(instance, transform, patrol, direction, health, melee_attack)| {
+            (instance.iid.clone(), EnemyState {
+                transform: *transform,
+                patrol: patrol.clone(),
+                direction: direction.clone(),
+                health: *health,
+                melee_attack: melee_attack.clone(),
+            })
+        })
+        .collect();
+    let looted = containers
+        .iter()
+        .filter(|(_, container)| container.looted)
+        .map(|(instance, _)| instance.iid.clone())
+        .collect();
+    // Opened doors are hidden
+    let opened = doors
+        .iter()
+        .filter(|(_, visibility)| !visibility.is_visible)
+        .map(|(instance, _)| instance.iid.clone())
+        .collect();
+
+    info!(entities = spawned.0.len(), enemies = enemies.len(), "LDtk project modified, reloading the level");
+    commands.insert_resource(ReloadSnapshot {
+        player,
+        instances: spawned.0.clone(),
+        enemies,
+        looted,
+        opened,
+        started: time.time_since_startup(),
+    });
+}
+
+/// Puts the player back where they were and hands unchanged enemies and props their state,
+/// entities the edit touched start over from their new definition
+#[allow(clippy::too_many_arguments)]
+pub fn restore_after_reload(
+    mut commands: Commands,
+    snapshot: Option<Res<ReloadSnapshot>>,
+    mut level_events: EventReader<LevelEvent>,
+    time: Res<Time>,
+    level_selection: Option<Res<LevelSelection>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    mut player: Query<(&mut Transform, &mut Health, &mut Ammo, &mut Inventory, &mut EquippedItem, &mut SafePosition), Added<Player>>,
+    mut enemies: Query<(Entity, &EntityInstance, &mut Transform), (Added<EnemyKind>, Without<Player>)>,
+    mut containers: Query<(&EntityInstance, &mut Container), Added<Container>>,
+    mut doors: Query<(&EntityInstance, &mut Door, &mut Visibility), Added<Door>>,
+) {
+    // Read either way, so events from before the reload don't end it early
+    let transformed = level_events.iter().any(|event| matches!(event, LevelEvent::Transformed(_)));
+    let snapshot = match snapshot {
+        Some(snapshot) => snapshot,
+        None => return,
+    };
+
+    // The edit may have removed the level, or it failed to spawn, so the snapshot would never be used
+    let level_exists = level_selection.map_or(false, |selection| {
+        ldtk_assets
+            .iter()
+            .any(|(_, ldtk_asset)| ldtk_asset.project.levels.iter().enumerate().any(|(index, level)| selection.is_match(&index, level)))
+    });
+    if !level_exists || time.time_since_startup() - snapshot.started > RELOAD_TIMEOUT {
+        warn!("The level didn't spawn again after the LDtk project was modified, its state is lost");
+        commands.remove_resource::<ReloadSnapshot>();
+        return;
+    }
+
+    if let Some(state) = &snapshot.player {
+        for (mut transform, mut health, mut ammo, mut inventory, mut equipped_item, mut safe_position) in player.iter_mut() {
+            *transform = state.transform;
+            *health = state.health;
+            *ammo = state.ammo;
+            *inventory = state.inventory.clone();
+            *equipped_item = state.equipped_item.clone();
+            *safe_position = state.safe_position;
+        }
+    }
+
+    for (entity, instance, mut transform) in enemies.iter_mut() {
+        snapshot.restore_enemy(&mut commands, entity, instance, &mut transform);
+    }
+
+    for (instance, mut container) in containers.iter_mut() {
+        if snapshot.unchanged(instance) && snapshot.looted.contains(&instance.iid) {
+            container.loot.clear();
+            container.looted = true;
+        }
+    }
+    // Door blockers spawn during the update, so they exist by now and are removed with the door
+    for (instance, mut door, mut visibility) in doors.iter_mut() {
+        if snapshot.unchanged(instance) && snapshot.opened.contains(&instance.iid) {
+            open_door(&mut commands, &mut door, &mut visibility);
+        }
+    }
+
+    // The level's entities have all been through here by the time it is placed in the world
+    if transformed {
+        commands.remove_resource::<ReloadSnapshot>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+    use bevy_ecs_ldtk::ldtk::LdtkJson;
+    use crate::level::LDTK_PROJECT;
+    use super::*;
+
+    fn knights() -> Vec<EntityInstance> {
+        let project: LdtkJson = serde_json::from_str(&std::fs::read_to_string(format!("assets/{}", LDTK_PROJECT)).unwrap()).unwrap();
+        project
+            .levels
+            .iter()
+            .flat_map(|level| level.layer_instances.iter().flatten())
+            .flat_map(|layer| layer.entity_instances.iter())
+            .filter(|instance| instance.identifier == "Knight")
+            .cloned()
+            .collect()
+    }
+
+    fn wounded() -> EnemyState {
+        EnemyState {
+            transform: Transform::from_xyz(40.0, 24.0, 0.0),
+            patrol: Patrol { points: vec![Vec2::new(40.0, 24.0)], index: 0, forward: false },
+            direction: Direction::Left,
+            health: Health { current: 5, max: 30 },
+            melee_attack: MeleeAttack::default(),
+        }
+    }
+
+    #[test]
+    fn only_unchanged_enemies_keep_their_state() {
+        let knights = knights();
+        let (unchanged, changed, defeated) = (&knights[0], &knights[1], &knights[2]);
+
+        // Before the edit the changed knight stood elsewhere, and the defeated one was no longer alive
+        let mut before = changed.clone();
+        before.px += IVec2::new(16, 0);
+        let snapshot = ReloadSnapshot {
+            player: None,
+            instances: [unchanged.clone(), before, defeated.clone()]
+                .into_iter()
+                .map(|instance| (instance.iid.clone(), instance))
+                .collect(),
+            enemies: [(unchanged.iid.clone(), wounded()), (changed.iid.clone(), wounded())].into_iter().collect(),
+            looted: HashSet::new(),
+            opened: HashSet::new(),
+            started: Duration::ZERO,
+        };
+
+        let mut world = World::new();
+        let respawned: Vec<Entity> = [unchanged, changed, defeated]
+            .iter()
+            .map(|instance| world.spawn().insert((*instance).clone()).insert(Health::new(30)).id())
+            .collect();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let mut transforms = Vec::new();
+        for (entity, instance) in respawned.iter().zip([unchanged, changed, defeated]) {
+            let mut transform = Transform::default();
+            snapshot.restore_enemy(&mut commands, *entity, instance, &mut transform);
+            transforms.push(transform);
+        }
+        queue.apply(&mut world);
+
+        assert_eq!(transforms[0], wounded().transform);
+        assert_eq!(world.get::<Health>(respawned[0]), Some(&Health { current: 5, max: 30 }));
+        assert_eq!(world.get::<Patrol>(respawned[0]), Some(&wounded().patrol));
+
+        assert_eq!(transforms[1], Transform::default());
+        assert_eq!(world.get::<Health>(respawned[1]), Some(&Health::new(30)));
+        assert!(world.get::<Patrol>(respawned[1]).is_none());
+
+        assert!(world.get_entity(respawned[2]).is_none());
+    }
+}
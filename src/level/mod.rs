@@ -0,0 +1,304 @@
+use std::{fs, collections::{HashMap, HashSet}};
+use bevy_ecs_ldtk::{LdtkWorldBundle, LdtkEntity, prelude::*};
+use iyes_loopless::{prelude::{AppLooplessStateExt, ConditionSet, IntoConditionalSystem}, condition::IntoConditionalExclusiveSystem};
+use serde::{Deserialize, Serialize};
+use bevy::{prelude::*, transform::TransformSystem};
+
+use walls::merge_walls;
+use terrain::{SafePosition, SpeedModifier, TerrainMap, DamageFloorTimer};
+use crate::{SPRITE_SCALE, TILE_OFFSET, Player, player::Direction, player::Orientation, misc::{state::GameState, label::{LevelLabel, PlayerLabel}}, player::AnimationTimer, combat::{Health, health_from_life}, depth::YSort, projectile::{Ammo, ammo_from_field}, item::{EquippedItem, Inventory}, validate, TILE_SIZE};
+
+pub mod dungeon;
+pub mod hot_reload;
+pub mod props;
+pub mod terrain;
+pub mod walls;
+
+/// Identifier of the IntGrid layer that holds the level's walls
+pub const COLLISIONS_LAYER: &str = "Collisions";
+/// Identifier of the layer that holds the level's entities
+pub const ENTITIES_LAYER: &str = "Entities";
+/// The LDtk project holding every level, relative to the assets folder
+pub const LDTK_PROJECT: &str = "levels/test2.ldtk";
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<TerrainMap>()
+            .init_resource::<DamageFloorTimer>()
+            .add_enter_system(GameState::LoadGame, load_level)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::InGame)
+                    .with_system(spawn_wall_collision)
+                    .with_system(terrain::tag_solid_terrain)
+                    .with_system(terrain::build_terrain_map)
+                    .with_system(props::spawn_door_blockers)
+                    .with_system(props::open_doors)
+                    .with_system(props::unlock_doors)
+                    .with_system(props::loot_containers)
+                    .with_system(arrive_at_entity)
+                    .with_system(dungeon::enter_requested_floor)
+                    .with_system(report_malformed_entities)
+                    .into(),
+             )
+            .add_system(
+                terrain::player_terrain_effects
+                    .run_in_state(GameState::InGame)
+                    .label(LevelLabel::Terrain)
+                    .before(PlayerLabel::Collision)
+            )
+            .init_resource::<hot_reload::SpawnedInstances>()
+            .add_system_to_stage(CoreStage::First, hot_reload::snapshot_before_reload)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                hot_reload::restore_after_reload
+                    .label("restore_after_reload")
+                    .before(TransformSystem::TransformPropagate)
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                hot_reload::record_spawned_instances.after("restore_after_reload")
+            );
+
+        terrain::register_terrain(app);
+    }
+}
+
+/// Where the player is travelling to, they are placed there once its level has spawned
+pub enum LevelArrival {
+    /// Next to an LDtk entity
+    Entity { entity_iid: String },
+    /// On a tile of a level, counted from its bottom-left corner
+    Tile { level_iid: String, tile: IVec2 },
+}
+
+/// Switches to the level `level_iid` and moves the player next to the entity `entity_iid` in it
+pub fn travel_to(commands: &mut Commands, level_iid: &str, entity_iid: &str) {
+    info!(level = %level_iid, entity = %entity_iid, "travelling to level");
+    commands.insert_resource(LevelSelection::Iid(level_iid.to_string()));
+    commands.insert_resource(LevelArrival::Entity { entity_iid: entity_iid.to_string() });
+}
+
+/// Switches to the level `level_iid` and moves the player onto `tile` in it
+pub fn travel_to_tile(commands: &mut Commands, level_iid: &str, tile: IVec2) {
+    info!(level = %level_iid, x = tile.x, y = tile.y, "travelling to level");
+    commands.insert_resource(LevelSelection::Iid(level_iid.to_string()));
+    commands.insert_resource(LevelArrival::Tile { level_iid: level_iid.to_string(), tile });
+}
+
+fn arrive_at_entity(
+    mut commands: Commands,
+    arrival: Option<Res<LevelArrival>>,
+    mut player: Query<&mut Transform, With<Player>>,
+    entities: Query<(&EntityInstance, &GlobalTransform, ChangeTrackers<EntityInstance>), Without<Player>>,
+    level_query: Query<(&Handle<LdtkLevel>, &GlobalTransform, ChangeTrackers<Handle<LdtkLevel>>)>,
+    levels: Res<Assets<LdtkLevel>>,
+) {
+    let arrival = match arrival {
+        Some(arrival) => arrival,
+        None => return,
+    };
+
+    // Freshly spawned entities don't have their transform propagated yet
+    let destination = match &*arrival {
+        LevelArrival::Entity { entity_iid } => entities
+            .iter()
+            .find(|(instance, _, tracker)| instance.iid == *entity_iid && !tracker.is_added())
+            // Step off the destination so arriving doesn't immediately set off whatever is there
+            .map(|(_, destination, _)| destination.translation().truncate() - Vec2::new(0.0, TILE_SIZE)),
+        LevelArrival::Tile { level_iid, tile } => level_query
+            .iter()
+            .filter(|(_, _, tracker)| !tracker.is_added())
+            .find(|(handle, _, _)| levels.get(handle).map_or(false, |level| level.level.iid == *level_iid))
+            .map(|(_, level, _)| level.translation().truncate() + (tile.as_vec2() + 0.5) * TILE_SIZE),
+    };
+    if let Some(destination) = destination {
+        debug!(x = destination.x, y = destination.y, "player arrived in level");
+        for mut transform in player.iter_mut() {
+            transform.translation.x = destination.x;
+            transform.translation.y = destination.y;
+        }
+        commands.remove_resource::<LevelArrival>();
+    }
+}
+
+/// Warns about spawned entities the game can't fully understand, so designers can find them in LDtk.
+/// They still spawn, falling back to what their bundles default to
+fn report_malformed_entities(
+    entities: Query<(&EntityInstance, &Parent), Added<EntityInstance>>,
+    parent_query: Query<&Parent>,
+    level_query: Query<&Handle<LdtkLevel>>,
+    levels: Res<Assets<LdtkLevel>>,
+) {
+    for (instance, layer) in entities.iter() {
+        let problems = validate::instance_problems(instance);
+        if problems.is_empty() {
+            continue;
+        }
+
+        // Like wall tiles, an entity's parent is its layer and its grandparent the level.
+        // Worldly entities hang off the world instead, their level is long gone by now
+        let level = parent_query
+            .get(layer.get())
+            .ok()
+            .and_then(|level| level_query.get(level.get()).ok())
+            .and_then(|handle| levels.get(handle))
+            .map_or("unknown level", |level| level.level.identifier.as_str());
+        for problem in problems {
+            warn!(level, entity = %instance.iid, "{} {}", instance.identifier, problem);
+        }
+    }
+}
+
+/// Raw JSON of the LDtk project, for checks that run before the asset is loaded
+pub fn ldtk_project_json() -> Option<serde_json::Value> {
+    let path = format!("assets/{}", LDTK_PROJECT);
+    let project = std::fs::read_to_string(&path)
+        .map_err(|error| format!("Failed to open {}: {}", path, error))
+        .and_then(|project| serde_json::from_str(&project).map_err(|error| format!("Failed to parse {}: {}", path, error)));
+    match project {
+        Ok(project) => Some(project),
+        Err(error) => {
+            warn!("{}, settings can't be checked against it", error);
+            None
+        },
+    }
+}
+
+pub fn load_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let ldtk_handle = asset_server.load(LDTK_PROJECT);
+    commands.spawn_bundle(LdtkWorldBundle {
+        ldtk_handle,
+        ..Default::default()
+    });
+}
+
+#[derive(Bundle, LdtkEntity)]
+pub struct PlayerBundle {
+    #[sprite_sheet_bundle]
+    #[bundle]
+    pub spritesheet_bundle: SpriteSheetBundle,
+    pub player: Player,
+    pub direction: Direction,
+    pub orientation: Orientation, 
+    #[with(health_from_life)]
+    pub health: Health,
+    #[with(ammo_from_field)]
+    pub ammo: Ammo,
+    pub inventory: Inventory,
+    pub equipped_item: EquippedItem,
+    pub speed_modifier: SpeedModifier,
+    pub safe_position: SafePosition,
+    pub y_sort: YSort,
+    #[worldly]
+    pub worldly: Worldly
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Default, Component, Debug)]
+pub struct Wall;
+
+#[derive(Copy, Clone, Eq, PartialEq, Default, Component, Debug)]
+pub struct Collider;
+
+pub fn spawn_wall_collision(
+    mut commands: Commands,
+    wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
+    parent_query: Query<&Parent, Without<Wall>>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    levels: Res<Assets<LdtkLevel>>,
+) {
+    // Consider where the walls are
+    // storing them as GridCoords in a HashSet for quick, easy lookup
+    //
+    // The key of this map will be the entity of the level the wall belongs to.
+    // This has two consequences in the resulting collision entities:
+    // 1. it forces the walls to be split along level boundaries
+    // 2. it lets us easily add the collision entities as children of the appropriate level entity
+    let mut level_to_wall_locations: HashMap<Entity, HashSet<GridCoords>> = HashMap::new();
+
+    wall_query.for_each(|(&grid_coords, parent)| {
+        // An intgrid tile's direct parent will be a layer entity, not the level entity
+        // To get the level entity, you need the tile's grandparent.
+        // This is where parent_query comes in.
+        if let Ok(grandparent) = parent_query.get(parent.get()) {
+            level_to_wall_locations
+                .entry(grandparent.get())
+                .or_insert(HashSet::new())
+                .insert(grid_coords);
+        }
+    });
+
+    if !wall_query.is_empty() {
+        level_query.for_each(|(level_entity, level_handle)| {
+            if let Some(level_walls) = level_to_wall_locations.get(&level_entity) {
+                // Walls without their level or its layers can't be placed, so the level stays walkable
+                let level = match levels.get(level_handle) {
+                    Some(level) => level,
+                    None => {
+                        warn!(?level_entity, "level asset isn't loaded, skipping its wall colliders");
+                        return;
+                    },
+                };
+                let _span = debug_span!("spawn_wall_collision", level = %level.level.identifier).entered();
+
+                let collisions = level
+                    .level
+                    .layer_instances
+                    .iter()
+                    .flatten()
+                    .find(|layer| layer.identifier == COLLISIONS_LAYER);
+                let &LayerInstance {
+                    c_wid: width,
+                    c_hei: height,
+                    grid_size,
+                    ..
+                } = match collisions {
+                    Some(layer) => layer,
+                    None => {
+                        warn!(level = %level.level.identifier, "level has no {} layer, skipping its wall colliders", COLLISIONS_LAYER);
+                        return;
+                    },
+                };
+
+                let wall_rects = merge_walls(level_walls, width, height);
+                debug!(walls = level_walls.len(), colliders = wall_rects.len(), "merged wall tiles into colliders");
+
+                commands.entity(level_entity).with_children(|level| {
+                    // Spawn colliders for every rectangle..
+                    // Making the collider a child of the level serves two purposes:
+                    // 1. Adjusts the transforms to be relative to the level for free
+                    // 2. the colliders will be despawned automatically when levels unload
+                    for wall_rect in wall_rects {
+                        level
+                            .spawn()
+                            .insert(GlobalTransform::default())
+                            .insert(Transform {
+                                translation: Vec3::new(
+                                    (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32
+                                        / 2.0,
+                                    (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32
+                                        / 2.0,
+                                    0.0,
+                                ),
+                                scale: Vec3::new(
+                                    (wall_rect.right as f32 - wall_rect.left as f32 + 1.0)
+                                        * grid_size as f32,
+                                    (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.0)
+                                        * grid_size as f32,
+                                    0.0,
+                                ),
+                                ..Default::default()
+                            })
+                            .insert(Collider);
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{prelude::*, EntityInstance};
+
+use crate::{depth::YSort, dialogue::{Dialogue, dialogue_from_field}, item::{CollectItemEvent, Inventory, Item, ItemEffect, ItemRegistry, UnlockEvent, UseItemEvent}, player::Player, TILE_SIZE};
+use super::Collider;
+
+/// How close the player has to stand to a prop, device or speaker to interact with it
+pub const INTERACT_RANGE: f32 = TILE_SIZE * 1.5;
+
+#[derive(Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Door {
+    /// Item needed to open the door, read from the `lockedWith` field
+    pub locked_with: Option<Item>,
+    /// Collider keeping the player out while the door is locked
+    pub blocker: Option<Entity>,
+}
+
+impl From<EntityInstance> for Door {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let locked_with = entity_instance
+            .field_instances
+            .iter()
+            .find(|f| f.identifier == *"lockedWith")
+            .and_then(|f| match &f.value {
+                FieldValue::Enum(item) => item.as_deref().map(Item::new),
+                _ => None,
+            });
+
+        Door { locked_with, blocker: None }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct DoorBundle {
+    // Doors have no tile in the LDtk project, so they are drawn from the level tileset
+    #[sprite_sheet_bundle("sprites/TopDown_by_deepnight.png", 16.0, 16.0, 11, 11, 0.0, 12)]
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub door: Door,
+    pub y_sort: YSort,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Container {
+    pub label: String,
+    pub description: Option<String>,
+    pub loot: Vec<Item>,
+    pub looted: bool,
+}
+
+impl From<EntityInstance> for Container {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut container = Container::default();
+        for field in entity_instance.field_instances.iter() {
+            match (field.identifier.as_str(), &field.value) {
+                ("label", FieldValue::String(label)) => {
+                    container.label = label.clone().unwrap_or_default();
+                },
+                ("description", FieldValue::String(description)) => {
+                    container.description = description.clone();
+                },
+                ("loot", FieldValue::Enums(loot)) => {
+                    container.loot = loot.iter().flatten().map(|item| Item::new(item)).collect();
+                },
+                _ => (),
+            }
+        }
+
+        container
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct ContainerBundle {
+    // The LDtk tile for containers comes from the editor's internal icons,
+    // which aren't available at runtime
+    #[sprite_sheet_bundle("sprites/TopDown_by_deepnight.png", 16.0, 16.0, 11, 11, 0.0, 90)]
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub container: Container,
+    #[with(dialogue_from_field)]
+    pub dialogue: Dialogue,
+    pub y_sort: YSort,
+}
+
+/// Gives locked doors a collider next to them in the level, so they block like walls until opened
+pub fn spawn_door_blockers(
+    mut commands: Commands,
+    mut doors: Query<(&mut Door, &Transform, &Parent), Added<Door>>,
+) {
+    for (mut door, transform, parent) in doors.iter_mut() {
+        if door.locked_with.is_none() {
+            continue;
+        }
+
+        commands.entity(parent.get()).with_children(|level| {
+            let blocker = level
+                .spawn()
+                .insert(GlobalTransform::default())
+                .insert(Transform {
+                    translation: transform.translation,
+                    scale: Vec3::new(TILE_SIZE, TILE_SIZE, 0.0),
+                    ..Default::default()
+                })
+                .insert(Collider)
+                .id();
+            door.blocker = Some(blocker);
+        });
+    }
+}
+
+pub fn in_reach(player: &Transform, prop: &GlobalTransform) -> bool {
+    player.translation.truncate().distance(prop.translation().truncate()) <= INTERACT_RANGE
+}
+
+/// Uses the item named by a door's `lockedWith` field on it when the player presses E next to it
+pub fn open_doors(
+    player: Query<(Entity, &Transform, &Inventory), With<Player>>,
+    doors: Query<(Entity, &Door, &GlobalTransform)>,
+    registry: Res<ItemRegistry>,
+    keys: Res<Input<KeyCode>>,
+    mut use_item: EventWriter<UseItemEvent>,
+) {
+    if !keys.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    for (user, transform, inventory) in player.iter() {
+        for (door_entity, door, door_transform) in doors.iter() {
+            if !in_reach(transform, door_transform) {
+                continue;
+            }
+            let key = match &door.locked_with {
+                Some(key) => key,
+                None => continue,
+            };
+            let unlocks = registry
+                .get(key)
+                .map_or(false, |definition| definition.has_effect(|effect| matches!(effect, ItemEffect::Unlock)));
+            if unlocks && inventory.count(key) > 0 {
+                use_item.send(UseItemEvent { user, item: key.clone(), target: Some(door_entity) });
+            }
+        }
+    }
+}
+
+/// Opens the doors a fitting key was used on
+pub fn unlock_doors(
+    mut commands: Commands,
+    mut events: EventReader<UnlockEvent>,
+    mut doors: Query<(&mut Door, &mut Visibility)>,
+) {
+    for event in events.iter() {
+        if let Ok((mut door, mut visibility)) = doors.get_mut(event.target) {
+            if door.locked_with.as_ref() == Some(&event.key) {
+                open_door(&mut commands, &mut door, &mut visibility);
+            }
+        }
+    }
+}
+
+/// Opens a door regardless of its lock, removing the collider that kept the player out
+pub fn open_door(commands: &mut Commands, door: &mut Door, visibility: &mut Visibility) {
+    if let Some(blocker) = door.blocker.take() {
+        commands.entity(blocker).despawn_recursive();
+    }
+    door.locked_with = None;
+    visibility.is_visible = false;
+}
+
+/// Moves the loot of a container into the player's inventory when they press E next to it
+pub fn loot_containers(
+    player: Query<(Entity, &Transform), With<Player>>,
+    mut containers: Query<(&mut Container, &GlobalTransform)>,
+    mut collect: EventWriter<CollectItemEvent>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if !keys.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    for (collector, transform) in player.iter() {
+        for (mut container, container_transform) in containers.iter_mut() {
+            if container.looted || !in_reach(transform, container_transform) {
+                continue;
+            }
+
+            for item in container.loot.drain(..) {
+                collect.send(CollectItemEvent { collector, item, count: 1 });
+            }
+            container.looted = true;
+        }
+    }
+}
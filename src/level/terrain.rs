@@ -0,0 +1,303 @@
+use std::{collections::HashMap, time::Duration};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{TILE_SIZE, player::Player, combat::DamageEvent};
+
+use super::{Wall, COLLISIONS_LAYER};
+
+/// Damage dealt by a pit before the player is put back on solid ground
+const PIT_DAMAGE: i32 = 10;
+/// Damage dealt by a damage floor every time `DamageFloorTimer` finishes
+const FLOOR_DAMAGE: i32 = 5;
+const SLOW_MULTIPLIER: f32 = 0.5;
+
+/// Every value of the `Collisions` IntGrid layer
+/// The discriminants match the values defined in the LDtk project
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Component)]
+pub enum Terrain {
+    /// Solid wall, merged into `Collider`s
+    Wall = 1,
+    /// Front face of a wall, solid but drawn in front of the player
+    Face = 2,
+    /// Blocks walking, but not colliders so it does not stop anything flying over it
+    Water = 3,
+    /// Hurts the player and sends them back to the last safe tile
+    Pit = 4,
+    /// Slows the player down
+    Slow = 5,
+    /// Hurts the player periodically while standing on it
+    Damage = 6,
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 6] = [
+        Terrain::Wall,
+        Terrain::Face,
+        Terrain::Water,
+        Terrain::Pit,
+        Terrain::Slow,
+        Terrain::Damage,
+    ];
+
+    pub fn from_value(value: i32) -> Option<Terrain> {
+        Terrain::ALL.into_iter().find(|terrain| terrain.value() == value)
+    }
+
+    pub fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    /// Solid terrain becomes part of the merged wall colliders
+    pub fn is_solid(&self) -> bool {
+        matches!(self, Terrain::Wall | Terrain::Face)
+    }
+
+    /// Terrain the player can't walk onto
+    pub fn blocks_walking(&self) -> bool {
+        self.is_solid() || *self == Terrain::Water
+    }
+
+    pub fn speed_multiplier(&self) -> f32 {
+        match self {
+            Terrain::Slow => SLOW_MULTIPLIER,
+            _ => 1.0,
+        }
+    }
+}
+
+impl From<IntGridCell> for Terrain {
+    fn from(cell: IntGridCell) -> Self {
+        Terrain::from_value(cell.value).unwrap_or(Terrain::Wall)
+    }
+}
+
+#[derive(Clone, Debug, Bundle, LdtkIntCell)]
+pub struct TerrainBundle {
+    #[from_int_grid_cell]
+    pub terrain: Terrain,
+}
+
+/// Marks wall faces so depth sorting can draw them in front of characters
+#[derive(Copy, Clone, Eq, PartialEq, Default, Component, Debug)]
+pub struct WallFace;
+
+/// World-space lookup of every non-empty `Collisions` cell, keyed by world tile coordinates
+#[derive(Default, Debug)]
+pub struct TerrainMap(HashMap<IVec2, Terrain>);
+
+impl TerrainMap {
+    pub fn world_to_tile(position: Vec2) -> IVec2 {
+        (position / TILE_SIZE).floor().as_ivec2()
+    }
+
+    pub fn get(&self, position: Vec2) -> Option<Terrain> {
+        self.0.get(&TerrainMap::world_to_tile(position)).copied()
+    }
+
+    pub fn is_solid(&self, position: Vec2) -> bool {
+        self.get(position).map_or(false, |terrain| terrain.is_solid())
+    }
+}
+
+/// Whether a solid tile stands between `from` and `to`, the tiles at both ends don't count
+/// so light sources and walls facing them aren't hidden by themselves
+pub fn sight_blocked(from: Vec2, to: Vec2, is_solid: &impl Fn(Vec2) -> bool) -> bool {
+    let start = TerrainMap::world_to_tile(from);
+    let end = TerrainMap::world_to_tile(to);
+    // Sampling twice per tile is enough to never step over a wall
+    let steps = (from.distance(to) / (TILE_SIZE / 2.0)).ceil() as usize;
+    (1..steps).any(|step| {
+        let point = from.lerp(to, step as f32 / steps as f32);
+        let tile = TerrainMap::world_to_tile(point);
+        tile != start && tile != end && is_solid(point)
+    })
+}
+
+/// Multiplier applied to the player's speed by the terrain they're standing on
+#[derive(Deref, DerefMut, Clone, Copy, Component)]
+pub struct SpeedModifier(pub f32);
+
+impl Default for SpeedModifier {
+    fn default() -> Self {
+        SpeedModifier(1.0)
+    }
+}
+
+/// Last position the player stood on without a hazard, used to recover from pits
+#[derive(Deref, DerefMut, Clone, Copy, Default, Component)]
+pub struct SafePosition(pub Vec2);
+
+#[derive(Deref, DerefMut, Clone)]
+pub struct DamageFloorTimer(Timer);
+
+impl Default for DamageFloorTimer {
+    fn default() -> Self {
+        DamageFloorTimer(Timer::new(Duration::from_millis(750), true))
+    }
+}
+
+pub fn register_terrain(app: &mut App) {
+    for terrain in Terrain::ALL {
+        app.register_ldtk_int_cell_for_layer::<TerrainBundle>(COLLISIONS_LAYER, terrain.value());
+    }
+}
+
+/// Tags solid terrain with `Wall` so `spawn_wall_collision` merges it into colliders
+pub fn tag_solid_terrain(
+    mut commands: Commands,
+    terrain_query: Query<(Entity, &Terrain), Added<Terrain>>,
+) {
+    terrain_query.for_each(|(entity, terrain)| {
+        match terrain {
+            Terrain::Wall => {
+                commands.entity(entity).insert(Wall);
+            },
+            Terrain::Face => {
+                commands.entity(entity).insert(Wall).insert(WallFace);
+            },
+            _ => (),
+        }
+    })
+}
+
+pub fn build_terrain_map(
+    mut terrain_map: ResMut<TerrainMap>,
+    added: Query<(), Added<Terrain>>,
+    removed: RemovedComponents<Terrain>,
+    terrain_query: Query<(&Terrain, &GridCoords, &Parent)>,
+    transform_query: Query<(&Transform, Option<&Parent>), Without<Terrain>>,
+) {
+    if added.is_empty() && removed.iter().next().is_none() {
+        return;
+    }
+
+    terrain_map.0.clear();
+    terrain_query.for_each(|(terrain, grid_coords, parent)| {
+        // An intgrid tile's direct parent is the layer, and the layer's parent is the level
+        if let Ok((layer_transform, Some(level))) = transform_query.get(parent.get()) {
+            if let Ok((level_transform, _)) = transform_query.get(level.get()) {
+                let origin = (level_transform.translation + layer_transform.translation).truncate();
+                let tile = TerrainMap::world_to_tile(origin) + IVec2::new(grid_coords.x, grid_coords.y);
+                terrain_map.0.insert(tile, *terrain);
+            }
+        }
+    })
+}
+
+pub fn player_terrain_effects(
+    mut player: Query<(Entity, &mut Transform, &mut SpeedModifier, &mut SafePosition), With<Player>>,
+    terrain_map: Res<TerrainMap>,
+    mut damage_timer: ResMut<DamageFloorTimer>,
+    mut damage: EventWriter<DamageEvent>,
+    time: Res<Time>,
+) {
+    damage_timer.tick(time.delta());
+
+    for (entity, mut transform, mut speed, mut safe_position) in player.iter_mut() {
+        let terrain = terrain_map.get(transform.translation.truncate());
+        speed.0 = terrain.map_or(1.0, |terrain| terrain.speed_multiplier());
+
+        match terrain {
+            Some(Terrain::Pit) => {
+                damage.send(DamageEvent { target: entity, amount: PIT_DAMAGE, source: None });
+                transform.translation.x = safe_position.x;
+                transform.translation.y = safe_position.y;
+            },
+            Some(Terrain::Damage) => {
+                if damage_timer.just_finished() {
+                    damage.send(DamageEvent { target: entity, amount: FLOOR_DAMAGE, source: None });
+                }
+            },
+            _ => safe_position.0 = transform.translation.truncate(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use super::*;
+
+    /// A world holding the player on `terrain`, with the last safe position at the origin
+    fn standing_on(terrain: Terrain) -> (World, Entity) {
+        let position = Vec2::new(3.5, 2.5) * TILE_SIZE;
+        let mut terrain_map = TerrainMap::default();
+        terrain_map.0.insert(TerrainMap::world_to_tile(position), terrain);
+
+        let mut world = World::new();
+        world.insert_resource(terrain_map);
+        world.insert_resource(Time::default());
+        world.init_resource::<DamageFloorTimer>();
+        world.init_resource::<Events<DamageEvent>>();
+        let player = world
+            .spawn()
+            .insert(Player)
+            .insert(Transform::from_translation(position.extend(0.0)))
+            .insert(SpeedModifier::default())
+            .insert(SafePosition(Vec2::ZERO))
+            .id();
+        (world, player)
+    }
+
+    /// Runs the terrain effects once, `elapsed` after the previous run
+    fn step(world: &mut World, stage: &mut SystemStage, now: &mut Instant, elapsed: Duration) {
+        *now += elapsed;
+        world.resource_mut::<Time>().update_with_instant(*now);
+        stage.run(world);
+    }
+
+    fn damage_dealt(world: &World) -> Vec<i32> {
+        let events = world.resource::<Events<DamageEvent>>();
+        events.get_reader().iter(events).map(|event| event.amount).collect()
+    }
+
+    #[test]
+    fn unknown_values_fall_back_to_walls() {
+        for terrain in Terrain::ALL {
+            assert_eq!(Terrain::from_value(terrain.value()), Some(terrain));
+        }
+        assert_eq!(Terrain::from_value(0), None);
+        assert_eq!(Terrain::from(IntGridCell { value: 42 }), Terrain::Wall);
+    }
+
+    #[test]
+    fn only_slow_terrain_changes_speed() {
+        for terrain in Terrain::ALL {
+            let expected = if terrain == Terrain::Slow { SLOW_MULTIPLIER } else { 1.0 };
+            assert_eq!(terrain.speed_multiplier(), expected);
+        }
+
+        let (mut world, player) = standing_on(Terrain::Slow);
+        let mut stage = SystemStage::single_threaded().with_system(player_terrain_effects);
+        stage.run(&mut world);
+        assert_eq!(world.get::<SpeedModifier>(player).unwrap().0, SLOW_MULTIPLIER);
+    }
+
+    #[test]
+    fn pits_hurt_and_send_the_player_back_to_safety() {
+        let (mut world, player) = standing_on(Terrain::Pit);
+        let mut stage = SystemStage::single_threaded().with_system(player_terrain_effects);
+        stage.run(&mut world);
+
+        assert_eq!(world.get::<Transform>(player).unwrap().translation, Vec3::ZERO);
+        assert_eq!(damage_dealt(&world), vec![PIT_DAMAGE]);
+    }
+
+    #[test]
+    fn damage_floors_hurt_each_time_the_timer_finishes() {
+        let (mut world, _) = standing_on(Terrain::Damage);
+        let mut stage = SystemStage::single_threaded().with_system(player_terrain_effects);
+        let mut now = Instant::now();
+        world.resource_mut::<Time>().update_with_instant(now);
+
+        step(&mut world, &mut stage, &mut now, Duration::from_millis(500));
+        assert!(damage_dealt(&world).is_empty());
+
+        step(&mut world, &mut stage, &mut now, Duration::from_millis(300));
+        assert_eq!(damage_dealt(&world), vec![FLOOR_DAMAGE]);
+
+        step(&mut world, &mut stage, &mut now, Duration::from_millis(300));
+        assert_eq!(damage_dealt(&world), vec![FLOOR_DAMAGE]);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+use bevy_ecs_ldtk::GridCoords;
+
+/// Represents a wide wall that is 1 tile tall
+/// Used to spawn wall collisions
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+struct Plate {
+    left: i32,
+    right: i32,
+}
+
+/// A simple rectangle type representing a wall of any size
+/// All bounds are inclusive grid coordinates
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct Rect {
+    pub left: i32,
+    pub right: i32,
+    pub top: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    pub fn contains(&self, coords: GridCoords) -> bool {
+        coords.x >= self.left
+            && coords.x <= self.right
+            && coords.y >= self.bottom
+            && coords.y <= self.top
+    }
+}
+
+/// Merges the wall tiles of a `width` x `height` grid into as few rectangles as the
+/// plate algorithm allows. Every wall tile ends up in exactly one rectangle.
+pub fn merge_walls(walls: &HashSet<GridCoords>, width: i32, height: i32) -> Vec<Rect> {
+    // combine wall tiles into flat "plates" in each individual row
+    let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
+
+    for y in 0..height {
+        let mut row_plates: Vec<Plate> = Vec::new();
+        let mut plate_start = None;
+
+        // + 1 to the width so the algorithm "terminates" plates that touch the right
+        // edge
+        for x in 0..width + 1 {
+            match (plate_start, x < width && walls.contains(&GridCoords { x, y })) {
+                (Some(s), false) => {
+                    row_plates.push(Plate {
+                        left: s,
+                        right: x - 1,
+                    });
+                    plate_start = None;
+                }
+                (None, true) => plate_start = Some(x),
+                _ => (),
+            }
+        }
+
+        plate_stack.push(row_plates);
+    }
+
+    // combine "plates" into rectangles across multiple rows
+    let mut wall_rects: Vec<Rect> = Vec::new();
+    let mut previous_rects: HashMap<Plate, Rect> = HashMap::new();
+
+    // an extra empty row so the algorithm "terminates" the rects that touch the top
+    // edge
+    plate_stack.push(Vec::new());
+
+    for (y, row) in plate_stack.iter().enumerate() {
+        let mut current_rects: HashMap<Plate, Rect> = HashMap::new();
+        for plate in row {
+            if let Some(previous_rect) = previous_rects.remove(plate) {
+                current_rects.insert(
+                    *plate,
+                    Rect {
+                        top: previous_rect.top + 1,
+                        ..previous_rect
+                    },
+                );
+            } else {
+                current_rects.insert(
+                    *plate,
+                    Rect {
+                        bottom: y as i32,
+                        top: y as i32,
+                        left: plate.left,
+                        right: plate.right,
+                    },
+                );
+            }
+        }
+
+        // Any plates that weren't removed above have terminated
+        wall_rects.extend(previous_rects.values().copied());
+        previous_rects = current_rects;
+    }
+
+    wall_rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small xorshift generator so the property tests are reproducible without extra
+    /// dependencies
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: u64) -> u64 {
+            self.next() % max
+        }
+    }
+
+    fn random_walls(rng: &mut XorShift, width: i32, height: i32, density: u64) -> HashSet<GridCoords> {
+        let mut walls = HashSet::new();
+        for y in 0..height {
+            for x in 0..width {
+                if rng.below(100) < density {
+                    walls.insert(GridCoords { x, y });
+                }
+            }
+        }
+        walls
+    }
+
+    fn assert_exact_cover(walls: &HashSet<GridCoords>, width: i32, height: i32) {
+        let rects = merge_walls(walls, width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let coords = GridCoords { x, y };
+                let covering = rects.iter().filter(|rect| rect.contains(coords)).count();
+                if walls.contains(&coords) {
+                    assert_eq!(covering, 1, "wall tile {:?} covered {} times", coords, covering);
+                } else {
+                    assert_eq!(covering, 0, "floor tile {:?} covered {} times", coords, covering);
+                }
+            }
+        }
+        for rect in rects {
+            assert!(rect.left <= rect.right && rect.bottom <= rect.top, "degenerate {:?}", rect);
+            assert!(rect.left >= 0 && rect.right < width, "{:?} outside width {}", rect, width);
+            assert!(rect.bottom >= 0 && rect.top < height, "{:?} outside height {}", rect, height);
+        }
+    }
+
+    #[test]
+    fn empty_grid_has_no_rects() {
+        assert!(merge_walls(&HashSet::new(), 8, 8).is_empty());
+    }
+
+    #[test]
+    fn full_grid_is_one_rect() {
+        let walls = (0..5)
+            .flat_map(|y| (0..7).map(move |x| GridCoords { x, y }))
+            .collect();
+        assert_eq!(
+            merge_walls(&walls, 7, 5),
+            vec![Rect { left: 0, right: 6, top: 4, bottom: 0 }]
+        );
+    }
+
+    #[test]
+    fn walls_outside_the_grid_are_ignored() {
+        let walls = HashSet::from([GridCoords { x: 4, y: 0 }, GridCoords { x: 0, y: 4 }]);
+        assert!(merge_walls(&walls, 4, 4).is_empty());
+    }
+
+    #[test]
+    fn random_grids_are_covered_exactly_once() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let width = rng.below(24) as i32 + 1;
+            let height = rng.below(24) as i32 + 1;
+            let density = rng.below(101);
+            let walls = random_walls(&mut rng, width, height, density);
+            assert_exact_cover(&walls, width, height);
+        }
+    }
+}
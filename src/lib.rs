@@ -0,0 +1,172 @@
+use combat::CombatPlugin;
+use crafting::CraftingPlugin;
+use device::{DevicePlugin, ElevatorBundle};
+use dialogue::DialoguePlugin;
+use depth::DepthPlugin;
+use encounter::EncounterPlugin;
+use fog::FogPlugin;
+use projectile::{AmmoPickupBundle, ProjectilePlugin};
+use bevy_ecs_ldtk::{LdtkPlugin, LevelSelection, prelude::RegisterLdtkObjects};
+use enemy::{knight::KnightBundle, EnemyPlugin};
+use iyes_loopless::{prelude::AppLooplessStateExt, state::NextState};
+use level::{LevelPlugin, PlayerBundle, props::{DoorBundle, ContainerBundle}};
+use bevy::{prelude::*, asset::AssetServerSettings, log::LogPlugin, render::texture::ImageSettings, time::FixedTimestep, sprite::collide_aabb::{collide, Collision}, window::WindowId, winit::WinitWindows};
+use misc::state::GameState;
+use player::{AnimationTimer, Player, Direction, PlayerPlugin};
+use ui::{main_menu::MainMenuPlugin, combat_menu::CombatMenuPlugin, crafting::CraftingMenuPlugin, dialogue::DialogueBoxPlugin, hud::HudPlugin, inventory::InventoryMenuPlugin, minimap::MinimapPlugin, quest_log::QuestLogPlugin, world_map::WorldMapPlugin};
+use item::ItemPlugin;
+use lighting::{LightingPlugin, TorchBundle};
+use logging::LoggingPlugin;
+use quest::QuestPlugin;
+use save::SavePlugin;
+use trigger::{TriggerPlugin, TriggerZoneBundle};
+use winit::window::Icon;
+
+mod level;
+mod combat;
+#[cfg(debug_assertions)]
+mod console;
+mod crafting;
+#[cfg(debug_assertions)]
+mod debug;
+mod depth;
+mod device;
+mod dialogue;
+mod encounter;
+mod fog;
+#[cfg(debug_assertions)]
+mod free_camera;
+mod item;
+mod lighting;
+mod logging;
+mod map;
+mod projectile;
+mod player;
+mod quest;
+mod enemy;
+mod misc;
+mod save;
+mod trigger;
+mod ui;
+pub mod validate;
+
+const SPRITE_SCALE: f32 = 3.5;
+const TILE_SIZE: f32 = 16.0;
+const TILE_OFFSET: f32 = SPRITE_SCALE * TILE_SIZE;
+const PLAYER_SPEED: f32 = 2.0/3.0;
+const ICON_PATH: &str = "assets/sprites/icon.png";
+
+/// Builds the game and runs it until the window is closed
+pub fn run() {
+    let mut app = App::new();
+    app
+        .insert_resource(ImageSettings::default_nearest())
+        .insert_resource(Msaa { samples: 1 })
+        .insert_resource(WindowDescriptor {
+            title: "Concord".to_string(),
+            width: 1024.0,
+            height: 768.0,
+            ..Default::default()
+        })
+        // Picks up edits to the LDtk project while the game runs, see `level::hot_reload`
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: cfg!(debug_assertions),
+            ..Default::default()
+        })
+        .add_plugin(LoggingPlugin::from_env())
+        .add_plugins_with(DefaultPlugins, |group| group.disable::<LogPlugin>())
+        .add_loopless_state(GameState::LoadMenu)
+        .add_plugin(LdtkPlugin)
+        .add_startup_system(set_window_icon)
+        .add_enter_system(GameState::LoadGame, setup_camera)
+        .add_plugin(LevelPlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(MainMenuPlugin)
+        .add_plugin(EnemyPlugin)
+        .add_plugin(CombatPlugin)
+        .add_plugin(DepthPlugin)
+        .add_plugin(ProjectilePlugin)
+        .add_plugin(EncounterPlugin)
+        .add_plugin(CombatMenuPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(ItemPlugin)
+        .add_plugin(CraftingPlugin)
+        .add_plugin(InventoryMenuPlugin)
+        .add_plugin(CraftingMenuPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(DevicePlugin)
+        .add_plugin(DialoguePlugin)
+        .add_plugin(DialogueBoxPlugin)
+        .add_plugin(QuestPlugin)
+        .add_plugin(QuestLogPlugin)
+        .add_plugin(TriggerPlugin)
+        .add_plugin(LightingPlugin)
+        .add_plugin(FogPlugin)
+        .add_plugin(MinimapPlugin)
+        .add_plugin(WorldMapPlugin)
+        .insert_resource(LevelSelection::Index(0))
+        // `validate::ENTITY_SCHEMAS` lists the fields each of these reads, keep them in sync
+        .register_ldtk_entity::<PlayerBundle>("Player")
+        .register_ldtk_entity::<KnightBundle>("Knight")
+        .register_ldtk_entity::<DoorBundle>("Door")
+        .register_ldtk_entity::<ContainerBundle>("Container")
+        .register_ldtk_entity::<AmmoPickupBundle>("AmmoPickup")
+        .register_ldtk_entity::<ElevatorBundle>("ElevatorLink")
+        .register_ldtk_entity::<TriggerZoneBundle>("TriggerZone")
+        .register_ldtk_entity::<TorchBundle>("Torch");
+
+    // Playtesting tools, left out of release builds
+    #[cfg(debug_assertions)]
+    app
+        .add_plugin(debug::DebugPlugin)
+        .add_plugin(console::ConsolePlugin)
+        .add_plugin(free_camera::FreeCameraPlugin);
+
+    app.run();
+}
+
+
+#[derive(Component)]
+struct PlayerCamera;
+
+fn setup_camera(
+    mut commands: Commands,
+) {
+    commands
+        .spawn_bundle(Camera2dBundle {
+            projection: OrthographicProjection {
+                scale: 1.0/SPRITE_SCALE,
+                ..Default::default()
+            },
+            ..Default::default()
+        }) 
+        .insert(PlayerCamera);
+
+    commands.insert_resource(NextState(GameState::InGame));
+}
+
+/// Keeps the default icon when ours can't be used, it isn't worth failing to start over
+fn set_window_icon(
+    windows: NonSend<WinitWindows>,
+) {
+    let primary = match windows.get_window(WindowId::primary()) {
+        Some(primary) => primary,
+        None => {
+            warn!("No primary window to set the icon of");
+            return;
+        },
+    };
+    let image = match image::open(ICON_PATH) {
+        Ok(image) => image.into_rgba8(),
+        Err(error) => {
+            warn!("Failed to open {}: {}", ICON_PATH, error);
+            return;
+        },
+    };
+
+    let (width, height) = image.dimensions();
+    match Icon::from_rgba(image.into_raw(), width, height) {
+        Ok(icon) => primary.set_window_icon(Some(icon)),
+        Err(error) => warn!("{} isn't a valid icon: {}", ICON_PATH, error),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,457 @@
+use std::fs;
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        renderer::RenderAdapterInfo,
+        texture::ImageSampler,
+    },
+};
+use bevy_ecs_ldtk::{prelude::*, EntityInstance};
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+use crate::{misc::state::GameState, player::Player, level::terrain::{TerrainMap, sight_blocked}, depth::YSort, TILE_SIZE};
+
+const LIGHTING_SETTINGS_PATH: &str = "settings/lighting.json";
+/// Level field holding how dark the level is where no light reaches, from 0.0 to 1.0
+const DARKNESS_FIELD: &str = "Darkness";
+/// Above every LDtk layer and sorted sprite, below the camera
+const DARKNESS_Z: f32 = 100.0;
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(LightingSettings::load())
+            .add_startup_system(choose_renderer)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::InGame)
+                    .with_system(light_player)
+                    .with_system(light_torches)
+                    .with_system(spawn_darkness)
+                    .with_system(update_darkness)
+                    .into()
+            );
+    }
+}
+
+/// How the darkness overlay is drawn
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
+pub enum LightingRenderer {
+    /// Software rendering gets `Tiles`, everything else `LightMap`
+    Auto,
+    /// Smooth light from a texture rewritten every frame
+    LightMap,
+    /// One translucent sprite per tile, which never uploads textures and stays fast
+    /// under software rendering
+    Tiles,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct LightSettings {
+    /// In tiles
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LightingSettings {
+    pub renderer: LightingRenderer,
+    /// Whether walls from the `Collisions` layer block light
+    pub shadows: bool,
+    /// Resolution of the `LightMap` renderer
+    pub texels_per_tile: usize,
+    pub player_light: LightSettings,
+    pub torch_intensity: f32,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        LightingSettings {
+            renderer: LightingRenderer::Auto,
+            shadows: true,
+            texels_per_tile: 4,
+            player_light: LightSettings { radius: 5.0, intensity: 1.0 },
+            torch_intensity: 0.9,
+        }
+    }
+}
+
+impl LightingSettings {
+    /// Falls back to the default lighting when the settings can't be read
+    pub fn load() -> Self {
+        fs::read_to_string(LIGHTING_SETTINGS_PATH)
+            .ok()
+            .and_then(|settings| serde_json::from_str(&settings).ok())
+            .unwrap_or_else(|| {
+                warn!("Failed to read {}, using the default lighting", LIGHTING_SETTINGS_PATH);
+                LightingSettings::default()
+            })
+    }
+}
+
+/// Point light, in world units
+#[derive(Copy, Clone, PartialEq, Debug, Default, Component)]
+pub struct LightSource {
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+impl LightSource {
+    /// Light reaching a point `distance` away, fading out smoothly towards the radius
+    pub fn falloff(&self, distance: f32) -> f32 {
+        if distance >= self.radius {
+            return 0.0;
+        }
+        let fade = distance / self.radius;
+        self.intensity * (1.0 - fade * fade)
+    }
+}
+
+/// Wall torch lighting up `radius` tiles around it, read from its LDtk field
+#[derive(Copy, Clone, PartialEq, Debug, Default, Component)]
+pub struct Torch {
+    pub radius: f32,
+}
+
+impl From<EntityInstance> for Torch {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let radius = entity_instance
+            .field_instances
+            .iter()
+            .find(|f| f.identifier == *"radius")
+            .and_then(|f| match f.value {
+                FieldValue::Float(radius) => radius,
+                _ => None,
+            })
+            .unwrap_or(4.0);
+
+        Torch { radius }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct TorchBundle {
+    #[sprite_sheet_bundle]
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub torch: Torch,
+    pub y_sort: YSort,
+}
+
+/// Overlay darkening a level, spawned as a child of levels with a `Darkness` field above zero
+#[derive(Clone, Debug, Component)]
+pub struct Darkness {
+    /// Opacity of the overlay where no light reaches
+    pub ambient: f32,
+    pub columns: usize,
+    pub rows: usize,
+    pub texels_per_tile: usize,
+    /// Only set for the `LightMap` renderer
+    light_map: Option<Handle<Image>>,
+    /// Only filled for the `Tiles` renderer, row by row from the bottom
+    tiles: Vec<Entity>,
+}
+
+impl Darkness {
+    fn size(&self) -> Vec2 {
+        Vec2::new(self.columns as f32, self.rows as f32) * TILE_SIZE
+    }
+}
+
+/// Resolves `LightingRenderer::Auto` against the adapter bevy picked
+fn choose_renderer(
+    mut settings: ResMut<LightingSettings>,
+    adapter: Option<Res<RenderAdapterInfo>>,
+) {
+    if settings.renderer != LightingRenderer::Auto {
+        return;
+    }
+
+    let software = adapter.map_or(false, |adapter| adapter.device_type == wgpu::DeviceType::Cpu);
+    settings.renderer = if software { LightingRenderer::Tiles } else { LightingRenderer::LightMap };
+}
+
+fn light_player(
+    mut commands: Commands,
+    player: Query<Entity, Added<Player>>,
+    settings: Res<LightingSettings>,
+) {
+    for entity in player.iter() {
+        commands.entity(entity).insert(LightSource {
+            radius: settings.player_light.radius * TILE_SIZE,
+            intensity: settings.player_light.intensity,
+        });
+    }
+}
+
+fn light_torches(
+    mut commands: Commands,
+    torches: Query<(Entity, &Torch), Added<Torch>>,
+    settings: Res<LightingSettings>,
+) {
+    for (entity, torch) in torches.iter() {
+        commands.entity(entity).insert(LightSource {
+            radius: torch.radius * TILE_SIZE,
+            intensity: settings.torch_intensity,
+        });
+    }
+}
+
+fn spawn_darkness(
+    mut commands: Commands,
+    mut level_events: EventReader<LevelEvent>,
+    mut images: ResMut<Assets<Image>>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    levels: Res<Assets<LdtkLevel>>,
+    settings: Res<LightingSettings>,
+) {
+    for event in level_events.iter() {
+        let iid = match event {
+            LevelEvent::Spawned(iid) => iid,
+            _ => continue,
+        };
+        let found = level_query
+            .iter()
+            .filter_map(|(entity, handle)| levels.get(handle).map(|level| (entity, level)))
+            .find(|(_, level)| level.level.iid == *iid);
+        let (level_entity, level) = match found {
+            Some(found) => found,
+            None => continue,
+        };
+
+        let ambient = level
+            .level
+            .field_instances
+            .iter()
+            .find(|f| f.identifier == DARKNESS_FIELD)
+            .and_then(|f| match f.value {
+                FieldValue::Float(darkness) => darkness,
+                _ => None,
+            })
+            .unwrap_or(0.0)
+            .clamp(0.0, 1.0);
+        if ambient <= 0.0 {
+            continue;
+        }
+
+        let texels_per_tile = match settings.renderer {
+            LightingRenderer::Tiles => 1,
+            _ => settings.texels_per_tile.max(1),
+        };
+        let mut darkness = Darkness {
+            ambient,
+            columns: (level.level.px_wid as f32 / TILE_SIZE).ceil() as usize,
+            rows: (level.level.px_hei as f32 / TILE_SIZE).ceil() as usize,
+            texels_per_tile,
+            light_map: None,
+            tiles: Vec::new(),
+        };
+        let size = darkness.size();
+        let alpha = (ambient * 255.0) as u8;
+        let transform = Transform::from_translation((size / 2.0).extend(DARKNESS_Z));
+
+        commands.entity(level_entity).with_children(|level| {
+            let mut overlay = level.spawn();
+
+            if settings.renderer == LightingRenderer::Tiles {
+                overlay.insert_bundle(SpatialBundle { transform, ..Default::default() });
+                overlay.with_children(|overlay| {
+                    for row in 0..darkness.rows {
+                        for column in 0..darkness.columns {
+                            let center = (Vec2::new(column as f32, row as f32) + 0.5) * TILE_SIZE - size / 2.0;
+                            let tile = overlay
+                                .spawn_bundle(SpriteBundle {
+                                    sprite: Sprite {
+                                        color: Color::rgba(0.0, 0.0, 0.0, ambient),
+                                        custom_size: Some(Vec2::splat(TILE_SIZE)),
+                                        ..Default::default()
+                                    },
+                                    transform: Transform::from_translation(center.extend(0.0)),
+                                    ..Default::default()
+                                })
+                                .id();
+                            darkness.tiles.push(tile);
+                        }
+                    }
+                });
+            } else {
+                let mut image = Image::new_fill(
+                    Extent3d {
+                        width: (darkness.columns * texels_per_tile) as u32,
+                        height: (darkness.rows * texels_per_tile) as u32,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    &[0, 0, 0, alpha],
+                    TextureFormat::Rgba8UnormSrgb,
+                );
+                // Blend between texels so the light map doesn't look like the tile fallback
+                image.sampler_descriptor = ImageSampler::linear();
+                let light_map = images.add(image);
+
+                overlay.insert_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(size),
+                        ..Default::default()
+                    },
+                    texture: light_map.clone(),
+                    transform,
+                    ..Default::default()
+                });
+                darkness.light_map = Some(light_map);
+            }
+
+            overlay.insert(darkness);
+        });
+    }
+}
+
+/// Light reaching `point`, with `is_solid` telling which world positions block light when
+/// `shadows` is on. Lights always reach their own tile and the tile being lit.
+pub fn light_at(
+    point: Vec2,
+    lights: &[(Vec2, LightSource)],
+    shadows: bool,
+    is_solid: impl Fn(Vec2) -> bool,
+) -> f32 {
+    let mut light = 0.0;
+    for (position, source) in lights {
+        let distance = position.distance(point);
+        let amount = source.falloff(distance);
+        if amount <= 0.0 {
+            continue;
+        }
+        if shadows && sight_blocked(*position, point, &is_solid) {
+            continue;
+        }
+        light += amount;
+    }
+    light.min(1.0)
+}
+
+/// Redraws the darkness overlays, only when a light moved, came or went, or a new overlay or wall layout appeared
+fn update_darkness(
+    darkness_query: Query<(&Darkness, &GlobalTransform)>,
+    added_darkness: Query<(), Added<Darkness>>,
+    lights: Query<(&LightSource, &GlobalTransform)>,
+    changed_lights: Query<(), (With<LightSource>, Or<(Changed<LightSource>, Changed<GlobalTransform>)>)>,
+    removed_lights: RemovedComponents<LightSource>,
+    mut tiles: Query<&mut Sprite>,
+    mut images: ResMut<Assets<Image>>,
+    terrain_map: Res<TerrainMap>,
+    settings: Res<LightingSettings>,
+) {
+    let lights_changed = !changed_lights.is_empty() || removed_lights.iter().next().is_some();
+    if !lights_changed && added_darkness.is_empty() && !terrain_map.is_changed() {
+        return;
+    }
+
+    let lights: Vec<(Vec2, LightSource)> = lights
+        .iter()
+        .map(|(source, transform)| (transform.translation().truncate(), *source))
+        .collect();
+    let is_solid = |point: Vec2| terrain_map.is_solid(point);
+
+    for (darkness, transform) in darkness_query.iter() {
+        let origin = transform.translation().truncate() - darkness.size() / 2.0;
+        let texel_size = TILE_SIZE / darkness.texels_per_tile as f32;
+        let width = darkness.columns * darkness.texels_per_tile;
+        let height = darkness.rows * darkness.texels_per_tile;
+        let opacity = |column: usize, row: usize| {
+            let point = origin + (Vec2::new(column as f32, row as f32) + 0.5) * texel_size;
+            darkness.ambient * (1.0 - light_at(point, &lights, settings.shadows, is_solid))
+        };
+
+        if let Some(light_map) = darkness.light_map.as_ref().and_then(|handle| images.get_mut(handle)) {
+            for row in 0..height {
+                // Image rows go from the top down
+                let offset = (height - 1 - row) * width;
+                for column in 0..width {
+                    light_map.data[(offset + column) * 4 + 3] = (opacity(column, row) * 255.0) as u8;
+                }
+            }
+        }
+
+        for (index, tile) in darkness.tiles.iter().enumerate() {
+            if let Ok(mut sprite) = tiles.get_mut(*tile) {
+                let alpha = opacity(index % width, index / width);
+                // Leave unchanged tiles alone so they aren't re-extracted every frame
+                if (sprite.color.a() - alpha).abs() > 0.01 {
+                    sprite.color.set_a(alpha);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::AssetPlugin;
+    use super::*;
+
+    const TORCH: LightSource = LightSource { radius: 64.0, intensity: 1.0 };
+
+    #[test]
+    fn light_fades_towards_radius() {
+        let lights = [(Vec2::ZERO, TORCH)];
+        let near = light_at(Vec2::new(8.0, 0.0), &lights, false, |_| false);
+        let far = light_at(Vec2::new(48.0, 0.0), &lights, false, |_| false);
+
+        assert!(near > far);
+        assert!(far > 0.0);
+        assert_eq!(light_at(Vec2::new(64.0, 0.0), &lights, false, |_| false), 0.0);
+    }
+
+    #[test]
+    fn walls_cast_shadows_only_when_enabled() {
+        let lights = [(Vec2::new(8.0, 8.0), TORCH)];
+        // A wall one tile to the right of the light
+        let wall = |point: Vec2| TerrainMap::world_to_tile(point) == IVec2::new(1, 0);
+        let behind = Vec2::new(40.0, 8.0);
+
+        assert_eq!(light_at(behind, &lights, true, wall), 0.0);
+        assert!(light_at(behind, &lights, false, wall) > 0.0);
+        // The wall itself is still lit
+        assert!(light_at(Vec2::new(24.0, 8.0), &lights, true, wall) > 0.0);
+    }
+
+    #[test]
+    fn darkness_is_only_redrawn_when_lights_move() {
+        let mut app = App::new();
+        app
+            .add_plugin(AssetPlugin)
+            .add_asset::<Image>()
+            .insert_resource(TerrainMap::default())
+            .insert_resource(LightingSettings::default())
+            .add_system(update_darkness);
+
+        let tile = app.world.spawn().insert(Sprite::default()).id();
+        // A single tile lit by a torch standing on it
+        app.world
+            .spawn()
+            .insert(Darkness { ambient: 1.0, columns: 1, rows: 1, texels_per_tile: 1, light_map: None, tiles: vec![tile] })
+            .insert(GlobalTransform::from_translation(Vec3::splat(TILE_SIZE / 2.0)));
+        let torch = app.world
+            .spawn()
+            .insert(TORCH)
+            .insert(GlobalTransform::from_translation(Vec3::splat(TILE_SIZE / 2.0)))
+            .id();
+        let alpha = |app: &App| app.world.get::<Sprite>(tile).unwrap().color.a();
+
+        app.update();
+        assert!(alpha(&app) < 0.01);
+
+        // Nothing moved, so whatever the tile shows is left alone
+        app.world.get_mut::<Sprite>(tile).unwrap().color.set_a(0.5);
+        app.update();
+        assert_eq!(alpha(&app), 0.5);
+
+        *app.world.get_mut::<GlobalTransform>(torch).unwrap() = GlobalTransform::from_translation(Vec3::splat(1000.0));
+        app.update();
+        assert_eq!(alpha(&app), 1.0);
+    }
+}
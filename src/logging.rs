@@ -0,0 +1,123 @@
+use std::{env, fs::File, path::PathBuf, sync::Mutex};
+use bevy::prelude::*;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+/// Same as bevy's own log plugin, which this one replaces
+const DEFAULT_FILTER: &str = "info,wgpu=error";
+const FILTER_VARIABLE: &str = "CONCORDE_LOG";
+const FILE_VARIABLE: &str = "CONCORDE_LOG_FILE";
+const FILTER_FLAG: &str = "--log";
+const FILE_FLAG: &str = "--log-file";
+
+/// Installs the global `tracing` subscriber, must be added before `DefaultPlugins` with
+/// bevy's `LogPlugin` disabled
+///
+/// Events are filtered with `EnvFilter` directives and gameplay modules log under their path,
+/// so `--log info,concorde::enemy=trace` only makes enemies verbose.
+/// The command line takes precedence over `CONCORDE_LOG` and `CONCORDE_LOG_FILE`
+pub struct LoggingPlugin {
+    pub filter: String,
+    /// Also writes every event as one JSON object per line, to send along with playtest reports
+    pub file: Option<PathBuf>,
+}
+
+impl LoggingPlugin {
+    pub fn from_env() -> Self {
+        LoggingPlugin::from_args(env::args().skip(1), |name| env::var(name).ok())
+    }
+
+    fn from_args(args: impl Iterator<Item = String>, var: impl Fn(&str) -> Option<String>) -> Self {
+        let mut filter = None;
+        let mut file = None;
+
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let target = match flag.as_str() {
+                FILTER_FLAG => &mut filter,
+                FILE_FLAG => &mut file,
+                _ => continue,
+            };
+            // Accept both `--log debug` and `--log=debug`
+            let value = value.or_else(|| args.next_if(|next| !next.starts_with("--")));
+            if value.is_some() {
+                *target = value;
+            }
+        }
+
+        LoggingPlugin {
+            filter: filter
+                .or_else(|| var(FILTER_VARIABLE))
+                .unwrap_or_else(|| DEFAULT_FILTER.to_string()),
+            file: file.or_else(|| var(FILE_VARIABLE)).map(PathBuf::from),
+        }
+    }
+}
+
+impl Plugin for LoggingPlugin {
+    fn build(&self, _app: &mut App) {
+        // Nothing can be logged before the subscriber exists, so setup problems go to stderr
+        let filter = EnvFilter::try_new(&self.filter).unwrap_or_else(|error| {
+            eprintln!("Invalid log filter \"{}\" ({}), using \"{}\"", self.filter, error, DEFAULT_FILTER);
+            EnvFilter::new(DEFAULT_FILTER)
+        });
+
+        let json = self.file.as_ref().and_then(|path| match File::create(path) {
+            Ok(file) => Some(
+                fmt::layer()
+                    .json()
+                    .with_ansi(false)
+                    .with_current_span(true)
+                    .with_writer(Mutex::new(file))
+            ),
+            Err(error) => {
+                eprintln!("Failed to create log file {}: {}", path.display(), error);
+                None
+            }
+        });
+
+        let subscriber = tracing_subscriber::registry()
+            .with(filter)
+            .with(fmt::layer())
+            .with(json);
+        if subscriber.try_init().is_err() {
+            eprintln!("A global logger was already set, LoggingPlugin has no effect");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(str::to_string)
+    }
+
+    #[test]
+    fn command_line_overrides_environment() {
+        let var = |name: &str| match name {
+            FILTER_VARIABLE => Some("warn".to_string()),
+            FILE_VARIABLE => Some("env.log".to_string()),
+            _ => None,
+        };
+
+        let plugin = LoggingPlugin::from_args(args("--log concorde::enemy=trace --log-file=playtest.json"), var);
+        assert_eq!(plugin.filter, "concorde::enemy=trace");
+        assert_eq!(plugin.file, Some(PathBuf::from("playtest.json")));
+
+        let plugin = LoggingPlugin::from_args(args(""), var);
+        assert_eq!(plugin.filter, "warn");
+        assert_eq!(plugin.file, Some(PathBuf::from("env.log")));
+    }
+
+    #[test]
+    fn defaults_without_flags_or_environment() {
+        let plugin = LoggingPlugin::from_args(args("--log --fullscreen"), |_| None);
+        assert_eq!(plugin.filter, DEFAULT_FILTER);
+        assert_eq!(plugin.file, None);
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use bevy::{prelude::*, render::render_resource::{Extent3d, TextureDimension, TextureFormat}};
+use bevy_ecs_ldtk::{prelude::*, ldtk::Level};
+use crate::{level::{COLLISIONS_LAYER, terrain::Terrain}, fog::VisibilityGrid};
+
+/// Layers whose tiles tell the walkable parts of a level apart from the void around it,
+/// since empty `Collisions` cells are both
+const GROUND_LAYERS: [&str; 2] = ["Default_ground", "Custom_grounds"];
+
+pub const PLAYER_COLOR: [u8; 4] = [255, 255, 255, 255];
+pub const ENEMY_COLOR: [u8; 4] = [220, 40, 40, 255];
+pub const DOOR_COLOR: [u8; 4] = [200, 130, 60, 255];
+pub const CONTAINER_COLOR: [u8; 4] = [255, 209, 41, 255];
+pub const CONNECTION_COLOR: [u8; 4] = [169, 70, 176, 255];
+
+/// What one tile of a level looks like on the maps
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MapCell {
+    Void,
+    Floor,
+    Wall,
+    Water,
+    Hazard,
+}
+
+impl MapCell {
+    fn from_collision(value: i32, ground: bool) -> Self {
+        match Terrain::from_value(value) {
+            Some(Terrain::Wall | Terrain::Face) => MapCell::Wall,
+            Some(Terrain::Water) => MapCell::Water,
+            Some(Terrain::Pit | Terrain::Damage) => MapCell::Hazard,
+            Some(Terrain::Slow) => MapCell::Floor,
+            None if ground => MapCell::Floor,
+            None => MapCell::Void,
+        }
+    }
+
+    pub fn color(&self) -> [u8; 4] {
+        match self {
+            MapCell::Void => [0, 0, 0, 0],
+            MapCell::Floor => [70, 62, 58, 230],
+            MapCell::Wall => [150, 150, 160, 255],
+            MapCell::Water => [47, 111, 209, 255],
+            MapCell::Hazard => [160, 60, 30, 255],
+        }
+    }
+}
+
+/// A level's `Collisions` layer reduced to one cell per tile, in LDtk grid coordinates
+/// with y going down
+#[derive(Clone, Debug)]
+pub struct LevelMap {
+    pub iid: String,
+    pub identifier: String,
+    /// Top-left corner of the level in the LDtk world, in tiles
+    pub world_origin: IVec2,
+    pub columns: i32,
+    pub rows: i32,
+    pub grid_size: i32,
+    cells: Vec<MapCell>,
+}
+
+impl LevelMap {
+    pub fn from_level(level: &Level) -> Option<Self> {
+        let layers = level.layer_instances.as_ref()?;
+        let collisions = layers.iter().find(|layer| layer.identifier == COLLISIONS_LAYER)?;
+        let (columns, rows, grid_size) = (collisions.c_wid, collisions.c_hei, collisions.grid_size);
+
+        let mut ground = vec![false; (columns * rows).max(0) as usize];
+        for layer in layers.iter().filter(|layer| GROUND_LAYERS.contains(&layer.identifier.as_str())) {
+            for tile in layer.grid_tiles.iter().chain(layer.auto_layer_tiles.iter()) {
+                let cell = tile.px / grid_size;
+                if cell.x >= 0 && cell.y >= 0 && cell.x < columns && cell.y < rows {
+                    ground[(cell.y * columns + cell.x) as usize] = true;
+                }
+            }
+        }
+
+        let cells = collisions
+            .int_grid_csv
+            .iter()
+            .zip(ground)
+            .map(|(&value, ground)| MapCell::from_collision(value, ground))
+            .collect();
+
+        Some(LevelMap {
+            iid: level.iid.clone(),
+            identifier: level.identifier.clone(),
+            world_origin: IVec2::new(level.world_x, level.world_y) / grid_size,
+            columns,
+            rows,
+            grid_size,
+            cells,
+        })
+    }
+
+    pub fn contains(&self, tile: IVec2) -> bool {
+        tile.x >= 0 && tile.y >= 0 && tile.x < self.columns && tile.y < self.rows
+    }
+
+    pub fn cell(&self, tile: IVec2) -> MapCell {
+        if !self.contains(tile) {
+            return MapCell::Void;
+        }
+        self.cells[(tile.y * self.columns + tile.x) as usize]
+    }
+
+    /// Tile under a point relative to the level's bottom-left corner, in LDtk grid coordinates
+    pub fn tile_at(&self, position: Vec2) -> IVec2 {
+        let tile = (position / self.grid_size as f32).floor().as_ivec2();
+        IVec2::new(tile.x, self.rows - 1 - tile.y)
+    }
+
+    /// Whether the tile has been seen, always true without fog of war
+    pub fn is_explored(&self, tile: IVec2, explored: Option<&VisibilityGrid>) -> bool {
+        explored.map_or(true, |grid| grid.is_revealed(IVec2::new(tile.x, self.rows - 1 - tile.y)))
+    }
+
+    /// Draws the level with its top-left corner at `offset`, leaving unexplored tiles out
+    pub fn paint(&self, canvas: &mut MapCanvas, offset: IVec2, explored: Option<&VisibilityGrid>) {
+        for y in 0..self.rows {
+            for x in 0..self.columns {
+                let tile = IVec2::new(x, y);
+                let cell = self.cell(tile);
+                if cell != MapCell::Void && self.is_explored(tile, explored) {
+                    canvas.set(offset + tile, cell.color());
+                }
+            }
+        }
+    }
+}
+
+/// World tile of every LDtk entity, keyed by iid
+pub fn entity_world_tiles(levels: &[Level]) -> HashMap<String, IVec2> {
+    let mut tiles = HashMap::new();
+    for level in levels {
+        let map = match LevelMap::from_level(level) {
+            Some(map) => map,
+            None => continue,
+        };
+        for layer in level.layer_instances.iter().flatten() {
+            for entity in layer.entity_instances.iter() {
+                tiles.insert(entity.iid.clone(), map.world_origin + entity.grid);
+            }
+        }
+    }
+    tiles
+}
+
+/// Pairs of world tiles linked by an entity reference, such as elevators and their destination
+pub fn connections(levels: &[Level]) -> Vec<(IVec2, IVec2)> {
+    let tiles = entity_world_tiles(levels);
+    let mut connections = Vec::new();
+    for level in levels {
+        for layer in level.layer_instances.iter().flatten() {
+            for entity in layer.entity_instances.iter() {
+                let from = match tiles.get(&entity.iid) {
+                    Some(from) => *from,
+                    None => continue,
+                };
+                for field in entity.field_instances.iter() {
+                    let references = match &field.value {
+                        FieldValue::EntityRef(reference) => vec![reference.clone()],
+                        FieldValue::EntityRefs(references) => references.clone(),
+                        _ => continue,
+                    };
+                    let targets = references
+                        .into_iter()
+                        .flatten()
+                        // References within a level don't tell how levels connect
+                        .filter(|reference| reference.level_iid != level.iid)
+                        .filter_map(|reference| tiles.get(&reference.entity_iid));
+                    for to in targets {
+                        connections.push((from, *to));
+                    }
+                }
+            }
+        }
+    }
+    connections
+}
+
+/// RGBA pixels for the maps, one pixel per tile with y going down like LDtk
+#[derive(Clone, Debug)]
+pub struct MapCanvas {
+    pub width: i32,
+    pub height: i32,
+    data: Vec<u8>,
+}
+
+impl MapCanvas {
+    pub fn new(width: i32, height: i32) -> Self {
+        MapCanvas {
+            width: width.max(1),
+            height: height.max(1),
+            data: vec![0; (width.max(1) * height.max(1) * 4) as usize],
+        }
+    }
+
+    pub fn get(&self, pixel: IVec2) -> Option<[u8; 4]> {
+        if pixel.x < 0 || pixel.y < 0 || pixel.x >= self.width || pixel.y >= self.height {
+            return None;
+        }
+        let index = ((pixel.y * self.width + pixel.x) * 4) as usize;
+        let mut color = [0; 4];
+        color.copy_from_slice(&self.data[index..index + 4]);
+        Some(color)
+    }
+
+    pub fn set(&mut self, pixel: IVec2, color: [u8; 4]) {
+        if pixel.x < 0 || pixel.y < 0 || pixel.x >= self.width || pixel.y >= self.height {
+            return;
+        }
+        let index = ((pixel.y * self.width + pixel.x) * 4) as usize;
+        self.data[index..index + 4].copy_from_slice(&color);
+    }
+
+    pub fn line(&mut self, from: IVec2, to: IVec2, color: [u8; 4]) {
+        // Bresenham's line algorithm
+        let delta = IVec2::new((to.x - from.x).abs(), -(to.y - from.y).abs());
+        let step = IVec2::new((to.x - from.x).signum(), (to.y - from.y).signum());
+        let mut error = delta.x + delta.y;
+        let mut pixel = from;
+        loop {
+            self.set(pixel, color);
+            if pixel == to {
+                break;
+            }
+            let doubled = error * 2;
+            if doubled >= delta.y {
+                error += delta.y;
+                pixel.x += step.x;
+            }
+            if doubled <= delta.x {
+                error += delta.x;
+                pixel.y += step.y;
+            }
+        }
+    }
+
+    pub fn into_image(self) -> Image {
+        Image::new(
+            Extent3d {
+                width: self.width as u32,
+                height: self.height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.data,
+            TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_reach_both_ends() {
+        let mut canvas = MapCanvas::new(8, 8);
+        canvas.line(IVec2::new(1, 6), IVec2::new(6, 2), CONNECTION_COLOR);
+
+        assert_eq!(canvas.get(IVec2::new(1, 6)), Some(CONNECTION_COLOR));
+        assert_eq!(canvas.get(IVec2::new(6, 2)), Some(CONNECTION_COLOR));
+        assert_eq!(canvas.get(IVec2::new(0, 0)), Some([0; 4]));
+        assert_eq!(canvas.get(IVec2::new(8, 0)), None);
+    }
+
+    #[test]
+    fn tiles_count_rows_from_the_top() {
+        let map = LevelMap {
+            iid: String::new(),
+            identifier: String::new(),
+            world_origin: IVec2::ZERO,
+            columns: 4,
+            rows: 3,
+            grid_size: 16,
+            cells: vec![MapCell::Floor; 12],
+        };
+
+        assert_eq!(map.tile_at(Vec2::new(8.0, 8.0)), IVec2::new(0, 2));
+        assert_eq!(map.tile_at(Vec2::new(56.0, 40.0)), IVec2::new(3, 0));
+    }
+}
@@ -7,3 +7,18 @@ pub enum PlayerLabel {
     Movement,
 }
 
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CombatLabel {
+    Hitbox,
+    Damage,
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LevelLabel {
+    Terrain,
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TriggerLabel {
+    Detect,
+}
\ No newline at end of file
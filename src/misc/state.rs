@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum GameState {
     MainMenu,
@@ -5,4 +7,40 @@ pub enum GameState {
     InGame,
     LoadGame,
     Paused,
-}
\ No newline at end of file
+    Combat,
+    Inventory,
+    Crafting,
+    Dialogue,
+    QuestLog,
+    WorldMap,
+    FreeCamera,
+}
+
+impl GameState {
+    pub const ALL: [GameState; 12] = [
+        GameState::MainMenu,
+        GameState::LoadMenu,
+        GameState::InGame,
+        GameState::LoadGame,
+        GameState::Paused,
+        GameState::Combat,
+        GameState::Inventory,
+        GameState::Crafting,
+        GameState::Dialogue,
+        GameState::QuestLog,
+        GameState::WorldMap,
+        GameState::FreeCamera,
+    ];
+}
+
+/// Parses the variant name, ignoring case
+impl FromStr for GameState {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        GameState::ALL
+            .into_iter()
+            .find(|state| format!("{:?}", state).eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown game state {}", name))
+    }
+}
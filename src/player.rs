@@ -1,7 +1,7 @@
 use std::time::Duration;
 use iyes_loopless::prelude::*;
 use bevy::{prelude::*, sprite::collide_aabb::{collide, Collision}};
-use crate::{SPRITE_SCALE, TILE_OFFSET, PLAYER_SPEED, PlayerCamera, misc::{label::PlayerLabel, state::GameState}, level::Collider, TILE_SIZE};
+use crate::{SPRITE_SCALE, TILE_OFFSET, PLAYER_SPEED, PlayerCamera, misc::{label::PlayerLabel, state::GameState}, level::{Collider, terrain::{SpeedModifier, TerrainMap}}, combat::{Knockback, not_hit_stopped}, TILE_SIZE};
 
 pub struct PlayerPlugin;
 
@@ -25,6 +25,7 @@ impl Plugin for PlayerPlugin {
                 player_movement
                     .run_in_state(GameState::InGame)
                     .run_if(frame_tick)
+                    .run_if(not_hit_stopped)
                     .after(PlayerLabel::Collision)
                     .label(PlayerLabel::Movement)
             )
@@ -40,13 +41,45 @@ impl Plugin for PlayerPlugin {
                     .run_in_state(GameState::InGame)
                     .after(PlayerLabel::Movement)
             );
+
+        // Only the debug tools turn noclip on, and they are left out of release builds
+        #[cfg(debug_assertions)]
+        app
+            .add_event::<NoClipEvent>()
+            .add_system(set_noclip);
     }
 }
 
 #[derive(Component, Default, Clone)]
 pub struct Player;
 
-#[derive(PartialEq, Clone, Default, Component)]
+/// Lets the player walk through colliders and blocking terrain, toggled from the console or with F2
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug, Component)]
+pub struct NoClip;
+
+/// Sent to turn `NoClip` on or off for `target`, only the debug tools send it
+#[cfg(debug_assertions)]
+pub struct NoClipEvent {
+    pub target: Entity,
+    pub enabled: bool,
+}
+
+#[cfg(debug_assertions)]
+fn set_noclip(
+    mut commands: Commands,
+    mut events: EventReader<NoClipEvent>,
+) {
+    for event in events.iter() {
+        if event.enabled {
+            commands.entity(event.target).insert(NoClip);
+        } else {
+            commands.entity(event.target).remove::<NoClip>();
+        }
+        info!(enabled = event.enabled, "noclip toggled");
+    }
+}
+
+#[derive(PartialEq, Clone, Default, Debug, Component)]
 pub enum Direction {
     Left,
     Right,
@@ -55,8 +88,21 @@ pub enum Direction {
     #[default] None
 }
 
+impl Direction {
+    /// Unit vector pointing in this direction, zero for `Direction::None`
+    pub fn vector(&self) -> Vec2 {
+        match self {
+            Direction::Left => Vec2::new(-1.0, 0.0),
+            Direction::Right => Vec2::new(1.0, 0.0),
+            Direction::Up => Vec2::new(0.0, 1.0),
+            Direction::Down => Vec2::new(0.0, -1.0),
+            Direction::None => Vec2::ZERO,
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Component)]
-pub struct Orientation(Direction); 
+pub struct Orientation(pub Direction); 
 
 impl Default for Orientation {
     fn default() -> Self {
@@ -82,7 +128,7 @@ impl Default for FrameTimer {
     }
 }
 
-fn camera_on_player(
+pub fn camera_on_player(
     player: Query<&Transform, With<Player>>,
     mut camera: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>,
 ) {
@@ -122,10 +168,9 @@ fn animate_player_movement(
                 }
             },
             Direction::Down => {
-                println!("Down animation detected");
                 match sprite.index {
-                    0..=2 => {sprite.index += 1; println!("Sprite Increased");},
-                    _ => {sprite.index = 0; println!("Sprite reset to default");}
+                    0..=2 => sprite.index += 1,
+                    _ => sprite.index = 0
                 }
             },
             Direction::None => match orientation.0 {
@@ -146,6 +191,8 @@ fn animate_player_movement(
                 _ => sprite.index 
             }
         }
+
+        trace!(?direction, frame = sprite.index, "animated player");
     })
 }
 
@@ -178,70 +225,80 @@ fn movement_input(
 }
 
 fn collision_check(
-    mut player: Query<(&Transform, &mut Direction), With<Player>>,
-    collider : Query<&Transform, (With<Collider>, Without<Player>)>
+    mut player: Query<(&Transform, &mut Direction, &SpeedModifier), (With<Player>, Without<NoClip>)>,
+    collider : Query<&Transform, (With<Collider>, Without<Player>)>,
+    terrain_map: Res<TerrainMap>,
 ) {
-    for (transform, mut direction) in player.iter_mut() {    
-        let new_translation = match *direction {
-            Direction::Left => Vec3::new(transform.translation.x - PLAYER_SPEED, transform.translation.y, transform.translation.z),
-            Direction::Right => Vec3::new(transform.translation.x + PLAYER_SPEED, transform.translation.y, transform.translation.z),
-            Direction::Up => Vec3::new(transform.translation.x, transform.translation.y + PLAYER_SPEED, transform.translation.z),
-            Direction::Down => Vec3::new(transform.translation.x, transform.translation.y - PLAYER_SPEED, transform.translation.z),
-            Direction::None => transform.translation,
-        };
-        collider.for_each(|(collider)| {
-                let collision = collide(
-                    new_translation,
-                    Vec2::splat(TILE_SIZE - 1.0),
-                    collider.translation,
-                    collider.scale.truncate(),
-                );
-            
-                match collision {
-                    Some(collision) => {
-                        match collision {
-                        Collision::Left => {
-                            if *direction == Direction::Right {
-                                *direction = Direction::None;
-                            }
-                        },
-                        Collision::Right => {
-                            if *direction == Direction::Left {
-                                *direction = Direction::None;
-                            }
-                        },
-                        Collision::Top => {
-                            if *direction == Direction::Down {
-                                *direction = Direction::None;
-                            }
-                        },
-                        Collision::Bottom => {
-                            if *direction == Direction::Up {
-                                *direction = Direction::None;
-                            }
-                        },
-                        Collision::Inside => (),
-                    }
-                },
-                None => (),
-            }
-        })
+    for (transform, mut direction, speed_modifier) in player.iter_mut() {    
+        let speed = PLAYER_SPEED * speed_modifier.0;
+        if !can_move(transform.translation, &direction, speed, collider.iter(), &terrain_map) {
+            *direction = Direction::None;
+        }
     }
 }
 
+/// Whether a character at `translation` can move `distance` towards `direction`
+/// without walking into a collider or terrain that blocks walking
+pub fn can_move<'a>(
+    translation: Vec3,
+    direction: &Direction,
+    distance: f32,
+    colliders: impl IntoIterator<Item = &'a Transform>,
+    terrain_map: &TerrainMap,
+) -> bool {
+    let new_translation = translation + (direction.vector() * distance).extend(0.0);
+
+    // Terrain such as water blocks walking without being a collider,
+    // so check every corner of the next bounding box against the terrain map
+    let half_size = (TILE_SIZE - 1.0) / 2.0;
+    let blocked = [
+        Vec2::new(-half_size, -half_size),
+        Vec2::new(-half_size, half_size),
+        Vec2::new(half_size, -half_size),
+        Vec2::new(half_size, half_size),
+    ].iter().any(|corner| {
+        terrain_map
+            .get(new_translation.truncate() + *corner)
+            .map_or(false, |terrain| terrain.blocks_walking())
+    });
+    if blocked {
+        return false;
+    }
+
+    colliders.into_iter().all(|collider| {
+        let collision = collide(
+            new_translation,
+            Vec2::splat(TILE_SIZE - 1.0),
+            collider.translation,
+            collider.scale.truncate(),
+        );
+
+        match collision {
+            Some(Collision::Left) => *direction != Direction::Right,
+            Some(Collision::Right) => *direction != Direction::Left,
+            Some(Collision::Top) => *direction != Direction::Down,
+            Some(Collision::Bottom) => *direction != Direction::Up,
+            Some(Collision::Inside) | None => true,
+        }
+    })
+}
+
 fn player_movement(
-    mut query: Query<(&mut Transform, &mut Direction, &mut Orientation), With<Player>>,
+    mut query: Query<(&mut Transform, &mut Direction, &mut Orientation, &SpeedModifier), (With<Player>, Without<Knockback>)>,
 ) {
-    query.for_each_mut(|(mut transform, mut direction, mut orientation)| {
+    query.for_each_mut(|(mut transform, mut direction, mut orientation, speed_modifier)| {
+        let speed = PLAYER_SPEED * speed_modifier.0;
         match *direction {
-            Direction::Left => transform.translation.x -= PLAYER_SPEED,
-            Direction::Right => transform.translation.x += PLAYER_SPEED,
-            Direction::Up => transform.translation.y += PLAYER_SPEED,
-            Direction::Down => transform.translation.y -= PLAYER_SPEED,
+            Direction::Left => transform.translation.x -= speed,
+            Direction::Right => transform.translation.x += speed,
+            Direction::Up => transform.translation.y += speed,
+            Direction::Down => transform.translation.y -= speed,
             _ => (),
         };
             
-        orientation.0 = direction.clone();
+        if *direction != Direction::None {
+            orientation.0 = direction.clone();
+        }
         *direction = Direction::None;
     })
 }
@@ -0,0 +1,327 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy_ecs_ldtk::{prelude::*, EntityInstance};
+use iyes_loopless::prelude::*;
+use crate::{misc::{label::CombatLabel, state::GameState}, player::{Orientation, Player}, level::Collider, combat::DamageEvent, item::PowerBuff, enemy::settings::EnemyKind, depth::YSort, TILE_SIZE};
+
+/// Projectiles are spawned once and recycled, firing never allocates entities
+const POOL_SIZE: usize = 16;
+const PROJECTILE_SPEED: f32 = 240.0;
+const PROJECTILE_RANGE: f32 = 160.0;
+const PROJECTILE_DAMAGE: i32 = 8;
+const PROJECTILE_SIZE: f32 = 4.0;
+const PROJECTILE_COLOR: Color = Color::rgb(1.0, 0.9, 0.5);
+/// Matches the `max` of the `ammo` field in the LDtk project
+const MAX_AMMO: i32 = 50;
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_enter_system(GameState::LoadGame, spawn_projectile_pool)
+            .add_system(
+                fire_projectile
+                    .run_in_state(GameState::InGame)
+            )
+            .add_system(
+                move_projectiles
+                    .run_in_state(GameState::InGame)
+                    .before(CombatLabel::Damage)
+            )
+            .add_system(
+                pick_up_ammo
+                    .run_in_state(GameState::InGame)
+            );
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Component)]
+pub struct Ammo {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Default for Ammo {
+    fn default() -> Self {
+        Ammo { current: 0, max: MAX_AMMO }
+    }
+}
+
+/// Reads the `ammo` field of the LDtk `Player` entity
+pub fn ammo_from_field(entity_instance: &EntityInstance) -> Ammo {
+    let current = entity_instance
+        .field_instances
+        .iter()
+        .find(|f| f.identifier == *"ammo")
+        .and_then(|f| match f.value {
+            FieldValue::Int(ammo) => ammo,
+            _ => None,
+        })
+        .unwrap_or(0);
+
+    Ammo { current: current.min(MAX_AMMO), ..Default::default() }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct AmmoPickup {
+    pub amount: i32,
+}
+
+impl From<EntityInstance> for AmmoPickup {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let amount = entity_instance
+            .field_instances
+            .iter()
+            .find(|f| f.identifier == *"amount")
+            .and_then(|f| match f.value {
+                FieldValue::Int(amount) => amount,
+                _ => None,
+            })
+            .unwrap_or(0);
+
+        AmmoPickup { amount }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct AmmoPickupBundle {
+    #[sprite_sheet_bundle("sprites/TopDown_by_deepnight.png", 16.0, 16.0, 11, 11, 0.0, 100)]
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub ammo_pickup: AmmoPickup,
+    pub y_sort: YSort,
+}
+
+/// A pooled projectile, only moves and collides while `active`
+#[derive(Clone, Debug, Default, Component)]
+pub struct Projectile {
+    pub owner: Option<Entity>,
+    pub velocity: Vec2,
+    pub travelled: f32,
+    pub active: bool,
+}
+
+fn spawn_projectile_pool(
+    mut commands: Commands,
+    pool: Query<(), With<Projectile>>,
+) {
+    // LoadGame is entered again for every save loaded, the pool from the first one is still there
+    if !pool.is_empty() {
+        return;
+    }
+
+    for _ in 0..POOL_SIZE {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: PROJECTILE_COLOR,
+                    custom_size: Some(Vec2::splat(PROJECTILE_SIZE)),
+                    ..Default::default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(Projectile::default())
+            .insert(YSort);
+    }
+}
+
+fn release(projectile: &mut Projectile, visibility: &mut Visibility) {
+    projectile.active = false;
+    visibility.is_visible = false;
+}
+
+fn fire_projectile(
+    mut player: Query<(Entity, &Transform, &Orientation, &mut Ammo), With<Player>>,
+    mut projectiles: Query<(&mut Projectile, &mut Transform, &mut Visibility), Without<Player>>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if !keys.just_pressed(KeyCode::F) {
+        return;
+    }
+
+    for (entity, transform, orientation, mut ammo) in player.iter_mut() {
+        if ammo.current <= 0 {
+            continue;
+        }
+
+        // With the whole pool in flight the shot is dropped instead of recycling one mid-air
+        if let Some((mut projectile, mut projectile_transform, mut visibility)) = projectiles
+            .iter_mut()
+            .find(|(projectile, _, _)| !projectile.active)
+        {
+            ammo.current -= 1;
+            *projectile = Projectile {
+                owner: Some(entity),
+                velocity: orientation.0.vector() * PROJECTILE_SPEED,
+                travelled: 0.0,
+                active: true,
+            };
+            projectile_transform.translation = transform.translation;
+            visibility.is_visible = true;
+        }
+    }
+}
+
+fn move_projectiles(
+    mut projectiles: Query<(&mut Projectile, &mut Transform, &mut Visibility)>,
+    walls: Query<&Transform, (With<Collider>, Without<EnemyKind>, Without<Projectile>)>,
+    enemies: Query<(Entity, &GlobalTransform), With<EnemyKind>>,
+    buffs: Query<&PowerBuff>,
+    mut damage: EventWriter<DamageEvent>,
+    time: Res<Time>,
+) {
+    projectiles.for_each_mut(|(mut projectile, mut transform, mut visibility)| {
+        if !projectile.active {
+            return;
+        }
+
+        let step = projectile.velocity * time.delta_seconds();
+        transform.translation += step.extend(0.0);
+        projectile.travelled += step.length();
+        if projectile.travelled >= PROJECTILE_RANGE {
+            release(&mut projectile, &mut visibility);
+            return;
+        }
+
+        let size = Vec2::splat(PROJECTILE_SIZE);
+        let hit_enemy = enemies.iter().find(|(_, enemy)| {
+            collide(transform.translation, size, enemy.translation(), Vec2::splat(TILE_SIZE - 1.0)).is_some()
+        });
+        if let Some((enemy, _)) = hit_enemy {
+            let buff = projectile.owner.and_then(|owner| buffs.get(owner).ok());
+            damage.send(DamageEvent {
+                target: enemy,
+                amount: buff.map_or(PROJECTILE_DAMAGE, |buff| buff.apply(PROJECTILE_DAMAGE)),
+                source: projectile.owner,
+            });
+            release(&mut projectile, &mut visibility);
+            return;
+        }
+
+        let hit_wall = walls.iter().any(|wall| {
+            collide(transform.translation, size, wall.translation, wall.scale.truncate()).is_some()
+        });
+        if hit_wall {
+            release(&mut projectile, &mut visibility);
+        }
+    })
+}
+
+fn pick_up_ammo(
+    mut commands: Commands,
+    mut player: Query<(&Transform, &mut Ammo), With<Player>>,
+    pickups: Query<(Entity, &GlobalTransform, &AmmoPickup)>,
+) {
+    for (transform, mut ammo) in player.iter_mut() {
+        for (entity, pickup_transform, pickup) in pickups.iter() {
+            let touching = collide(
+                transform.translation,
+                Vec2::splat(TILE_SIZE - 1.0),
+                pickup_transform.translation(),
+                Vec2::splat(TILE_SIZE - 1.0),
+            ).is_some();
+
+            if touching && ammo.current < ammo.max {
+                ammo.current = (ammo.current + pickup.amount).min(ammo.max);
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::player::Direction;
+    use super::*;
+
+    /// A world with the projectile pool and a player facing right, holding F
+    fn world_with_player(ammo: i32) -> (World, Entity) {
+        let mut world = World::new();
+        let mut keys = Input::<KeyCode>::default();
+        keys.press(KeyCode::F);
+        world.insert_resource(keys);
+        world.insert_resource(Time::default());
+        world.init_resource::<Events<DamageEvent>>();
+        SystemStage::single_threaded().with_system(spawn_projectile_pool).run(&mut world);
+
+        let player = world
+            .spawn()
+            .insert(Player)
+            .insert(Transform::from_xyz(32.0, 48.0, 0.0))
+            .insert(Orientation(Direction::Right))
+            .insert(Ammo { current: ammo, max: MAX_AMMO })
+            .id();
+        (world, player)
+    }
+
+    fn fire(world: &mut World) {
+        SystemStage::single_threaded().with_system(fire_projectile).run(world);
+    }
+
+    fn active(world: &mut World) -> Vec<Entity> {
+        world
+            .query::<(Entity, &Projectile)>()
+            .iter(world)
+            .filter(|(_, projectile)| projectile.active)
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    #[test]
+    fn firing_reuses_a_hidden_pooled_projectile() {
+        let (mut world, player) = world_with_player(3);
+        // Entering LoadGame again doesn't grow the pool
+        SystemStage::single_threaded().with_system(spawn_projectile_pool).run(&mut world);
+        assert_eq!(world.query::<&Projectile>().iter(&world).count(), POOL_SIZE);
+
+        fire(&mut world);
+
+        assert_eq!(world.query::<&Projectile>().iter(&world).count(), POOL_SIZE);
+        let fired = active(&mut world);
+        assert_eq!(fired.len(), 1);
+        assert!(world.get::<Visibility>(fired[0]).unwrap().is_visible);
+        assert_eq!(world.get::<Transform>(fired[0]).unwrap().translation, Vec3::new(32.0, 48.0, 0.0));
+        assert_eq!(world.get::<Projectile>(fired[0]).unwrap().owner, Some(player));
+        assert_eq!(world.get::<Ammo>(player).unwrap().current, 2);
+    }
+
+    #[test]
+    fn nothing_fires_without_ammo_or_a_free_projectile() {
+        let (mut world, _) = world_with_player(0);
+        fire(&mut world);
+        assert!(active(&mut world).is_empty());
+
+        let (mut world, player) = world_with_player(3);
+        for mut projectile in world.query::<&mut Projectile>().iter_mut(&mut world) {
+            projectile.active = true;
+        }
+        fire(&mut world);
+        assert_eq!(world.get::<Ammo>(player).unwrap().current, 3);
+    }
+
+    #[test]
+    fn projectiles_are_recycled_after_their_range() {
+        let (mut world, _) = world_with_player(3);
+        fire(&mut world);
+        let fired = active(&mut world)[0];
+
+        let mut stage = SystemStage::single_threaded().with_system(move_projectiles);
+        let mut now = Instant::now();
+        world.resource_mut::<Time>().update_with_instant(now);
+        let flight = Duration::from_secs_f32(PROJECTILE_RANGE / PROJECTILE_SPEED);
+
+        now += flight / 2;
+        world.resource_mut::<Time>().update_with_instant(now);
+        stage.run(&mut world);
+        assert!(world.get::<Projectile>(fired).unwrap().active);
+
+        now += flight;
+        world.resource_mut::<Time>().update_with_instant(now);
+        stage.run(&mut world);
+        assert!(!world.get::<Projectile>(fired).unwrap().active);
+        assert!(!world.get::<Visibility>(fired).unwrap().is_visible);
+    }
+}
@@ -0,0 +1,393 @@
+use std::{collections::{BTreeMap, HashSet}, fs};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{misc::state::GameState, item::Item, enemy::settings::EnemyKind, level::ldtk_project_json, crafting::ldtk_items, save::SaveData};
+
+const QUEST_SETTINGS_PATH: &str = "settings/quests.json";
+
+pub struct QuestPlugin;
+
+impl Plugin for QuestPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(QuestBook::load())
+            .init_resource::<QuestLog>()
+            .init_resource::<CurrentLevel>()
+            .add_event::<QuestEvent>()
+            .add_enter_system(GameState::LoadGame, restore_quest_progress)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::InGame)
+                    .with_system(level_entered)
+                    .with_system(track_objectives)
+                    .with_system(save_quest_progress)
+                    .into()
+            );
+    }
+}
+
+/// Something that happened in the game which objectives can count towards
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuestEvent {
+    ItemCollected(Item),
+    EnemyDefeated(EnemyKind),
+    /// Carries the LDtk identifier of the level
+    LevelEntered(String),
+    /// Named event fired from the level, such as a trigger zone
+    Trigger(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Objective {
+    Collect { item: Item, #[serde(default = "one")] count: u32 },
+    /// Leaving out `count` means every enemy of that kind placed in the level
+    Defeat { enemy: EnemyKind, level: String, count: Option<u32> },
+    Reach { level: String },
+    Trigger { event: String },
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ObjectiveDefinition {
+    pub description: String,
+    #[serde(flatten)]
+    pub objective: Objective,
+}
+
+impl ObjectiveDefinition {
+    pub fn required(&self) -> u32 {
+        match self.objective {
+            Objective::Collect { count, .. } => count,
+            Objective::Defeat { count, .. } => count.unwrap_or(1),
+            Objective::Reach { .. } | Objective::Trigger { .. } => 1,
+        }
+    }
+
+    /// Whether `event` counts towards this objective while the player is in `level`
+    pub fn counts(&self, event: &QuestEvent, level: &str) -> bool {
+        match (&self.objective, event) {
+            (Objective::Collect { item, .. }, QuestEvent::ItemCollected(collected)) => item == collected,
+            (Objective::Defeat { enemy, level: target, .. }, QuestEvent::EnemyDefeated(kind)) => enemy == kind && target == level,
+            (Objective::Reach { level: target }, QuestEvent::LevelEntered(entered)) => target == entered,
+            (Objective::Trigger { event: name }, QuestEvent::Trigger(fired)) => name == fired,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Quest {
+    pub id: String,
+    pub title: String,
+    pub objectives: Vec<ObjectiveDefinition>,
+    /// Quest that has to be completed before this one starts
+    pub requires: Option<String>,
+}
+
+/// Every quest, in the order they are listed in `settings/quests.json`
+#[derive(Clone, Debug, Default, Deref)]
+pub struct QuestBook(pub Vec<Quest>);
+
+impl QuestBook {
+    pub fn read() -> Result<Self, String> {
+        let settings = fs::read_to_string(QUEST_SETTINGS_PATH)
+            .map_err(|error| format!("Failed to open {}: {}", QUEST_SETTINGS_PATH, error))?;
+        serde_json::from_str(&settings)
+            .map(QuestBook)
+            .map_err(|error| format!("Failed to parse {}: {}", QUEST_SETTINGS_PATH, error))
+    }
+
+    /// Falls back to no quests at all when the settings can't be read or don't match the LDtk project
+    pub fn load() -> Self {
+        let mut quests = match QuestBook::read() {
+            Ok(quests) => quests,
+            Err(error) => {
+                warn!("{}, there will be no quests", error);
+                return QuestBook::default();
+            },
+        };
+
+        if let Some(project) = ldtk_project_json() {
+            quests.count_placed_enemies(&project);
+            if let Err(errors) = quests.validate(&ldtk_items(&project), &ldtk_levels(&project)) {
+                warn!("Invalid quests in {}, there will be no quests:\n{}", QUEST_SETTINGS_PATH, errors.join("\n"));
+                return QuestBook::default();
+            }
+        }
+        quests
+    }
+
+    /// Fills in `count` of "defeat every enemy" objectives from the enemies placed in the LDtk project
+    fn count_placed_enemies(&mut self, project: &serde_json::Value) {
+        for quest in self.0.iter_mut() {
+            for objective in quest.objectives.iter_mut() {
+                if let Objective::Defeat { enemy, level, count: count @ None } = &mut objective.objective {
+                    let placed = project["levels"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter(|ldtk_level| ldtk_level["identifier"] == level.as_str())
+                        .flat_map(|ldtk_level| ldtk_level["layerInstances"].as_array().cloned().unwrap_or_default())
+                        .flat_map(|layer| layer["entityInstances"].as_array().cloned().unwrap_or_default())
+                        .filter(|entity| entity["__identifier"] == enemy.identifier())
+                        .count();
+                    *count = Some(placed as u32);
+                }
+            }
+        }
+    }
+
+    /// Checks that objectives only reference items and levels of the LDtk project
+    pub fn validate(&self, known_items: &HashSet<String>, known_levels: &HashSet<String>) -> Result<(), Vec<String>> {
+        let ids: HashSet<&str> = self.iter().map(|quest| quest.id.as_str()).collect();
+        let mut errors = Vec::new();
+
+        for quest in self.iter() {
+            if let Some(required) = &quest.requires {
+                if !ids.contains(required.as_str()) {
+                    errors.push(format!("{} requires unknown quest {}", quest.id, required));
+                }
+            }
+
+            for objective in quest.objectives.iter() {
+                match &objective.objective {
+                    Objective::Collect { item, .. } if !known_items.contains(item.identifier()) => {
+                        errors.push(format!("{} collects unknown item {}", quest.id, item.identifier()));
+                    },
+                    Objective::Defeat { level, .. } | Objective::Reach { level } if !known_levels.contains(level) => {
+                        errors.push(format!("{} refers to unknown level {}", quest.id, level));
+                    },
+                    Objective::Defeat { count: Some(0), level, .. } => {
+                        errors.push(format!("{} wants enemies defeated in {}, but none are placed there", quest.id, level));
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Identifiers of every level in the LDtk project
+pub fn ldtk_levels(project: &serde_json::Value) -> HashSet<String> {
+    project["levels"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|level| level["identifier"].as_str().map(str::to_string))
+        .collect()
+}
+
+/// How far the player is in every quest, keyed by quest id, with one counter per objective
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuestLog {
+    pub progress: BTreeMap<String, Vec<u32>>,
+}
+
+impl QuestLog {
+    pub fn objective_progress(&self, quest: &Quest, objective: usize) -> u32 {
+        self.progress
+            .get(&quest.id)
+            .and_then(|progress| progress.get(objective))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn is_complete(&self, quest: &Quest) -> bool {
+        quest
+            .objectives
+            .iter()
+            .enumerate()
+            .all(|(index, objective)| self.objective_progress(quest, index) >= objective.required())
+    }
+
+    pub fn is_active(&self, quest: &Quest, quests: &QuestBook) -> bool {
+        let unlocked = quest.requires.as_ref().map_or(true, |required| {
+            quests.iter().find(|other| &other.id == required).map_or(false, |other| self.is_complete(other))
+        });
+        unlocked && !self.is_complete(quest)
+    }
+
+    /// Counts `event` towards every active quest, returns whether anything moved forward
+    pub fn record(&mut self, event: &QuestEvent, level: &str, quests: &QuestBook) -> bool {
+        let mut advanced = false;
+        for quest in quests.iter().filter(|quest| self.is_active(quest, quests)) {
+            let progress = self
+                .progress
+                .entry(quest.id.clone())
+                .or_insert_with(|| vec![0; quest.objectives.len()]);
+            progress.resize(quest.objectives.len(), 0);
+
+            for (objective, count) in quest.objectives.iter().zip(progress.iter_mut()) {
+                if *count < objective.required() && objective.counts(event, level) {
+                    *count += 1;
+                    advanced = true;
+                }
+            }
+        }
+        advanced
+    }
+
+    /// First unfinished objective of the first active quest, shown in the HUD
+    pub fn current_objective<'a>(&self, quests: &'a QuestBook) -> Option<(&'a ObjectiveDefinition, u32)> {
+        let quest = quests.iter().find(|quest| self.is_active(quest, quests))?;
+        quest
+            .objectives
+            .iter()
+            .enumerate()
+            .map(|(index, objective)| (objective, self.objective_progress(quest, index)))
+            .find(|(objective, progress)| *progress < objective.required())
+    }
+}
+
+/// LDtk identifier of the level the player is in
+#[derive(Clone, Debug, Default)]
+pub struct CurrentLevel(pub String);
+
+fn restore_quest_progress(
+    mut log: ResMut<QuestLog>,
+    save: Res<SaveData>,
+) {
+    *log = save.quests.clone();
+}
+
+fn save_quest_progress(
+    log: Res<QuestLog>,
+    mut save: ResMut<SaveData>,
+) {
+    if log.is_changed() && !log.is_added() && save.quests != *log {
+        save.quests = log.clone();
+    }
+}
+
+fn level_entered(
+    mut level_events: EventReader<LevelEvent>,
+    mut quest_events: EventWriter<QuestEvent>,
+    mut current_level: ResMut<CurrentLevel>,
+    levels: Res<Assets<LdtkLevel>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    worlds: Query<&Handle<LdtkAsset>>,
+) {
+    for event in level_events.iter() {
+        if let LevelEvent::Spawned(iid) = event {
+            let level = worlds
+                .iter()
+                .filter_map(|handle| ldtk_assets.get(handle))
+                .find_map(|ldtk_asset| ldtk_asset.level_map.get(iid))
+                .and_then(|handle| levels.get(handle));
+
+            if let Some(level) = level {
+                current_level.0 = level.level.identifier.clone();
+                quest_events.send(QuestEvent::LevelEntered(level.level.identifier.clone()));
+            }
+        }
+    }
+}
+
+fn track_objectives(
+    mut events: EventReader<QuestEvent>,
+    mut log: ResMut<QuestLog>,
+    quests: Res<QuestBook>,
+    current_level: Res<CurrentLevel>,
+) {
+    // Only mark the log changed when something counted, so the save isn't rewritten for nothing
+    let mut advanced = false;
+    for event in events.iter() {
+        advanced |= log.bypass_change_detection().record(event, &current_level.0, &quests);
+    }
+    if advanced {
+        log.set_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quests() -> QuestBook {
+        serde_json::from_str::<Vec<Quest>>(r#"[
+            {
+                "id": "first",
+                "title": "First",
+                "objectives": [
+                    { "kind": "Collect", "item": "Key", "description": "Find a key" },
+                    { "kind": "Defeat", "enemy": "Knight", "level": "Basement", "count": 2, "description": "Clear the basement" }
+                ]
+            },
+            {
+                "id": "second",
+                "title": "Second",
+                "requires": "first",
+                "objectives": [
+                    { "kind": "Reach", "level": "Elevator", "description": "Find the elevator" }
+                ]
+            }
+        ]"#).map(QuestBook).unwrap()
+    }
+
+    #[test]
+    fn objectives_only_count_matching_events() {
+        let quests = quests();
+        let mut log = QuestLog::default();
+
+        assert!(!log.record(&QuestEvent::ItemCollected(Item::new("Food")), "Entrance", &quests));
+        assert!(!log.record(&QuestEvent::EnemyDefeated(EnemyKind::Knight), "Entrance", &quests));
+        assert!(log.record(&QuestEvent::EnemyDefeated(EnemyKind::Knight), "Basement", &quests));
+        assert_eq!(log.objective_progress(&quests[0], 1), 1);
+        assert_eq!(log.current_objective(&quests).unwrap().0.description, "Find a key");
+    }
+
+    #[test]
+    fn defeat_objectives_count_the_placed_enemies() {
+        let mut quests = serde_json::from_str::<Vec<Quest>>(r#"[
+            {
+                "id": "clear",
+                "title": "Clear",
+                "objectives": [
+                    { "kind": "Defeat", "enemy": "Knight", "level": "Entrance", "description": "Clear the entrance" }
+                ]
+            }
+        ]"#).map(QuestBook).unwrap();
+
+        quests.count_placed_enemies(&ldtk_project_json().unwrap());
+        assert!(matches!(quests[0].objectives[0].objective, Objective::Defeat { count: Some(6), .. }));
+    }
+
+    #[test]
+    fn later_quests_wait_for_their_requirement() {
+        let quests = quests();
+        let mut log = QuestLog::default();
+
+        assert!(!log.record(&QuestEvent::LevelEntered("Elevator".to_string()), "Elevator", &quests));
+        log.record(&QuestEvent::ItemCollected(Item::new("Key")), "Basement", &quests);
+        log.record(&QuestEvent::EnemyDefeated(EnemyKind::Knight), "Basement", &quests);
+        log.record(&QuestEvent::EnemyDefeated(EnemyKind::Knight), "Basement", &quests);
+        assert!(log.is_complete(&quests[0]));
+
+        assert!(log.record(&QuestEvent::LevelEntered("Elevator".to_string()), "Elevator", &quests));
+        assert!(log.is_complete(&quests[1]));
+        assert!(log.current_objective(&quests).is_none());
+    }
+
+    #[test]
+    fn unknown_items_levels_and_requirements_are_reported() {
+        let mut quests = quests();
+        quests.0[1].requires = Some("missing".to_string());
+        let items = HashSet::from(["Food".to_string()]);
+        let levels = HashSet::from(["Elevator".to_string()]);
+
+        let errors = quests.validate(&items, &levels).unwrap_err();
+        assert_eq!(errors.len(), 3);
+    }
+}
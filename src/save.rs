@@ -0,0 +1,66 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs, path::Path};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{fog::VisibilityGrid, quest::QuestLog};
+
+const SAVE_DIRECTORY: &str = "saves";
+const SAVE_PATH: &str = "saves/save.json";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(SaveData::load())
+            .add_system_to_stage(CoreStage::Last, write_save_data);
+    }
+}
+
+/// Progress that outlives a play session, written to `saves/save.json` whenever it changes
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    /// LDtk iids of the devices that have been powered
+    pub powered_devices: BTreeSet<String>,
+    /// LDtk iids of the `Once` trigger zones that have fired
+    pub fired_triggers: BTreeSet<String>,
+    pub quests: QuestLog,
+    /// Explored tiles of the levels with fog of war, keyed by LDtk level iid
+    pub explored: BTreeMap<String, VisibilityGrid>,
+}
+
+impl SaveData {
+    /// Starts a fresh save when there is none yet or it can't be read
+    pub fn load() -> Self {
+        if !Path::new(SAVE_PATH).exists() {
+            return SaveData::default();
+        }
+
+        fs::read_to_string(SAVE_PATH)
+            .ok()
+            .and_then(|save| serde_json::from_str(&save).ok())
+            .unwrap_or_else(|| {
+                warn!("Failed to read {}, starting a new save", SAVE_PATH);
+                SaveData::default()
+            })
+    }
+
+    pub fn write(&self) -> std::io::Result<()> {
+        fs::create_dir_all(SAVE_DIRECTORY)?;
+        let save = serde_json::to_string_pretty(self).expect("Failed to serialize save data");
+        fs::write(SAVE_PATH, save)
+    }
+}
+
+fn write_save_data(
+    save: Res<SaveData>,
+) {
+    // Skip the frame the resource was inserted, nothing has been played yet
+    if !save.is_changed() || save.is_added() {
+        return;
+    }
+
+    if let Err(error) = save.write() {
+        error!("Failed to write {}: {}", SAVE_PATH, error);
+    }
+}
@@ -0,0 +1,427 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy_ecs_ldtk::{prelude::*, EntityInstance};
+use iyes_loopless::prelude::*;
+use crate::{
+    misc::{label::TriggerLabel, state::GameState},
+    player::Player,
+    quest::QuestEvent,
+    dialogue::{ActiveDialogue, DialogueScript},
+    enemy::{SpawnEnemyEvent, settings::EnemyKind},
+    level::{props::{Door, open_door}, travel_to},
+    save::SaveData,
+    TILE_SIZE,
+};
+
+pub struct TriggerPlugin;
+
+impl Plugin for TriggerPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Music>()
+            .add_event::<TriggerEntered>()
+            .add_event::<TriggerExited>()
+            .add_system(
+                restore_fired_triggers
+                    .run_in_state(GameState::InGame)
+                    .before(TriggerLabel::Detect)
+            )
+            .add_system(
+                detect_trigger_zones
+                    .run_in_state(GameState::InGame)
+                    .label(TriggerLabel::Detect)
+            )
+            .add_system(
+                dispatch_trigger_actions
+                    .run_in_state(GameState::InGame)
+                    .after(TriggerLabel::Detect)
+            );
+    }
+}
+
+/// What a trigger zone does when the player walks in, read from the LDtk `action` field
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TriggerAction {
+    SpawnEnemies,
+    OpenDoor,
+    ShowText,
+    ChangeMusic,
+    MoveToLevel,
+}
+
+impl TriggerAction {
+    fn from_field(value: &str) -> Option<Self> {
+        match value {
+            "SpawnEnemies" => Some(TriggerAction::SpawnEnemies),
+            "OpenDoor" => Some(TriggerAction::OpenDoor),
+            "ShowText" => Some(TriggerAction::ShowText),
+            "ChangeMusic" => Some(TriggerAction::ChangeMusic),
+            "MoveToLevel" => Some(TriggerAction::MoveToLevel),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a trigger zone fires only the first time the player enters it, or every time
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum TriggerMode {
+    #[default] Once,
+    Repeat,
+}
+
+/// Entity pointed at by a trigger zone's `targets` field
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TriggerTarget {
+    pub entity_iid: String,
+    pub level_iid: String,
+}
+
+/// Invisible area of the level that acts when the player walks in or out of it
+#[derive(Clone, Debug, Default, Component)]
+pub struct TriggerZone {
+    /// LDtk iid, used to remember fired `Once` zones in the save data
+    pub iid: String,
+    /// Quest event fired on entering, from the `event` field
+    pub event: Option<String>,
+    pub action: Option<TriggerAction>,
+    pub mode: TriggerMode,
+    /// Shown by `ShowText`
+    pub text: Option<String>,
+    /// Asset path of the track played by `ChangeMusic`
+    pub music: Option<String>,
+    /// Doors opened by `OpenDoor`, the first one is the destination of `MoveToLevel`
+    pub targets: Vec<TriggerTarget>,
+    /// Where `SpawnEnemies` places knights, relative to the center of the zone
+    pub spawns: Vec<Vec2>,
+    pub size: Vec2,
+    /// Whether the player is inside since a fired enter event
+    pub occupied: bool,
+    pub fired: bool,
+    /// Track that was playing when a `ChangeMusic` zone was entered, resumed on exit
+    previous_music: Option<String>,
+}
+
+impl From<EntityInstance> for TriggerZone {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let size = Vec2::new(entity_instance.width as f32, entity_instance.height as f32);
+        // The zone's pivot is its top-left corner, while its transform sits at its center
+        let center = entity_instance.px.as_vec2() + size / 2.0;
+
+        let mut zone = TriggerZone { iid: entity_instance.iid.clone(), size, ..Default::default() };
+        for field in entity_instance.field_instances.iter() {
+            match (field.identifier.as_str(), &field.value) {
+                ("event", FieldValue::String(event)) => {
+                    zone.event = event.clone();
+                },
+                ("action", FieldValue::Enum(action)) => {
+                    zone.action = action.as_deref().and_then(TriggerAction::from_field);
+                },
+                ("mode", FieldValue::Enum(mode)) => {
+                    if mode.as_deref() == Some("Repeat") {
+                        zone.mode = TriggerMode::Repeat;
+                    }
+                },
+                ("text", FieldValue::String(text)) => {
+                    zone.text = text.clone();
+                },
+                ("music", FieldValue::FilePath(music)) => {
+                    zone.music = music.clone();
+                },
+                ("targets", FieldValue::EntityRefs(targets)) => {
+                    zone.targets = targets
+                        .iter()
+                        .flatten()
+                        .map(|target| TriggerTarget {
+                            entity_iid: target.entity_iid.clone(),
+                            level_iid: target.level_iid.clone(),
+                        })
+                        .collect();
+                },
+                ("spawns", FieldValue::Points(spawns)) => {
+                    zone.spawns = spawns
+                        .iter()
+                        .flatten()
+                        .map(|cell| {
+                            let offset = (cell.as_vec2() + Vec2::splat(0.5)) * TILE_SIZE - center;
+                            // LDtk's y axis points down
+                            Vec2::new(offset.x, -offset.y)
+                        })
+                        .collect();
+                },
+                _ => (),
+            }
+        }
+
+        zone
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct TriggerZoneBundle {
+    #[from_entity_instance]
+    pub trigger_zone: TriggerZone,
+}
+
+/// Sent when the player walks into a trigger zone that is allowed to fire
+pub struct TriggerEntered {
+    pub zone: Entity,
+}
+
+/// Sent when the player walks out of a trigger zone whose enter event fired
+pub struct TriggerExited {
+    pub zone: Entity,
+}
+
+/// Background track started by a `ChangeMusic` trigger
+#[derive(Default)]
+pub struct Music {
+    pub track: Option<String>,
+    sink: Option<Handle<AudioSink>>,
+}
+
+fn restore_fired_triggers(
+    mut zones: Query<&mut TriggerZone, Added<TriggerZone>>,
+    save: Res<SaveData>,
+) {
+    for mut zone in zones.iter_mut() {
+        if save.fired_triggers.contains(&zone.iid) {
+            zone.fired = true;
+        }
+    }
+}
+
+/// Compares the player's bounding box against every zone and sends enter and exit events
+fn detect_trigger_zones(
+    player: Query<&Transform, With<Player>>,
+    mut zones: Query<(Entity, &mut TriggerZone, &GlobalTransform)>,
+    mut entered: EventWriter<TriggerEntered>,
+    mut exited: EventWriter<TriggerExited>,
+    mut save: ResMut<SaveData>,
+) {
+    for transform in player.iter() {
+        for (entity, mut zone, zone_transform) in zones.iter_mut() {
+            let inside = collide(
+                transform.translation,
+                Vec2::splat(TILE_SIZE),
+                zone_transform.translation(),
+                zone.size,
+            ).is_some();
+            if inside && !zone.occupied {
+                // Once zones stay dormant after their first visit
+                if zone.mode == TriggerMode::Once && zone.fired {
+                    continue;
+                }
+                zone.occupied = true;
+                zone.fired = true;
+                if zone.mode == TriggerMode::Once {
+                    save.fired_triggers.insert(zone.iid.clone());
+                }
+                entered.send(TriggerEntered { zone: entity });
+            } else if !inside && zone.occupied {
+                zone.occupied = false;
+                exited.send(TriggerExited { zone: entity });
+            }
+        }
+    }
+}
+
+/// Runs the action of the zones the player entered, and undoes music changes when they leave
+fn dispatch_trigger_actions(
+    mut commands: Commands,
+    mut entered: EventReader<TriggerEntered>,
+    mut exited: EventReader<TriggerExited>,
+    mut zones: Query<(&mut TriggerZone, &Transform, &Parent)>,
+    mut doors: Query<(&mut Door, &EntityInstance, &mut Visibility)>,
+    mut quest_events: EventWriter<QuestEvent>,
+    mut spawn_enemies: EventWriter<SpawnEnemyEvent>,
+    mut music: ResMut<Music>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+) {
+    for TriggerEntered { zone } in entered.iter() {
+        let (mut zone, transform, parent) = match zones.get_mut(*zone) {
+            Ok(zone) => zone,
+            Err(_) => continue,
+        };
+
+        if let Some(event) = &zone.event {
+            quest_events.send(QuestEvent::Trigger(event.clone()));
+        }
+
+        match zone.action {
+            Some(TriggerAction::SpawnEnemies) => {
+                for spawn in zone.spawns.iter() {
+                    spawn_enemies.send(SpawnEnemyEvent {
+                        level: parent.get(),
+                        kind: EnemyKind::Knight,
+                        translation: transform.translation + spawn.extend(0.0),
+                    });
+                }
+            },
+            Some(TriggerAction::OpenDoor) => {
+                for (mut door, instance, mut visibility) in doors.iter_mut() {
+                    if zone.targets.iter().any(|target| target.entity_iid == instance.iid) {
+                        open_door(&mut commands, &mut door, &mut visibility);
+                    }
+                }
+            },
+            Some(TriggerAction::ShowText) => {
+                if let Some(text) = &zone.text {
+                    commands.insert_resource(ActiveDialogue::new(DialogueScript::line(None, text.clone())));
+                    commands.insert_resource(NextState(GameState::Dialogue));
+                }
+            },
+            Some(TriggerAction::ChangeMusic) => {
+                zone.previous_music = music.track.clone();
+                let track = zone.music.clone();
+                play_music(&mut music, track, &asset_server, &audio, &audio_sinks);
+            },
+            Some(TriggerAction::MoveToLevel) => {
+                if let Some(target) = zone.targets.first() {
+                    travel_to(&mut commands, &target.level_iid, &target.entity_iid);
+                }
+            },
+            None => (),
+        }
+    }
+
+    for TriggerExited { zone } in exited.iter() {
+        if let Ok((mut zone, _, _)) = zones.get_mut(*zone) {
+            if zone.action == Some(TriggerAction::ChangeMusic) && zone.mode == TriggerMode::Repeat {
+                let track = zone.previous_music.take();
+                play_music(&mut music, track, &asset_server, &audio, &audio_sinks);
+            }
+        }
+    }
+}
+
+/// Stops the current track and loops `track` instead, or leaves silence when there is none
+fn play_music(
+    music: &mut Music,
+    track: Option<String>,
+    asset_server: &AssetServer,
+    audio: &Audio,
+    audio_sinks: &Assets<AudioSink>,
+) {
+    if music.track == track {
+        return;
+    }
+
+    if let Some(sink) = music.sink.take().and_then(|sink| audio_sinks.get(&sink)) {
+        sink.pause();
+    }
+    music.sink = track.as_ref().map(|track| {
+        // LDtk file paths are relative to the project, which sits in assets/levels
+        let path = track.trim_start_matches("../");
+        audio_sinks.get_handle(audio.play_with_settings(asset_server.load(path), PlaybackSettings::LOOP))
+    });
+    music.track = track;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A world with a two tile wide zone at the origin and the player standing outside of it
+    fn zone_with_mode(mode: TriggerMode) -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Events<TriggerEntered>>();
+        world.init_resource::<Events<TriggerExited>>();
+        world.init_resource::<SaveData>();
+        let zone = world
+            .spawn()
+            .insert(TriggerZone { iid: "zone".to_string(), mode, size: Vec2::splat(TILE_SIZE * 2.0), ..Default::default() })
+            .insert(GlobalTransform::default())
+            .id();
+        let player = world
+            .spawn()
+            .insert(Player)
+            .insert(Transform::from_xyz(TILE_SIZE * 5.0, 0.0, 0.0))
+            .id();
+        (world, zone, player)
+    }
+
+    /// Moves the player to `x` and runs the detection once
+    fn walk_to(world: &mut World, stage: &mut SystemStage, player: Entity, x: f32) {
+        world.get_mut::<Transform>(player).unwrap().translation.x = x;
+        stage.run(world);
+    }
+
+    fn sent<E: Send + Sync + 'static>(world: &World) -> usize {
+        let events = world.resource::<Events<E>>();
+        events.get_reader().iter(events).count()
+    }
+
+    #[test]
+    fn enter_and_exit_fire_only_on_crossing_the_edge() {
+        let (mut world, zone, player) = zone_with_mode(TriggerMode::Repeat);
+        let mut stage = SystemStage::single_threaded().with_system(detect_trigger_zones);
+
+        stage.run(&mut world);
+        assert_eq!(sent::<TriggerEntered>(&world), 0);
+
+        walk_to(&mut world, &mut stage, player, 0.0);
+        assert_eq!(sent::<TriggerEntered>(&world), 1);
+        assert!(world.get::<TriggerZone>(zone).unwrap().occupied);
+
+        // Staying inside doesn't enter again
+        walk_to(&mut world, &mut stage, player, TILE_SIZE * 0.5);
+        assert_eq!(sent::<TriggerEntered>(&world), 1);
+        assert_eq!(sent::<TriggerExited>(&world), 0);
+
+        walk_to(&mut world, &mut stage, player, TILE_SIZE * 5.0);
+        assert_eq!(sent::<TriggerExited>(&world), 1);
+        assert!(!world.get::<TriggerZone>(zone).unwrap().occupied);
+
+        // Nor does staying outside leave again
+        stage.run(&mut world);
+        assert_eq!(sent::<TriggerExited>(&world), 1);
+    }
+
+    #[test]
+    fn repeat_zones_fire_on_every_visit() {
+        let (mut world, _, player) = zone_with_mode(TriggerMode::Repeat);
+        let mut stage = SystemStage::single_threaded().with_system(detect_trigger_zones);
+
+        for _ in 0..3 {
+            walk_to(&mut world, &mut stage, player, 0.0);
+            walk_to(&mut world, &mut stage, player, TILE_SIZE * 5.0);
+        }
+        assert_eq!(sent::<TriggerEntered>(&world), 3);
+        assert_eq!(sent::<TriggerExited>(&world), 3);
+    }
+
+    #[test]
+    fn once_zones_stay_dormant_after_the_first_visit() {
+        let (mut world, zone, player) = zone_with_mode(TriggerMode::Once);
+        let mut stage = SystemStage::single_threaded().with_system(detect_trigger_zones);
+
+        for _ in 0..3 {
+            walk_to(&mut world, &mut stage, player, 0.0);
+            walk_to(&mut world, &mut stage, player, TILE_SIZE * 5.0);
+        }
+        assert_eq!(sent::<TriggerEntered>(&world), 1);
+        // The exit of the one visit that fired still goes out
+        assert_eq!(sent::<TriggerExited>(&world), 1);
+        assert!(world.get::<TriggerZone>(zone).unwrap().fired);
+    }
+
+    #[test]
+    fn fired_once_zones_stay_dormant_after_loading() {
+        let (mut world, _, player) = zone_with_mode(TriggerMode::Once);
+        let mut stage = SystemStage::single_threaded().with_system(detect_trigger_zones);
+        walk_to(&mut world, &mut stage, player, 0.0);
+        let save = world.resource::<SaveData>().clone();
+        assert!(save.fired_triggers.contains("zone"));
+
+        // The same zone spawned again from the level, with the save loaded
+        let (mut world, zone, player) = zone_with_mode(TriggerMode::Once);
+        world.insert_resource(save);
+        let mut stage = SystemStage::single_threaded()
+            .with_system(restore_fired_triggers.before(TriggerLabel::Detect))
+            .with_system(detect_trigger_zones.label(TriggerLabel::Detect));
+        walk_to(&mut world, &mut stage, player, 0.0);
+        assert!(world.get::<TriggerZone>(zone).unwrap().fired);
+        assert_eq!(sent::<TriggerEntered>(&world), 0);
+    }
+}
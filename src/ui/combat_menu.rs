@@ -0,0 +1,139 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, encounter::{CombatAction, Encounter, Turn}, combat::Health, player::Player};
+use super::{menu_assets::MenuAssets, style::{text_bundle, HOVERED_BUTTON, MENU_BACKGROUND, NORMAL_BUTTON}};
+
+pub struct CombatMenuPlugin;
+
+impl Plugin for CombatMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_enter_system(GameState::Combat, spawn_combat_menu)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Combat)
+                    .with_system(action_buttons)
+                    .with_system(update_combat_text)
+                    .into()
+            )
+            .add_exit_system(GameState::Combat, despawn_combat_menu);
+    }
+}
+
+#[derive(Component)]
+struct CombatMenuComponent;
+
+#[derive(Component)]
+struct CombatLogText;
+
+#[derive(Component)]
+struct CombatStatusText;
+
+#[derive(Component)]
+struct ActionButton(CombatAction);
+
+fn spawn_combat_menu(
+    mut commands: Commands,
+    menu_assets: Res<MenuAssets>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: MENU_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(CombatMenuComponent)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(text_bundle("", &menu_assets))
+                .insert(CombatStatusText);
+            parent
+                .spawn_bundle(text_bundle("", &menu_assets))
+                .insert(CombatLogText);
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for action in CombatAction::ALL {
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                                    margin: UiRect::all(Val::Px(8.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                image: menu_assets.button.clone().into(),
+                                color: NORMAL_BUTTON.into(),
+                                ..Default::default()
+                            })
+                            .insert(ActionButton(action))
+                            .with_children(|parent| {
+                                parent.spawn_bundle(text_bundle(action.label(), &menu_assets));
+                            });
+                    }
+                });
+        });
+}
+
+fn action_buttons(
+    mut buttons: Query<(&Interaction, &ActionButton, &mut UiColor), Changed<Interaction>>,
+    mut actions: EventWriter<CombatAction>,
+) {
+    buttons.for_each_mut(|(interaction, button, mut color)| {
+        match interaction {
+            Interaction::Clicked => actions.send(button.0),
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    })
+}
+
+fn update_combat_text(
+    encounter: Res<Encounter>,
+    player: Query<&Health, With<Player>>,
+    enemies: Query<&Health, Without<Player>>,
+    mut log: Query<&mut Text, (With<CombatLogText>, Without<CombatStatusText>)>,
+    mut status: Query<&mut Text, (With<CombatStatusText>, Without<CombatLogText>)>,
+) {
+    if !encounter.is_changed() {
+        return;
+    }
+
+    for mut text in log.iter_mut() {
+        text.sections[0].value = match (encounter.outcome, encounter.current_turn()) {
+            (Some(_), _) => format!("{}\nPress Enter to continue", encounter.log),
+            (None, Turn::Player) => format!("{}\nWhat will you do?", encounter.log),
+            (None, Turn::Enemy) => encounter.log.clone(),
+        };
+    }
+
+    let player_health = player.get_single().map_or(0, |health| health.current);
+    let enemy_health = enemies.get(encounter.enemy).map_or(0, |health| health.current);
+    for mut text in status.iter_mut() {
+        text.sections[0].value = format!("You: {} HP    Knight: {} HP", player_health, enemy_health);
+    }
+}
+
+fn despawn_combat_menu(
+    mut commands: Commands,
+    menu: Query<Entity, With<CombatMenuComponent>>,
+) {
+    menu.for_each(|entity| {
+        commands.entity(entity).despawn_recursive();
+    })
+}
@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, crafting::{CraftEvent, RecipeBook}, item::Inventory, player::Player};
+use super::{menu_assets::MenuAssets, style::{text_bundle, HOVERED_BUTTON, MENU_BACKGROUND, NORMAL_BUTTON}};
+
+/// Text colour of recipes the player doesn't have the ingredients for
+const MISSING_INGREDIENTS: Color = Color::rgb(0.5, 0.5, 0.5);
+
+pub struct CraftingMenuPlugin;
+
+impl Plugin for CraftingMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_enter_system(GameState::Crafting, spawn_crafting_menu)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Crafting)
+                    .with_system(recipe_buttons)
+                    .with_system(back_to_inventory)
+                    .with_system(update_recipe_colors)
+                    .into()
+            )
+            .add_exit_system(GameState::Crafting, despawn_crafting_menu);
+    }
+}
+
+#[derive(Component)]
+struct CraftingMenuComponent;
+
+/// Index of the recipe in the `RecipeBook`
+#[derive(Component)]
+struct RecipeButton(usize);
+
+#[derive(Component)]
+struct BackButton;
+
+fn button_bundle(menu_assets: &MenuAssets, width: f32) -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(width), Val::Px(65.0)),
+            margin: UiRect::all(Val::Px(8.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        image: menu_assets.button.clone().into(),
+        color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    }
+}
+
+fn spawn_crafting_menu(
+    mut commands: Commands,
+    menu_assets: Res<MenuAssets>,
+    recipes: Res<RecipeBook>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: MENU_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(CraftingMenuComponent)
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("Crafting", &menu_assets));
+
+            for (index, recipe) in recipes.iter().enumerate() {
+                let label = format!("{}: {}", recipe.name, recipe.describe_inputs());
+                parent
+                    .spawn_bundle(button_bundle(&menu_assets, 480.0))
+                    .insert(RecipeButton(index))
+                    .with_children(|parent| {
+                        parent.spawn_bundle(text_bundle(&label, &menu_assets));
+                    });
+            }
+
+            parent
+                .spawn_bundle(button_bundle(&menu_assets, 150.0))
+                .insert(BackButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(text_bundle("Back", &menu_assets));
+                });
+        });
+}
+
+fn recipe_buttons(
+    mut buttons: Query<(&Interaction, &RecipeButton, &mut UiColor), Changed<Interaction>>,
+    mut craft: EventWriter<CraftEvent>,
+) {
+    buttons.for_each_mut(|(interaction, button, mut color)| {
+        match interaction {
+            Interaction::Clicked => craft.send(CraftEvent(button.0)),
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    })
+}
+
+fn back_to_inventory(
+    mut commands: Commands,
+    mut buttons: Query<(&Interaction, &mut UiColor), (With<BackButton>, Changed<Interaction>)>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        commands.insert_resource(NextState(GameState::Inventory));
+    }
+
+    buttons.for_each_mut(|(interaction, mut color)| {
+        match interaction {
+            Interaction::Clicked => commands.insert_resource(NextState(GameState::Inventory)),
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    })
+}
+
+/// Greys out recipes the player can't afford, also runs on the first frame of the menu
+fn update_recipe_colors(
+    player: Query<&Inventory, With<Player>>,
+    buttons: Query<(&RecipeButton, &Children)>,
+    added: Query<(), Added<RecipeButton>>,
+    mut texts: Query<&mut Text>,
+    recipes: Res<RecipeBook>,
+    changed: Query<(), (With<Player>, Changed<Inventory>)>,
+) {
+    if changed.is_empty() && added.is_empty() {
+        return;
+    }
+    let inventory = match player.get_single() {
+        Ok(inventory) => inventory,
+        Err(_) => return,
+    };
+
+    for (button, children) in buttons.iter() {
+        let affordable = recipes.get(button.0).map_or(false, |recipe| recipe.can_craft(inventory));
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].style.color = if affordable { Color::WHITE } else { MISSING_INGREDIENTS };
+            }
+        }
+    }
+}
+
+fn despawn_crafting_menu(
+    mut commands: Commands,
+    menu: Query<Entity, With<CraftingMenuComponent>>,
+) {
+    menu.for_each(|entity| {
+        commands.entity(entity).despawn_recursive();
+    })
+}
@@ -0,0 +1,206 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, dialogue::{ActiveDialogue, DialogueChoiceEvent}};
+use super::{menu_assets::MenuAssets, style::{sized_text_bundle, HOVERED_BUTTON, MENU_BACKGROUND, NORMAL_BUTTON}};
+
+const SPEAKER_COLOR: Color = Color::rgb(1.0, 0.85, 0.4);
+const BOX_HEIGHT: f32 = 220.0;
+const PORTRAIT_SIZE: f32 = 128.0;
+const FONT_SIZE: f32 = 22.0;
+const TEXT_MARGIN: f32 = 4.0;
+
+pub struct DialogueBoxPlugin;
+
+impl Plugin for DialogueBoxPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_enter_system(GameState::Dialogue, spawn_dialogue_box)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Dialogue)
+                    .with_system(update_dialogue_box)
+                    .with_system(choice_buttons)
+                    .into()
+            )
+            .add_exit_system(GameState::Dialogue, despawn_dialogue_box);
+    }
+}
+
+#[derive(Component)]
+struct DialogueBoxComponent;
+
+#[derive(Component)]
+struct SpeakerText;
+
+#[derive(Component)]
+struct BodyText;
+
+#[derive(Component)]
+struct Portrait;
+
+#[derive(Component)]
+struct ChoiceList;
+
+#[derive(Component)]
+struct ChoiceButton(usize);
+
+fn spawn_dialogue_box(
+    mut commands: Commands,
+    menu_assets: Res<MenuAssets>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Px(BOX_HEIGHT)),
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(0.0),
+                    bottom: Val::Px(0.0),
+                    ..Default::default()
+                },
+                padding: UiRect::all(Val::Px(12.0)),
+                align_items: AlignItems::FlexStart,
+                ..Default::default()
+            },
+            color: MENU_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(DialogueBoxComponent)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ImageBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(PORTRAIT_SIZE), Val::Px(PORTRAIT_SIZE)),
+                        margin: UiRect::all(Val::Px(8.0)),
+                        ..Default::default()
+                    },
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(Portrait);
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        flex_grow: 1.0,
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(sized_text_bundle("", FONT_SIZE, SPEAKER_COLOR, TEXT_MARGIN, &menu_assets))
+                        .insert(SpeakerText);
+                    parent
+                        .spawn_bundle(sized_text_bundle("", FONT_SIZE, Color::WHITE, TEXT_MARGIN, &menu_assets))
+                        .insert(BodyText);
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::ColumnReverse,
+                                ..Default::default()
+                            },
+                            color: Color::NONE.into(),
+                            ..Default::default()
+                        })
+                        .insert(ChoiceList);
+                });
+        });
+}
+
+/// Follows the typewriter, and rebuilds the speaker, portrait and choices whenever the node changes
+#[allow(clippy::too_many_arguments)]
+fn update_dialogue_box(
+    mut commands: Commands,
+    dialogue: Res<ActiveDialogue>,
+    menu_assets: Res<MenuAssets>,
+    asset_server: Res<AssetServer>,
+    mut shown_node: Local<Option<(String, bool)>>,
+    mut body: Query<&mut Text, (With<BodyText>, Without<SpeakerText>)>,
+    mut speaker: Query<&mut Text, (With<SpeakerText>, Without<BodyText>)>,
+    mut portrait: Query<(&mut UiImage, &mut Visibility), With<Portrait>>,
+    choice_list: Query<Entity, With<ChoiceList>>,
+    new_box: Query<(), Added<DialogueBoxComponent>>,
+) {
+    if !new_box.is_empty() {
+        *shown_node = None;
+    } else if !dialogue.is_changed() {
+        return;
+    }
+    let node = match dialogue.current() {
+        Some(node) => node,
+        None => return,
+    };
+
+    for mut text in body.iter_mut() {
+        text.sections[0].value = dialogue.revealed_text();
+    }
+
+    // Choices only show up once the whole line has been typed out
+    let state = (dialogue.node.clone(), dialogue.is_fully_revealed());
+    if shown_node.as_ref() == Some(&state) {
+        return;
+    }
+    *shown_node = Some(state);
+
+    for mut text in speaker.iter_mut() {
+        text.sections[0].value = node.speaker.clone().unwrap_or_default();
+    }
+    for (mut image, mut visibility) in portrait.iter_mut() {
+        visibility.is_visible = node.portrait.is_some();
+        if let Some(path) = &node.portrait {
+            *image = asset_server.load(path.as_str()).into();
+        }
+    }
+
+    for list in choice_list.iter() {
+        commands.entity(list).despawn_descendants();
+        if !dialogue.is_fully_revealed() {
+            continue;
+        }
+
+        commands.entity(list).with_children(|parent| {
+            for (index, choice) in node.choices.iter().enumerate() {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            margin: UiRect::all(Val::Px(2.0)),
+                            padding: UiRect::all(Val::Px(4.0)),
+                            ..Default::default()
+                        },
+                        color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    })
+                    .insert(ChoiceButton(index))
+                    .with_children(|parent| {
+                        let label = format!("{}. {}", index + 1, choice.text);
+                        parent.spawn_bundle(sized_text_bundle(&label, FONT_SIZE, Color::WHITE, TEXT_MARGIN, &menu_assets));
+                    });
+            }
+        });
+    }
+}
+
+fn choice_buttons(
+    mut buttons: Query<(&Interaction, &ChoiceButton, &mut UiColor), Changed<Interaction>>,
+    mut choices: EventWriter<DialogueChoiceEvent>,
+) {
+    buttons.for_each_mut(|(interaction, button, mut color)| {
+        match interaction {
+            Interaction::Clicked => choices.send(DialogueChoiceEvent(button.0)),
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    })
+}
+
+fn despawn_dialogue_box(
+    mut commands: Commands,
+    dialogue_box: Query<Entity, With<DialogueBoxComponent>>,
+) {
+    dialogue_box.for_each(|entity| {
+        commands.entity(entity).despawn_recursive();
+    })
+}
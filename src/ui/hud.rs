@@ -0,0 +1,260 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, combat::Health, projectile::Ammo, item::EquippedItem, player::Player, quest::{QuestBook, QuestLog}};
+use super::{menu_assets::MenuAssets, style::sized_text_bundle};
+
+const HEALTH_BAR_WIDTH: f32 = 200.0;
+const HEALTH_BAR_HEIGHT: f32 = 16.0;
+const HEALTH_BAR_BACKGROUND: Color = Color::rgb(0.2, 0.05, 0.05);
+const HEALTH_BAR_COLOR: Color = Color::rgb(0.8, 0.1, 0.15);
+const FONT_SIZE: f32 = 20.0;
+const TEXT_MARGIN: f32 = 4.0;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_enter_system(GameState::InGame, spawn_hud)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::InGame)
+                    .with_system(update_health)
+                    .with_system(update_ammo)
+                    .with_system(update_quick_slot)
+                    .with_system(update_level_name)
+                    .with_system(update_objective)
+                    .into()
+            )
+            .add_exit_system(GameState::InGame, despawn_hud);
+    }
+}
+
+#[derive(Component)]
+struct HudComponent;
+
+#[derive(Component)]
+struct HealthBar;
+
+#[derive(Component)]
+struct HealthText;
+
+#[derive(Component)]
+struct AmmoText;
+
+#[derive(Component)]
+struct QuickSlotText;
+
+#[derive(Component)]
+struct LevelNameText;
+
+#[derive(Component)]
+struct ObjectiveText;
+
+fn health_text(health: &Health) -> String {
+    format!("{}/{}", health.current, health.max)
+}
+
+fn health_width(health: &Health) -> Val {
+    Val::Px(HEALTH_BAR_WIDTH * health.current.max(0) as f32 / health.max.max(1) as f32)
+}
+
+fn ammo_text(ammo: &Ammo) -> String {
+    format!("Ammo {}/{}", ammo.current, ammo.max)
+}
+
+fn quick_slot_text(equipped: &EquippedItem) -> String {
+    format!("[Q] {}", equipped.0.as_ref().map_or("-", |item| item.identifier()))
+}
+
+fn objective_text(log: &QuestLog, quests: &QuestBook) -> String {
+    log.current_objective(quests).map_or_else(String::new, |(objective, progress)| {
+        if objective.required() > 1 {
+            format!("> {} ({}/{})", objective.description, progress, objective.required())
+        } else {
+            format!("> {}", objective.description)
+        }
+    })
+}
+
+fn text_bundle(text: String, menu_assets: &MenuAssets) -> TextBundle {
+    sized_text_bundle(text, FONT_SIZE, Color::WHITE, TEXT_MARGIN, menu_assets)
+}
+
+/// Spawns the HUD already filled in, since the update systems only react to changes
+fn spawn_hud(
+    mut commands: Commands,
+    menu_assets: Res<MenuAssets>,
+    player: Query<(&Health, &Ammo, &EquippedItem), With<Player>>,
+    level_handles: Query<&Handle<LdtkLevel>>,
+    levels: Res<Assets<LdtkLevel>>,
+    quest_log: Res<QuestLog>,
+    quests: Res<QuestBook>,
+) {
+    let player = player.get_single().ok();
+    let health = player.map(|(health, _, _)| *health).unwrap_or_default();
+    let level_name = level_handles
+        .iter()
+        .find_map(|handle| levels.get(handle))
+        .map_or_else(String::new, |level| level.level.identifier.clone());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(12.0),
+                    top: Val::Px(12.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(HudComponent)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(text_bundle(level_name, &menu_assets))
+                .insert(LevelNameText);
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(HEALTH_BAR_WIDTH), Val::Px(HEALTH_BAR_HEIGHT)),
+                                margin: UiRect::all(Val::Px(4.0)),
+                                ..Default::default()
+                            },
+                            color: HEALTH_BAR_BACKGROUND.into(),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn_bundle(NodeBundle {
+                                    style: Style {
+                                        size: Size::new(health_width(&health), Val::Percent(100.0)),
+                                        ..Default::default()
+                                    },
+                                    color: HEALTH_BAR_COLOR.into(),
+                                    ..Default::default()
+                                })
+                                .insert(HealthBar);
+                        });
+                    parent
+                        .spawn_bundle(text_bundle(health_text(&health), &menu_assets))
+                        .insert(HealthText);
+                });
+
+            parent
+                .spawn_bundle(text_bundle(
+                    player.map_or_else(String::new, |(_, ammo, _)| ammo_text(ammo)),
+                    &menu_assets,
+                ))
+                .insert(AmmoText);
+            parent
+                .spawn_bundle(text_bundle(
+                    player.map_or_else(String::new, |(_, _, equipped)| quick_slot_text(equipped)),
+                    &menu_assets,
+                ))
+                .insert(QuickSlotText);
+            parent
+                .spawn_bundle(text_bundle(objective_text(&quest_log, &quests), &menu_assets))
+                .insert(ObjectiveText);
+        });
+}
+
+fn update_health(
+    player: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut bar: Query<&mut Style, With<HealthBar>>,
+    mut text: Query<&mut Text, With<HealthText>>,
+) {
+    for health in player.iter() {
+        for mut style in bar.iter_mut() {
+            style.size.width = health_width(health);
+        }
+        for mut text in text.iter_mut() {
+            text.sections[0].value = health_text(health);
+        }
+    }
+}
+
+fn update_ammo(
+    player: Query<&Ammo, (With<Player>, Changed<Ammo>)>,
+    mut text: Query<&mut Text, With<AmmoText>>,
+) {
+    for ammo in player.iter() {
+        for mut text in text.iter_mut() {
+            text.sections[0].value = ammo_text(ammo);
+        }
+    }
+}
+
+fn update_quick_slot(
+    player: Query<&EquippedItem, (With<Player>, Changed<EquippedItem>)>,
+    mut text: Query<&mut Text, With<QuickSlotText>>,
+) {
+    for equipped in player.iter() {
+        for mut text in text.iter_mut() {
+            text.sections[0].value = quick_slot_text(equipped);
+        }
+    }
+}
+
+fn update_level_name(
+    mut level_events: EventReader<LevelEvent>,
+    levels: Res<Assets<LdtkLevel>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    worlds: Query<&Handle<LdtkAsset>>,
+    mut text: Query<&mut Text, With<LevelNameText>>,
+) {
+    for event in level_events.iter() {
+        if let LevelEvent::Spawned(iid) = event {
+            let level = worlds
+                .iter()
+                .filter_map(|handle| ldtk_assets.get(handle))
+                .find_map(|ldtk_asset| ldtk_asset.level_map.get(iid))
+                .and_then(|handle| levels.get(handle));
+
+            if let Some(level) = level {
+                for mut text in text.iter_mut() {
+                    text.sections[0].value = level.level.identifier.clone();
+                }
+            }
+        }
+    }
+}
+
+fn update_objective(
+    quest_log: Res<QuestLog>,
+    quests: Res<QuestBook>,
+    mut text: Query<&mut Text, With<ObjectiveText>>,
+) {
+    if !quest_log.is_changed() {
+        return;
+    }
+
+    for mut text in text.iter_mut() {
+        text.sections[0].value = objective_text(&quest_log, &quests);
+    }
+}
+
+fn despawn_hud(
+    mut commands: Commands,
+    hud: Query<Entity, With<HudComponent>>,
+) {
+    hud.for_each(|entity| {
+        commands.entity(entity).despawn_recursive();
+    })
+}
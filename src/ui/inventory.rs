@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, item::{EquippedItem, Inventory}, player::Player};
+use super::{menu_assets::MenuAssets, style::{text_bundle, HOVERED_BUTTON, MENU_BACKGROUND, NORMAL_BUTTON}};
+
+pub struct InventoryMenuPlugin;
+
+impl Plugin for InventoryMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(
+                open_inventory
+                    .run_in_state(GameState::InGame)
+            )
+            .add_enter_system(GameState::Inventory, spawn_inventory_menu)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Inventory)
+                    .with_system(close_inventory)
+                    .with_system(crafting_button)
+                    .into()
+            )
+            .add_exit_system(GameState::Inventory, despawn_inventory_menu);
+    }
+}
+
+#[derive(Component)]
+struct InventoryMenuComponent;
+
+#[derive(Component)]
+struct CraftingButton;
+
+fn inventory_lines(inventory: &Inventory, equipped: &EquippedItem) -> Vec<String> {
+    if inventory.items.is_empty() {
+        return vec!["Your bag is empty".to_string()];
+    }
+
+    inventory
+        .items
+        .iter()
+        .map(|(item, count)| {
+            let marker = if equipped.0.as_ref() == Some(item) { " (equipped)" } else { "" };
+            format!("{} x{}{}", item.identifier(), count, marker)
+        })
+        .collect()
+}
+
+fn open_inventory(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::I) {
+        commands.insert_resource(NextState(GameState::Inventory));
+    }
+}
+
+fn close_inventory(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::I) || keys.just_pressed(KeyCode::Escape) {
+        commands.insert_resource(NextState(GameState::InGame));
+    }
+}
+
+fn spawn_inventory_menu(
+    mut commands: Commands,
+    menu_assets: Res<MenuAssets>,
+    player: Query<(&Inventory, &EquippedItem), With<Player>>,
+) {
+    let lines = player
+        .get_single()
+        .map_or_else(|_| Vec::new(), |(inventory, equipped)| inventory_lines(inventory, equipped));
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: MENU_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(InventoryMenuComponent)
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("Inventory", &menu_assets));
+            for line in lines.iter() {
+                parent.spawn_bundle(text_bundle(line, &menu_assets));
+            }
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                        margin: UiRect::all(Val::Px(8.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    image: menu_assets.button.clone().into(),
+                    color: NORMAL_BUTTON.into(),
+                    ..Default::default()
+                })
+                .insert(CraftingButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(text_bundle("Crafting", &menu_assets));
+                });
+        });
+}
+
+fn crafting_button(
+    mut commands: Commands,
+    mut buttons: Query<(&Interaction, &mut UiColor), (With<CraftingButton>, Changed<Interaction>)>,
+) {
+    buttons.for_each_mut(|(interaction, mut color)| {
+        match interaction {
+            Interaction::Clicked => commands.insert_resource(NextState(GameState::Crafting)),
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    })
+}
+
+fn despawn_inventory_menu(
+    mut commands: Commands,
+    menu: Query<Entity, With<InventoryMenuComponent>>,
+) {
+    menu.for_each(|entity| {
+        commands.entity(entity).despawn_recursive();
+    })
+}
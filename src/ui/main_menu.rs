@@ -1,10 +1,9 @@
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::{LoadingStateAppExt, LoadingState};
 use iyes_loopless::{prelude::{AppLooplessStateExt, IntoConditionalSystem}, state::NextState};
-use crate::misc::state::GameState;
-use super::menu_assets::MenuAssets;
+use crate::{misc::state::GameState, level::dungeon::DungeonRequest};
+use super::{menu_assets::MenuAssets, style::{text_bundle, NORMAL_BUTTON}};
 
-const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 pub struct MainMenuPlugin;
 
 impl Plugin for MainMenuPlugin {
@@ -20,6 +19,10 @@ impl Plugin for MainMenuPlugin {
                 start_button
                     .run_in_state(GameState::MainMenu)
             )
+            .add_system(
+                dungeon_button
+                    .run_in_state(GameState::MainMenu)
+            )
             .add_exit_system(GameState::MainMenu, despawn_menu);
     }
 }
@@ -30,6 +33,25 @@ struct MainMenuComponent;
 #[derive(Component)]
 struct StartButton;
 
+/// Starts the game on a generated floor instead of the first level
+#[derive(Component)]
+struct DungeonButton;
+
+fn button_bundle(menu_assets: &MenuAssets) -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+            margin: UiRect::all(Val::Px(8.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        image: menu_assets.button.clone().into(),
+        color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    }
+}
+
 fn spawn_menu(
     mut commands: Commands,
     menu_assets: Res<MenuAssets>,
@@ -42,7 +64,9 @@ fn spawn_menu(
         .spawn_bundle(NodeBundle {
             style: Style {
                 size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-                justify_content: JustifyContent::SpaceBetween,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
                 ..Default::default()
             },
             color: Color::NONE.into(),
@@ -51,19 +75,17 @@ fn spawn_menu(
         .insert(MainMenuComponent)
         .with_children(|parent| {
             parent
-                .spawn_bundle(ButtonBundle {
-                    style: Style {
-                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
-                        margin: UiRect::all(Val::Auto),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    image: menu_assets.button.clone().into(),
-                    color: NORMAL_BUTTON.into(),
-                    ..Default::default()
-                })
-                .insert(StartButton);
+                .spawn_bundle(button_bundle(&menu_assets))
+                .insert(StartButton)
+                .with_children(|button| {
+                    button.spawn_bundle(text_bundle("Start", &menu_assets));
+                });
+            parent
+                .spawn_bundle(button_bundle(&menu_assets))
+                .insert(DungeonButton)
+                .with_children(|button| {
+                    button.spawn_bundle(text_bundle("Dungeon", &menu_assets));
+                });
         });
 }
 
@@ -78,6 +100,19 @@ fn start_button(
     }) 
 }
 
+/// The seed comes from `CONCORDE_SEED` when it is set, so a floor can be played again
+fn dungeon_button(
+    mut commands: Commands,
+    interaction: Query<&Interaction, (Changed<Interaction>, With<DungeonButton>)>,
+) {
+    interaction.for_each(|interaction| {
+        if *interaction == Interaction::Clicked {
+            commands.insert_resource(DungeonRequest::from_env());
+            commands.insert_resource(NextState(GameState::LoadGame));
+        }
+    })
+}
+
 fn despawn_menu(
     mut commands: Commands,
     menu: Query<Entity, With<MainMenuComponent>>
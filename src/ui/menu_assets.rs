@@ -8,4 +8,6 @@ use bevy_asset_loader::prelude::AssetCollection;
 pub struct MenuAssets {
     #[asset(path = "sprites/button.png")]
     pub button: Handle<Image>,
+    #[asset(path = "fonts/DejaVuSans.ttf")]
+    pub font: Handle<Font>,
 }
\ No newline at end of file
@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{
+    misc::state::GameState,
+    player::Player,
+    enemy::settings::EnemyKind,
+    level::props::{Container, Door},
+    fog::ExploredMap,
+    map::{LevelMap, MapCanvas, CONTAINER_COLOR, DOOR_COLOR, ENEMY_COLOR, PLAYER_COLOR},
+};
+
+/// Screen pixels per level tile
+const MINIMAP_SCALE: f32 = 3.0;
+const MINIMAP_MARGIN: f32 = 12.0;
+const MINIMAP_BACKGROUND: Color = Color::rgba(0.0, 0.0, 0.0, 0.5);
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_enter_system(GameState::InGame, spawn_minimap)
+            .add_system(
+                update_minimap
+                    .run_in_state(GameState::InGame)
+            )
+            .add_exit_system(GameState::InGame, despawn_minimap);
+    }
+}
+
+#[derive(Component)]
+struct MinimapComponent;
+
+/// Map of the current level, redrawn whenever a marker moves or more of the level is explored
+#[derive(Component, Default)]
+struct Minimap {
+    level: Option<LevelMap>,
+    /// The level's tiles without any markers, painted again only when the explored tiles change
+    background: Option<MapCanvas>,
+    /// Markers on the image right now, in the order they were painted
+    markers: Vec<(IVec2, [u8; 4])>,
+}
+
+fn spawn_minimap(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(MINIMAP_MARGIN),
+                    top: Val::Px(MINIMAP_MARGIN),
+                    ..Default::default()
+                },
+                padding: UiRect::all(Val::Px(4.0)),
+                ..Default::default()
+            },
+            color: MINIMAP_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(MinimapComponent)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ImageBundle {
+                    image: UiImage(images.add(MapCanvas::new(1, 1).into_image())),
+                    style: Style {
+                        size: Size::new(Val::Px(0.0), Val::Px(0.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(Minimap::default());
+        });
+}
+
+fn update_minimap(
+    mut minimap: Query<(&mut Minimap, &UiImage, &mut Style)>,
+    mut images: ResMut<Assets<Image>>,
+    level_query: Query<(&Handle<LdtkLevel>, &GlobalTransform)>,
+    levels: Res<Assets<LdtkLevel>>,
+    explored: Res<ExploredMap>,
+    player: Query<&GlobalTransform, With<Player>>,
+    enemies: Query<&GlobalTransform, With<EnemyKind>>,
+    doors: Query<(&GlobalTransform, &Visibility), With<Door>>,
+    containers: Query<(&GlobalTransform, &Container)>,
+) {
+    let (mut minimap, image, mut style) = match minimap.get_single_mut() {
+        Ok(minimap) => minimap,
+        Err(_) => return,
+    };
+    let (level, level_transform) = match level_query
+        .iter()
+        .find_map(|(handle, transform)| levels.get(handle).map(|level| (level, transform))) {
+        Some(level) => level,
+        None => return,
+    };
+
+    // Only rebuild the tile layout when the level changes
+    if minimap.level.as_ref().map_or(true, |map| map.iid != level.level.iid) {
+        minimap.level = LevelMap::from_level(&level.level);
+        minimap.background = None;
+        if let Some(map) = &minimap.level {
+            style.size = Size::new(
+                Val::Px(map.columns as f32 * MINIMAP_SCALE),
+                Val::Px(map.rows as f32 * MINIMAP_SCALE),
+            );
+        }
+    }
+    let minimap = &mut *minimap;
+    let map = match &minimap.level {
+        Some(map) => map,
+        None => return,
+    };
+
+    let explored_changed = explored.is_changed();
+    let explored = explored.get(&map.iid);
+    let repaint = minimap.background.is_none() || explored_changed;
+    if repaint {
+        let mut background = MapCanvas::new(map.columns, map.rows);
+        map.paint(&mut background, IVec2::ZERO, explored);
+        minimap.background = Some(background);
+    }
+
+    let origin = level_transform.translation().truncate();
+    let tile_of = |transform: &GlobalTransform| map.tile_at(transform.translation().truncate() - origin);
+
+    // Only show what the player has seen, in levels with fog of war
+    let mut markers = Vec::new();
+    let mut mark = |tile: IVec2, color: [u8; 4]| {
+        if map.is_explored(tile, explored) {
+            markers.push((tile, color));
+        }
+    };
+    for (transform, container) in containers.iter() {
+        if !container.looted {
+            mark(tile_of(transform), CONTAINER_COLOR);
+        }
+    }
+    // Opened doors are hidden
+    for (transform, visibility) in doors.iter() {
+        if visibility.is_visible {
+            mark(tile_of(transform), DOOR_COLOR);
+        }
+    }
+    for transform in enemies.iter() {
+        mark(tile_of(transform), ENEMY_COLOR);
+    }
+    for transform in player.iter() {
+        markers.push((tile_of(transform), PLAYER_COLOR));
+    }
+
+    if !repaint && markers == minimap.markers {
+        return;
+    }
+    let mut canvas = match &minimap.background {
+        Some(background) => background.clone(),
+        None => return,
+    };
+    for (tile, color) in markers.iter() {
+        canvas.set(*tile, *color);
+    }
+    minimap.markers = markers;
+
+    if let Some(image) = images.get_mut(&image.0) {
+        *image = canvas.into_image();
+    }
+}
+
+fn despawn_minimap(
+    mut commands: Commands,
+    minimap: Query<Entity, With<MinimapComponent>>,
+) {
+    minimap.for_each(|entity| {
+        commands.entity(entity).despawn_recursive();
+    })
+}
@@ -1,2 +1,11 @@
+pub mod combat_menu;
+pub mod crafting;
+pub mod dialogue;
+pub mod hud;
+pub mod inventory;
 pub mod main_menu;
-mod menu_assets;
\ No newline at end of file
+pub mod minimap;
+pub mod quest_log;
+pub mod world_map;
+pub mod menu_assets;
+pub mod style;
\ No newline at end of file
@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{misc::state::GameState, quest::{QuestBook, QuestLog}};
+use super::{menu_assets::MenuAssets, style::{text_bundle, MENU_BACKGROUND}};
+
+const DONE_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
+const TITLE_COLOR: Color = Color::rgb(1.0, 0.85, 0.4);
+
+pub struct QuestLogPlugin;
+
+impl Plugin for QuestLogPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(
+                open_quest_log
+                    .run_in_state(GameState::InGame)
+            )
+            .add_enter_system(GameState::QuestLog, spawn_quest_log)
+            .add_system(
+                close_quest_log
+                    .run_in_state(GameState::QuestLog)
+            )
+            .add_exit_system(GameState::QuestLog, despawn_quest_log);
+    }
+}
+
+#[derive(Component)]
+struct QuestLogComponent;
+
+fn open_quest_log(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::J) {
+        commands.insert_resource(NextState(GameState::QuestLog));
+    }
+}
+
+fn close_quest_log(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::J) || keys.just_pressed(KeyCode::Escape) {
+        commands.insert_resource(NextState(GameState::InGame));
+    }
+}
+
+/// Lists started and finished quests, quests still waiting on another one stay hidden
+fn spawn_quest_log(
+    mut commands: Commands,
+    menu_assets: Res<MenuAssets>,
+    quest_log: Res<QuestLog>,
+    quests: Res<QuestBook>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: MENU_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(QuestLogComponent)
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("Quests", &menu_assets));
+
+            for quest in quests.iter() {
+                let complete = quest_log.is_complete(quest);
+                if !complete && !quest_log.is_active(quest, &quests) {
+                    continue;
+                }
+
+                let mut title = text_bundle(&quest.title, &menu_assets);
+                title.text.sections[0].style.color = if complete { DONE_COLOR } else { TITLE_COLOR };
+                parent.spawn_bundle(title);
+
+                for (index, objective) in quest.objectives.iter().enumerate() {
+                    let progress = quest_log.objective_progress(quest, index).min(objective.required());
+                    let done = progress >= objective.required();
+                    let line = format!(
+                        "[{}] {} ({}/{})",
+                        if done { "x" } else { " " },
+                        objective.description,
+                        progress,
+                        objective.required(),
+                    );
+
+                    let mut text = text_bundle(&line, &menu_assets);
+                    if done {
+                        text.text.sections[0].style.color = DONE_COLOR;
+                    }
+                    parent.spawn_bundle(text);
+                }
+            }
+        });
+}
+
+fn despawn_quest_log(
+    mut commands: Commands,
+    menu: Query<Entity, With<QuestLogComponent>>,
+) {
+    menu.for_each(|entity| {
+        commands.entity(entity).despawn_recursive();
+    })
+}
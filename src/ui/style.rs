@@ -0,0 +1,33 @@
+use bevy::prelude::*;
+use super::menu_assets::MenuAssets;
+
+pub const MENU_BACKGROUND: Color = Color::rgba(0.05, 0.05, 0.08, 0.9);
+pub const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+pub const HOVERED_BUTTON: Color = Color::rgb(0.3, 0.3, 0.3);
+pub const FONT_SIZE: f32 = 24.0;
+const MARGIN: f32 = 8.0;
+
+/// White menu text with some room around it
+pub fn text_bundle(text: &str, menu_assets: &MenuAssets) -> TextBundle {
+    sized_text_bundle(text, FONT_SIZE, Color::WHITE, MARGIN, menu_assets)
+}
+
+/// Text in the menu font, for UI that is laid out tighter than the menus
+pub fn sized_text_bundle(text: impl Into<String>, font_size: f32, color: Color, margin: f32, menu_assets: &MenuAssets) -> TextBundle {
+    TextBundle {
+        text: Text::with_section(
+            text,
+            TextStyle {
+                font: menu_assets.font.clone(),
+                font_size,
+                color,
+            },
+            TextAlignment::default(),
+        ),
+        style: Style {
+            margin: UiRect::all(Val::Px(margin)),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
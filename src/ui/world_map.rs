@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::{
+    misc::state::GameState,
+    player::Player,
+    fog::ExploredMap,
+    map::{connections, LevelMap, MapCanvas, CONNECTION_COLOR, PLAYER_COLOR},
+};
+use super::{menu_assets::MenuAssets, style::text_bundle};
+
+/// A little darker than the menus, so the map stands out from the level behind it
+const MAP_BACKGROUND: Color = Color::rgba(0.05, 0.05, 0.08, 0.95);
+const LABEL_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
+const CURRENT_LEVEL_COLOR: Color = Color::rgb(1.0, 0.85, 0.4);
+const LABEL_FONT_SIZE: f32 = 14.0;
+/// Share of the window the map may cover
+const MAP_WIDTH: f32 = 0.9;
+const MAP_HEIGHT: f32 = 0.75;
+
+pub struct WorldMapPlugin;
+
+impl Plugin for WorldMapPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(
+                open_world_map
+                    .run_in_state(GameState::InGame)
+            )
+            .add_enter_system(GameState::WorldMap, spawn_world_map)
+            .add_system(
+                close_world_map
+                    .run_in_state(GameState::WorldMap)
+            )
+            .add_exit_system(GameState::WorldMap, despawn_world_map);
+    }
+}
+
+#[derive(Component)]
+struct WorldMapComponent;
+
+fn open_world_map(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::M) {
+        commands.insert_resource(NextState(GameState::WorldMap));
+    }
+}
+
+fn close_world_map(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::M) || keys.just_pressed(KeyCode::Escape) {
+        commands.insert_resource(NextState(GameState::InGame));
+    }
+}
+
+fn label_bundle(text: &str, color: Color, left: f32, top: f32, menu_assets: &MenuAssets) -> TextBundle {
+    TextBundle {
+        text: Text::with_section(
+            text,
+            TextStyle {
+                font: menu_assets.font.clone(),
+                font_size: LABEL_FONT_SIZE,
+                color,
+            },
+            TextAlignment::default(),
+        ),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(left),
+                top: Val::Px(top),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Draws every level at its place in the LDtk world, with lines between linked entities
+/// such as elevators, and the player on top
+fn spawn_world_map(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    menu_assets: Res<MenuAssets>,
+    windows: Res<Windows>,
+    worlds: Query<&Handle<LdtkAsset>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    level_query: Query<(&Handle<LdtkLevel>, &GlobalTransform)>,
+    levels: Res<Assets<LdtkLevel>>,
+    explored: Res<ExploredMap>,
+    player: Query<&GlobalTransform, With<Player>>,
+) {
+    let project = match worlds.iter().find_map(|handle| ldtk_assets.get(handle)) {
+        Some(ldtk_asset) => &ldtk_asset.project,
+        None => return,
+    };
+    let current = level_query
+        .iter()
+        .find_map(|(handle, transform)| levels.get(handle).map(|level| (level.level.iid.clone(), transform)));
+    let current_iid = current.as_ref().map(|(iid, _)| iid.clone());
+
+    // The level the player is in is drawn last, so it stays on top of any overlapping one
+    let mut maps: Vec<LevelMap> = project.levels.iter().filter_map(LevelMap::from_level).collect();
+    maps.sort_by_key(|map| Some(&map.iid) == current_iid.as_ref());
+
+    let top_left = maps.iter().map(|map| map.world_origin).reduce(IVec2::min).unwrap_or_default();
+    let bottom_right = maps
+        .iter()
+        .map(|map| map.world_origin + IVec2::new(map.columns, map.rows))
+        .reduce(IVec2::max)
+        .unwrap_or_default();
+    let size = bottom_right - top_left;
+
+    let mut canvas = MapCanvas::new(size.x, size.y);
+    for map in maps.iter() {
+        map.paint(&mut canvas, map.world_origin - top_left, explored.get(&map.iid));
+    }
+    for (from, to) in connections(&project.levels) {
+        canvas.line(from - top_left, to - top_left, CONNECTION_COLOR);
+    }
+    if let (Some((iid, level_transform)), Ok(player)) = (&current, player.get_single()) {
+        if let Some(map) = maps.iter().find(|map| map.iid == *iid) {
+            let position = player.translation().truncate() - level_transform.translation().truncate();
+            canvas.set(map.world_origin + map.tile_at(position) - top_left, PLAYER_COLOR);
+        }
+    }
+
+    let window = windows.primary();
+    let scale = (window.width() * MAP_WIDTH / canvas.width as f32)
+        .min(window.height() * MAP_HEIGHT / canvas.height as f32)
+        .floor()
+        .max(1.0);
+    let image = images.add(canvas.into_image());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: MAP_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(WorldMapComponent)
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("World map", &menu_assets));
+
+            parent
+                .spawn_bundle(ImageBundle {
+                    image: UiImage(image),
+                    style: Style {
+                        size: Size::new(Val::Px(size.x as f32 * scale), Val::Px(size.y as f32 * scale)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|map_node| {
+                    for map in maps.iter() {
+                        let corner = (map.world_origin - top_left).as_vec2() * scale;
+                        let color = if Some(&map.iid) == current_iid.as_ref() { CURRENT_LEVEL_COLOR } else { LABEL_COLOR };
+                        map_node.spawn_bundle(label_bundle(&map.identifier.replace('_', " "), color, corner.x, corner.y, &menu_assets));
+                    }
+                });
+        });
+}
+
+fn despawn_world_map(
+    mut commands: Commands,
+    menu: Query<Entity, With<WorldMapComponent>>,
+) {
+    menu.for_each(|entity| {
+        commands.entity(entity).despawn_recursive();
+    })
+}
@@ -0,0 +1,412 @@
+use std::collections::{HashMap, HashSet};
+use bevy_ecs_ldtk::{EntityInstance, prelude::FieldValue};
+use serde_json::Value;
+use crate::{
+    item::ItemRegistry,
+    crafting::ldtk_items,
+    level::{COLLISIONS_LAYER, LDTK_PROJECT, terrain::Terrain},
+};
+
+/// A field an entity's bundle reads, with the LDtk type it expects
+#[derive(Copy, Clone, Debug)]
+pub struct FieldSchema {
+    pub identifier: &'static str,
+    pub field_type: &'static str,
+    /// Whether every instance has to set it
+    pub required: bool,
+}
+
+const fn field(identifier: &'static str, field_type: &'static str) -> FieldSchema {
+    FieldSchema { identifier, field_type, required: false }
+}
+
+const fn required(identifier: &'static str, field_type: &'static str) -> FieldSchema {
+    FieldSchema { identifier, field_type, required: true }
+}
+
+/// An entity registered with `register_ldtk_entity`, and the fields its bundle reads
+#[derive(Copy, Clone, Debug)]
+pub struct EntitySchema {
+    pub identifier: &'static str,
+    pub fields: &'static [FieldSchema],
+}
+
+pub const ENTITY_SCHEMAS: [EntitySchema; 8] = [
+    EntitySchema { identifier: "Player", fields: &[field("life", "Int"), field("ammo", "Int")] },
+    EntitySchema {
+        identifier: "Knight",
+        fields: &[
+            required("Patrol", "Array<Point>"),
+            field("Hostile", "Bool"),
+            field("Dialogue", "String"),
+        ],
+    },
+    EntitySchema { identifier: "Door", fields: &[field("lockedWith", "LocalEnum.Item")] },
+    EntitySchema {
+        identifier: "Container",
+        fields: &[
+            field("label", "String"),
+            field("description", "Multilines"),
+            field("loot", "Array<LocalEnum.Item>"),
+            field("Dialogue", "String"),
+        ],
+    },
+    EntitySchema { identifier: "AmmoPickup", fields: &[field("amount", "Int")] },
+    EntitySchema { identifier: "ElevatorLink", fields: &[required("destination", "EntityRef")] },
+    EntitySchema {
+        identifier: "TriggerZone",
+        fields: &[
+            field("event", "String"),
+            field("action", "LocalEnum.TriggerAction"),
+            field("mode", "LocalEnum.TriggerMode"),
+            field("text", "Multilines"),
+            field("music", "FilePath"),
+            field("targets", "Array<EntityRef>"),
+            field("spawns", "Array<Point>"),
+        ],
+    },
+    EntitySchema { identifier: "Torch", fields: &[field("radius", "Float")] },
+];
+
+/// Field types holding values of the LDtk `Item` enum
+const ITEM_FIELD_TYPES: [&str; 2] = ["LocalEnum.Item", "Array<LocalEnum.Item>"];
+
+/// The LDtk project the game loads, relative to the repository root
+pub fn default_project_path() -> String {
+    format!("assets/{}", LDTK_PROJECT)
+}
+
+/// Items defined in `settings/items.json`
+pub fn known_items() -> Result<HashSet<String>, String> {
+    let registry = ItemRegistry::read()?;
+    Ok(registry.keys().map(|item| item.identifier().to_string()).collect())
+}
+
+fn array(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+fn text(value: &Value) -> &str {
+    value.as_str().unwrap_or_default()
+}
+
+/// Checks an LDtk project against what the game registers and the items it knows about
+pub fn validate_project(project: &Value, known_items: &HashSet<String>) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    validate_item_enum(project, known_items, &mut errors);
+    validate_entity_definitions(project, &mut errors);
+
+    // Identifier and level iid of every entity, to follow references across levels
+    let entities: HashMap<&str, (&str, &str)> = array(&project["levels"])
+        .flat_map(|level| {
+            array(&level["layerInstances"])
+                .flat_map(|layer| array(&layer["entityInstances"]))
+                .map(move |entity| (text(&entity["iid"]), (text(&entity["__identifier"]), text(&level["iid"]))))
+        })
+        .collect();
+
+    for level in array(&project["levels"]) {
+        let level_name = text(&level["identifier"]);
+        let collisions = array(&level["layerInstances"]).find(|layer| layer["__identifier"] == COLLISIONS_LAYER);
+        if collisions.is_none() {
+            errors.push(format!("{}: has no {} layer", level_name, COLLISIONS_LAYER));
+        }
+
+        for entity in array(&level["layerInstances"]).flat_map(|layer| array(&layer["entityInstances"])) {
+            let identifier = text(&entity["__identifier"]);
+            let context = format!("{}: {} {}", level_name, identifier, text(&entity["iid"]));
+            let schema = match ENTITY_SCHEMAS.iter().find(|schema| schema.identifier == identifier) {
+                Some(schema) => schema,
+                None => {
+                    errors.push(format!("{}: isn't registered by the game", context));
+                    continue;
+                },
+            };
+
+            let fields: HashMap<&str, &Value> = array(&entity["fieldInstances"])
+                .map(|field| (text(&field["__identifier"]), field))
+                .collect();
+            for field in schema.fields.iter().filter(|field| field.required) {
+                if fields.get(field.identifier).map_or(true, |instance| instance["__value"].is_null()) {
+                    errors.push(format!("{}: {} isn't set", context, field.identifier));
+                }
+            }
+
+            for field in array(&entity["fieldInstances"]) {
+                if !ITEM_FIELD_TYPES.contains(&text(&field["__type"])) {
+                    continue;
+                }
+                let value = &field["__value"];
+                let values: Vec<&str> = match value.as_array() {
+                    Some(values) => values.iter().filter_map(Value::as_str).collect(),
+                    None => value.as_str().into_iter().collect(),
+                };
+                for item in values.into_iter().filter(|item| !known_items.contains(*item)) {
+                    errors.push(format!("{}: {} names unknown item {}", context, text(&field["__identifier"]), item));
+                }
+            }
+
+            if let (Some(patrol), Some(collisions)) = (fields.get("Patrol"), collisions) {
+                for point in array(&patrol["__value"]) {
+                    let tile = (point["cx"].as_i64().unwrap_or(-1), point["cy"].as_i64().unwrap_or(-1));
+                    if let Err(error) = walkable(collisions, tile) {
+                        errors.push(format!("{}: patrol point {} {} {}", context, tile.0, tile.1, error));
+                    }
+                }
+            }
+
+            if identifier == "ElevatorLink" {
+                let destination = fields.get("destination").map(|field| &field["__value"]);
+                if let Some(reference) = destination.filter(|reference| !reference.is_null()) {
+                    match entities.get(text(&reference["entityIid"])) {
+                        None => errors.push(format!("{}: destination doesn't exist", context)),
+                        Some((_, level_iid)) if *level_iid != text(&reference["levelIid"]) => {
+                            errors.push(format!("{}: destination is in another level than it says", context));
+                        },
+                        Some((target, _)) if *target != "ElevatorLink" => {
+                            errors.push(format!("{}: destination is a {}, not an ElevatorLink", context, target));
+                        },
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Problems with a spawned entity that the game works around, the same ones `validate_project` reports
+pub fn instance_problems(instance: &EntityInstance) -> Vec<String> {
+    let schema = match ENTITY_SCHEMAS.iter().find(|schema| schema.identifier == instance.identifier) {
+        Some(schema) => schema,
+        None => return vec!["isn't registered by the game".to_string()],
+    };
+
+    schema
+        .fields
+        .iter()
+        .filter(|field| field.required)
+        .filter(|field| {
+            instance
+                .field_instances
+                .iter()
+                .find(|instance| instance.identifier == field.identifier)
+                .map_or(true, |instance| is_unset(&instance.value))
+        })
+        .map(|field| format!("{} isn't set", field.identifier))
+        .collect()
+}
+
+fn is_unset(value: &FieldValue) -> bool {
+    matches!(
+        value,
+        FieldValue::Int(None)
+            | FieldValue::Float(None)
+            | FieldValue::String(None)
+            | FieldValue::FilePath(None)
+            | FieldValue::Enum(None)
+            | FieldValue::EntityRef(None)
+            | FieldValue::Point(None)
+    )
+}
+
+/// Every value of the LDtk `Item` enum needs an entry in the item settings, and the other way round
+fn validate_item_enum(project: &Value, known_items: &HashSet<String>, errors: &mut Vec<String>) {
+    let ldtk = ldtk_items(project);
+    let mut missing: Vec<&String> = ldtk.difference(known_items).collect();
+    let mut unused: Vec<&String> = known_items.difference(&ldtk).collect();
+    missing.sort();
+    unused.sort();
+
+    errors.extend(missing.into_iter().map(|item| format!("Item enum: {} has no entry in the item settings", item)));
+    errors.extend(unused.into_iter().map(|item| format!("Item enum: {} is missing, but the item settings define it", item)));
+}
+
+/// The game's entities need an LDtk definition with every field they read, of the right type
+fn validate_entity_definitions(project: &Value, errors: &mut Vec<String>) {
+    for schema in ENTITY_SCHEMAS.iter() {
+        let definition = array(&project["defs"]["entities"]).find(|definition| definition["identifier"] == schema.identifier);
+        let definition = match definition {
+            Some(definition) => definition,
+            None => {
+                errors.push(format!("{}: the game registers it, but there is no such entity", schema.identifier));
+                continue;
+            },
+        };
+
+        for field in schema.fields.iter() {
+            let field_type = array(&definition["fieldDefs"])
+                .find(|field_def| field_def["identifier"] == field.identifier)
+                .map(|field_def| text(&field_def["__type"]));
+            match field_type {
+                None => errors.push(format!("{}: has no {} field", schema.identifier, field.identifier)),
+                Some(field_type) if field_type != field.field_type => errors.push(format!(
+                    "{}: {} is {}, but the game reads {}",
+                    schema.identifier, field.identifier, field_type, field.field_type,
+                )),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Whether a tile of an IntGrid layer can be walked on, in LDtk grid coordinates
+fn walkable(collisions: &Value, (x, y): (i64, i64)) -> Result<(), String> {
+    let (columns, rows) = (collisions["__cWid"].as_i64().unwrap_or(0), collisions["__cHei"].as_i64().unwrap_or(0));
+    if x < 0 || y < 0 || x >= columns || y >= rows {
+        return Err("is outside the level".to_string());
+    }
+
+    let value = collisions["intGridCsv"][(y * columns + x) as usize].as_i64().unwrap_or(0);
+    match Terrain::from_value(value as i32) {
+        Some(terrain) if terrain.blocks_walking() => Err(format!("is on {:?}", terrain)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_ldtk::ldtk::LdtkJson;
+    use serde_json::json;
+    use super::*;
+
+    fn project(entities: Value) -> Value {
+        let entity_defs: Vec<Value> = ENTITY_SCHEMAS
+            .iter()
+            .map(|schema| json!({
+                "identifier": schema.identifier,
+                "fieldDefs": schema
+                    .fields
+                    .iter()
+                    .map(|field| json!({ "identifier": field.identifier, "__type": field.field_type }))
+                    .collect::<Vec<_>>(),
+            }))
+            .collect();
+
+        json!({
+            "defs": {
+                "entities": entity_defs,
+                "enums": [{ "identifier": "Item", "values": [{ "id": "Key" }] }],
+            },
+            "levels": [{
+                "identifier": "Cellar",
+                "iid": "cellar",
+                "layerInstances": [
+                    { "__identifier": "Entities", "entityInstances": entities },
+                    { "__identifier": COLLISIONS_LAYER, "__cWid": 3, "__cHei": 2, "intGridCsv": [0, 1, 0, 0, 3, 0] },
+                ],
+            }],
+        })
+    }
+
+    fn items() -> HashSet<String> {
+        HashSet::from(["Key".to_string()])
+    }
+
+    #[test]
+    fn accepts_entities_the_game_understands() {
+        let project = project(json!([
+            { "__identifier": "Knight", "iid": "knight", "fieldInstances": [
+                { "__identifier": "Patrol", "__type": "Array<Point>", "__value": [{ "cx": 0, "cy": 1 }, { "cx": 2, "cy": 0 }] },
+            ]},
+            { "__identifier": "Door", "iid": "door", "fieldInstances": [
+                { "__identifier": "lockedWith", "__type": "LocalEnum.Item", "__value": "Key" },
+            ]},
+        ]));
+
+        assert_eq!(validate_project(&project, &items()), Ok(()));
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_entity() {
+        let project = project(json!([
+            { "__identifier": "Skeleton", "iid": "skeleton", "fieldInstances": [] },
+            { "__identifier": "Knight", "iid": "knight", "fieldInstances": [
+                { "__identifier": "Patrol", "__type": "Array<Point>", "__value": [{ "cx": 1, "cy": 0 }, { "cx": 1, "cy": 1 }, { "cx": 5, "cy": 0 }] },
+            ]},
+            { "__identifier": "Knight", "iid": "lazy", "fieldInstances": [] },
+            { "__identifier": "Container", "iid": "chest", "fieldInstances": [
+                { "__identifier": "loot", "__type": "Array<LocalEnum.Item>", "__value": ["Key", "Sword"] },
+            ]},
+            { "__identifier": "ElevatorLink", "iid": "elevator", "fieldInstances": [
+                { "__identifier": "destination", "__type": "EntityRef", "__value": { "entityIid": "chest", "levelIid": "cellar" } },
+            ]},
+        ]));
+
+        let errors = validate_project(&project, &items()).unwrap_err();
+        assert_eq!(errors, vec![
+            "Cellar: Skeleton skeleton: isn't registered by the game",
+            "Cellar: Knight knight: patrol point 1 0 is on Wall",
+            "Cellar: Knight knight: patrol point 1 1 is on Water",
+            "Cellar: Knight knight: patrol point 5 0 is outside the level",
+            "Cellar: Knight lazy: Patrol isn't set",
+            "Cellar: Container chest: loot names unknown item Sword",
+            "Cellar: ElevatorLink elevator: destination is a Container, not an ElevatorLink",
+        ]);
+    }
+
+    /// Keeps `ENTITY_SCHEMAS` and the shipped project in step with what `run` registers
+    #[test]
+    fn shipped_project_is_valid() {
+        let path = default_project_path();
+        let project: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let known_items = known_items().unwrap();
+
+        if let Err(errors) = validate_project(&project, &known_items) {
+            panic!("{} has problems:\n{}", path, errors.join("\n"));
+        }
+    }
+
+    /// The first `identifier` entity of the shipped project, which sets every field it needs
+    fn shipped_instance(identifier: &str) -> EntityInstance {
+        let project: LdtkJson = serde_json::from_str(&std::fs::read_to_string(default_project_path()).unwrap()).unwrap();
+        project
+            .levels
+            .iter()
+            .flat_map(|level| level.layer_instances.iter().flatten())
+            .flat_map(|layer| layer.entity_instances.iter())
+            .find(|instance| instance.identifier == identifier)
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn complete_instances_have_no_problems() {
+        for identifier in ["Knight", "ElevatorLink", "Door"] {
+            assert!(instance_problems(&shipped_instance(identifier)).is_empty(), "{} has problems", identifier);
+        }
+    }
+
+    #[test]
+    fn instance_without_patrol_is_reported() {
+        let mut knight = shipped_instance("Knight");
+        knight.field_instances.retain(|field| field.identifier != "Patrol");
+
+        assert_eq!(instance_problems(&knight), vec!["Patrol isn't set"]);
+    }
+
+    #[test]
+    fn null_destination_is_reported() {
+        let mut elevator = shipped_instance("ElevatorLink");
+        for field in elevator.field_instances.iter_mut().filter(|field| field.identifier == "destination") {
+            field.value = FieldValue::EntityRef(None);
+        }
+
+        assert_eq!(instance_problems(&elevator), vec!["destination isn't set"]);
+        assert!(is_unset(&FieldValue::EntityRef(None)));
+        assert!(!is_unset(&FieldValue::Bool(false)));
+    }
+
+    #[test]
+    fn unregistered_identifier_is_reported() {
+        let mut skeleton = shipped_instance("Knight");
+        skeleton.identifier = "Skeleton".to_string();
+
+        assert_eq!(instance_problems(&skeleton), vec!["isn't registered by the game"]);
+    }
+}